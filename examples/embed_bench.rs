@@ -1,4 +1,5 @@
 use anyhow::Result;
+use memex::config::{Paths, UserConfig};
 use memex::embed::{EmbedderHandle, ModelChoice};
 use std::time::Instant;
 
@@ -19,6 +20,7 @@ fn main() -> Result<()> {
     println!("==================================================");
     println!("CPU cores: {}", std::thread::available_parallelism()?.get());
 
+    let cache = UserConfig::load(&Paths::new(None)?)?.model_cache()?;
     let texts = generate_texts(500);
     let text_refs: Vec<&str> = texts.iter().map(|s| s.as_str()).collect();
 
@@ -26,14 +28,14 @@ fn main() -> Result<()> {
     println!("\n--- Test 1: MiniLM (default settings) ---");
     {
         let start = Instant::now();
-        let mut embedder = EmbedderHandle::with_model(ModelChoice::MiniLM)?;
+        let mut embedder = EmbedderHandle::with_model(&ModelChoice::MiniLM, &cache)?;
         println!("  Model init: {}ms", start.elapsed().as_millis());
 
         // Warmup
-        let _ = embedder.embed_texts(&["warmup"])?;
+        let _ = embedder.embed_documents(&["warmup"])?;
 
         let start = Instant::now();
-        let results = embedder.embed_texts(&text_refs)?;
+        let results = embedder.embed_documents(&text_refs)?;
         let elapsed = start.elapsed();
         println!(
             "  500 texts: {}ms ({:.0} texts/sec) - {} embeddings",
@@ -51,7 +53,7 @@ fn main() -> Result<()> {
         }
 
         let start = Instant::now();
-        let mut embedder = match EmbedderHandle::with_model(ModelChoice::MiniLM) {
+        let mut embedder = match EmbedderHandle::with_model(&ModelChoice::MiniLM, &cache) {
             Ok(e) => e,
             Err(e) => {
                 println!("  Failed to init: {e}");
@@ -60,10 +62,10 @@ fn main() -> Result<()> {
         };
         println!("  Model init: {}ms", start.elapsed().as_millis());
 
-        let _ = embedder.embed_texts(&["warmup"])?;
+        let _ = embedder.embed_documents(&["warmup"])?;
 
         let start = Instant::now();
-        let results = embedder.embed_texts(&text_refs)?;
+        let results = embedder.embed_documents(&text_refs)?;
         let elapsed = start.elapsed();
         println!(
             "  500 texts: {}ms ({:.0} texts/sec)",
@@ -80,13 +82,13 @@ fn main() -> Result<()> {
     }
     {
         let start = Instant::now();
-        let mut embedder = EmbedderHandle::with_model(ModelChoice::Gemma)?;
+        let mut embedder = EmbedderHandle::with_model(&ModelChoice::Gemma, &cache)?;
         println!("  Model init: {}ms", start.elapsed().as_millis());
 
-        let _ = embedder.embed_texts(&["warmup"])?;
+        let _ = embedder.embed_documents(&["warmup"])?;
 
         let start = Instant::now();
-        let results = embedder.embed_texts(&text_refs)?;
+        let results = embedder.embed_documents(&text_refs)?;
         let elapsed = start.elapsed();
         println!(
             "  500 texts: {}ms ({:.0} texts/sec)",
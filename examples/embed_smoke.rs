@@ -1,15 +1,13 @@
 use anyhow::Result;
-use memex::embed::{EmbedderHandle, ModelChoice};
+use memex::config::{Paths, UserConfig};
+use memex::embed::EmbedderHandle;
 
 fn main() -> Result<()> {
     let input = vec!["hello world", "small embedding smoke test"];
-    let choice = std::env::var("MEMEX_MODEL")
-        .ok()
-        .map(|s| ModelChoice::parse(&s))
-        .transpose()?
-        .unwrap_or_default();
-    let mut embedder = EmbedderHandle::with_model(choice)?;
-    let embeddings = embedder.embed_texts(&input)?;
+    let config = UserConfig::load(&Paths::new(None)?)?;
+    let choice = config.resolve_model(None)?;
+    let mut embedder = EmbedderHandle::with_model(&choice, &config.model_cache()?)?;
+    let embeddings = embedder.embed_documents(&input)?;
     if embeddings.is_empty() {
         anyhow::bail!("no embeddings returned");
     }
@@ -0,0 +1,446 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+const HOUR_MS: u64 = 3_600_000;
+
+/// Length of one activity bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Period {
+    Day,
+    /// ISO weeks, starting on Monday.
+    Week,
+}
+
+/// The fields of one record an activity report needs.
+#[derive(Debug, Clone)]
+pub struct ActivityEvent {
+    pub ts: u64,
+    pub project: String,
+    pub session_id: String,
+    pub role: String,
+    pub source: String,
+}
+
+/// Activity counts for a group of records.
+///
+/// A session counts where its first record in the range falls. Active hours
+/// are the distinct clock hours with at least one record.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct Counts {
+    pub records: usize,
+    pub sessions: usize,
+    pub prompts: usize,
+    pub tool_calls: usize,
+    pub active_hours: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PeriodActivity {
+    /// First day of the period, `YYYY-MM-DD` (UTC).
+    pub start: String,
+    #[serde(flatten)]
+    pub counts: Counts,
+    pub projects: BTreeMap<String, Counts>,
+    pub sources: BTreeMap<String, Counts>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GroupActivity {
+    pub name: String,
+    #[serde(flatten)]
+    pub counts: Counts,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionSpan {
+    pub session_id: String,
+    pub project: String,
+    pub source: String,
+    pub start: String,
+    pub end: String,
+    pub minutes: u64,
+    pub prompts: usize,
+    pub tool_calls: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ActivityReport {
+    pub period: Period,
+    pub totals: Counts,
+    /// Every period from the first record to the last, including idle ones.
+    pub periods: Vec<PeriodActivity>,
+    /// Projects by active hours, most active first.
+    pub projects: Vec<GroupActivity>,
+    pub sources: Vec<GroupActivity>,
+    pub longest_sessions: Vec<SessionSpan>,
+}
+
+#[derive(Default)]
+struct Tally {
+    counts: Counts,
+    hours: HashSet<u64>,
+}
+
+impl Tally {
+    fn add(&mut self, event: &ActivityEvent, starts_session: bool) {
+        self.counts.records += 1;
+        self.counts.sessions += starts_session as usize;
+        self.counts.prompts += (event.role == "user") as usize;
+        self.counts.tool_calls += (event.role == "tool_use") as usize;
+        self.hours.insert(event.ts / HOUR_MS);
+    }
+
+    fn finish(self) -> Counts {
+        Counts {
+            active_hours: self.hours.len(),
+            ..self.counts
+        }
+    }
+}
+
+#[derive(Default)]
+struct PeriodTally {
+    all: Tally,
+    projects: BTreeMap<String, Tally>,
+    sources: BTreeMap<String, Tally>,
+}
+
+/// Aggregate `events` into `period` buckets, keeping the `top` longest
+/// sessions.
+pub fn report(mut events: Vec<ActivityEvent>, period: Period, top: usize) -> ActivityReport {
+    events.sort_by_key(|event| event.ts);
+
+    let mut totals = Tally::default();
+    let mut periods: BTreeMap<NaiveDate, PeriodTally> = BTreeMap::new();
+    let mut projects: HashMap<String, Tally> = HashMap::new();
+    let mut sources: HashMap<String, Tally> = HashMap::new();
+    let mut sessions: HashMap<String, SessionSpan> = HashMap::new();
+    let mut bounds: HashMap<String, (u64, u64)> = HashMap::new();
+
+    for event in &events {
+        let starts_session = !bounds.contains_key(&event.session_id);
+        let span = sessions
+            .entry(event.session_id.clone())
+            .or_insert_with(|| SessionSpan {
+                session_id: event.session_id.clone(),
+                project: event.project.clone(),
+                source: event.source.clone(),
+                start: String::new(),
+                end: String::new(),
+                minutes: 0,
+                prompts: 0,
+                tool_calls: 0,
+            });
+        span.prompts += (event.role == "user") as usize;
+        span.tool_calls += (event.role == "tool_use") as usize;
+        bounds
+            .entry(event.session_id.clone())
+            .or_insert((event.ts, event.ts))
+            .1 = event.ts;
+
+        totals.add(event, starts_session);
+        let bucket = periods.entry(period_start(event.ts, period)).or_default();
+        bucket.all.add(event, starts_session);
+        bucket
+            .projects
+            .entry(event.project.clone())
+            .or_default()
+            .add(event, starts_session);
+        bucket
+            .sources
+            .entry(event.source.clone())
+            .or_default()
+            .add(event, starts_session);
+        projects
+            .entry(event.project.clone())
+            .or_default()
+            .add(event, starts_session);
+        sources
+            .entry(event.source.clone())
+            .or_default()
+            .add(event, starts_session);
+    }
+
+    // Fill idle periods so the timeline has no gaps.
+    if let (Some(first), Some(last)) = (
+        periods.keys().next().copied(),
+        periods.keys().next_back().copied(),
+    ) {
+        let step = match period {
+            Period::Day => Duration::days(1),
+            Period::Week => Duration::weeks(1),
+        };
+        let mut day = first;
+        while day < last {
+            periods.entry(day).or_default();
+            day += step;
+        }
+    }
+    let periods = periods
+        .into_iter()
+        .map(|(start, tally)| PeriodActivity {
+            start: start.format("%Y-%m-%d").to_string(),
+            counts: tally.all.finish(),
+            projects: finish_all(tally.projects).collect(),
+            sources: finish_all(tally.sources).collect(),
+        })
+        .collect();
+
+    let mut longest: Vec<SessionSpan> = sessions
+        .into_values()
+        .map(|mut span| {
+            let (first, last) = bounds[&span.session_id];
+            span.start = format_ts(first);
+            span.end = format_ts(last);
+            span.minutes = (last - first) / 60_000;
+            span
+        })
+        .collect();
+    longest.sort_by(|a, b| {
+        b.minutes
+            .cmp(&a.minutes)
+            .then_with(|| a.session_id.cmp(&b.session_id))
+    });
+    longest.truncate(top);
+
+    ActivityReport {
+        period,
+        totals: totals.finish(),
+        periods,
+        projects: ranked(projects),
+        sources: ranked(sources),
+        longest_sessions: longest,
+    }
+}
+
+fn finish_all<I>(tallies: I) -> impl Iterator<Item = (String, Counts)>
+where
+    I: IntoIterator<Item = (String, Tally)>,
+{
+    tallies
+        .into_iter()
+        .map(|(name, tally)| (name, tally.finish()))
+}
+
+fn ranked(tallies: HashMap<String, Tally>) -> Vec<GroupActivity> {
+    let mut groups: Vec<GroupActivity> = finish_all(tallies)
+        .map(|(name, counts)| GroupActivity { name, counts })
+        .collect();
+    groups.sort_by(|a, b| {
+        b.counts
+            .active_hours
+            .cmp(&a.counts.active_hours)
+            .then_with(|| b.counts.records.cmp(&a.counts.records))
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    groups
+}
+
+fn period_start(ts: u64, period: Period) -> NaiveDate {
+    let day = DateTime::<Utc>::from_timestamp_millis(ts as i64)
+        .unwrap_or_default()
+        .date_naive();
+    match period {
+        Period::Day => day,
+        Period::Week => day - Duration::days(day.weekday().num_days_from_monday() as i64),
+    }
+}
+
+fn format_ts(ts: u64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(ts as i64)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+/// One block per value, scaled to the largest; zero is blank.
+pub fn sparkline(values: &[usize]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().copied().max().unwrap_or(0);
+    values
+        .iter()
+        .map(|&value| {
+            if value == 0 {
+                ' '
+            } else {
+                BLOCKS[(value * BLOCKS.len()).div_ceil(max) - 1]
+            }
+        })
+        .collect()
+}
+
+/// One shade per value, scaled to `max`; zero is a dot.
+fn heat_row(values: &[usize], max: usize) -> String {
+    const SHADES: [char; 4] = ['░', '▒', '▓', '█'];
+    values
+        .iter()
+        .map(|&value| {
+            if value == 0 || max == 0 {
+                '·'
+            } else {
+                SHADES[(value * SHADES.len()).div_ceil(max) - 1]
+            }
+        })
+        .collect()
+}
+
+/// Projects shown in the heatmap.
+const HEATMAP_ROWS: usize = 8;
+
+impl ActivityReport {
+    /// The report as sparklines per metric, a project heatmap of active hours,
+    /// per-source totals and the longest sessions.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let (Some(first), Some(last)) = (self.periods.first(), self.periods.last()) else {
+            return "no activity\n".to_string();
+        };
+        let by = match self.period {
+            Period::Day => "day",
+            Period::Week => "week",
+        };
+        out.push_str(&format!(
+            "activity {} .. {} (by {by})\n\n",
+            first.start, last.start
+        ));
+
+        type Metric = fn(&Counts) -> usize;
+        let metrics: [(&str, Metric); 4] = [
+            ("sessions", |c| c.sessions),
+            ("prompts", |c| c.prompts),
+            ("tool calls", |c| c.tool_calls),
+            ("active hours", |c| c.active_hours),
+        ];
+        for (name, metric) in metrics {
+            let values: Vec<usize> = self.periods.iter().map(|p| metric(&p.counts)).collect();
+            out.push_str(&format!(
+                "{name:<12} {:>6}  {}\n",
+                metric(&self.totals),
+                sparkline(&values)
+            ));
+        }
+
+        let rows: Vec<&GroupActivity> = self.projects.iter().take(HEATMAP_ROWS).collect();
+        if !rows.is_empty() {
+            let width = rows
+                .iter()
+                .map(|row| row.name.chars().count().min(24))
+                .max()
+                .unwrap_or(0)
+                .max("project".len());
+            let cells = |name: &str| -> Vec<usize> {
+                self.periods
+                    .iter()
+                    .map(|p| p.projects.get(name).map_or(0, |c| c.active_hours))
+                    .collect()
+            };
+            let max = rows
+                .iter()
+                .flat_map(|row| cells(&row.name))
+                .max()
+                .unwrap_or(0);
+            out.push_str(&format!(
+                "\n{:<width$} {:>6}  active hours per {by}\n",
+                "project", "hours"
+            ));
+            for row in rows {
+                let name: String = row.name.chars().take(24).collect();
+                out.push_str(&format!(
+                    "{name:<width$} {:>6}  {}\n",
+                    row.counts.active_hours,
+                    heat_row(&cells(&row.name), max)
+                ));
+            }
+            if self.projects.len() > HEATMAP_ROWS {
+                out.push_str(&format!(
+                    "({} more projects)\n",
+                    self.projects.len() - HEATMAP_ROWS
+                ));
+            }
+        }
+
+        if !self.sources.is_empty() {
+            out.push_str("\nsource     sessions  prompts  tool calls  active hours\n");
+            for source in &self.sources {
+                let c = &source.counts;
+                out.push_str(&format!(
+                    "{:<10} {:>8} {:>8} {:>11} {:>13}\n",
+                    source.name, c.sessions, c.prompts, c.tool_calls, c.active_hours
+                ));
+            }
+        }
+
+        if !self.longest_sessions.is_empty() {
+            out.push_str("\nlongest sessions\n");
+            for span in &self.longest_sessions {
+                out.push_str(&format!(
+                    "{:>3}h{:02}m  {}  {}  {} ({} prompts, {} tool calls)\n",
+                    span.minutes / 60,
+                    span.minutes % 60,
+                    span.start,
+                    span.project,
+                    span.session_id,
+                    span.prompts,
+                    span.tool_calls
+                ));
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(ts: u64, project: &str, session: &str, role: &str) -> ActivityEvent {
+        ActivityEvent {
+            ts,
+            project: project.to_string(),
+            session_id: session.to_string(),
+            role: role.to_string(),
+            source: "claude".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_weekly_report() {
+        // 2025-01-01 is a Wednesday.
+        let day = 86_400_000;
+        let jan1 = 1_735_689_600_000;
+        let events = vec![
+            event(jan1, "web", "s1", "user"),
+            event(jan1 + 90 * 60_000, "web", "s1", "tool_use"),
+            event(jan1 + 20 * day, "api", "s2", "user"),
+            event(jan1 + 20 * day + 60_000, "api", "s2", "tool_use"),
+            event(jan1 + 20 * day + 120_000, "api", "s2", "tool_use"),
+        ];
+        let report = report(events, Period::Week, 1);
+
+        let starts: Vec<&str> = report.periods.iter().map(|p| p.start.as_str()).collect();
+        assert_eq!(
+            starts,
+            ["2024-12-30", "2025-01-06", "2025-01-13", "2025-01-20"]
+        );
+        assert_eq!(
+            report.totals,
+            Counts {
+                records: 5,
+                sessions: 2,
+                prompts: 2,
+                tool_calls: 3,
+                active_hours: 3,
+            }
+        );
+        assert_eq!(report.periods[1].counts, Counts::default());
+        assert_eq!(report.projects[0].name, "web");
+        assert_eq!(report.periods[3].projects["api"].tool_calls, 2);
+        assert_eq!(report.longest_sessions.len(), 1);
+        assert_eq!(report.longest_sessions[0].session_id, "s1");
+        assert_eq!(report.longest_sessions[0].minutes, 90);
+
+        assert_eq!(sparkline(&[0, 1, 4, 8]), " ▁▄█");
+    }
+}
@@ -0,0 +1,251 @@
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// One model's embeddings by a hash of the text they were made from. It
+/// outlives the index and vector stores, so a rebuilt index only embeds text
+/// it has never seen.
+///
+/// `keys.bin` holds one 16-byte hash per row and `vectors.bin` the rows'
+/// embeddings, `dims` little-endian f32s each, in the same order.
+pub struct EmbeddingCache {
+    dims: usize,
+    /// Document prompt version, hashed with each text: the same text behind
+    /// another prompt embeds differently.
+    template: u32,
+    rows: HashMap<[u8; 16], u64>,
+    /// Keys looked up or inserted since the cache was opened.
+    used: HashSet<[u8; 16]>,
+    dir: PathBuf,
+    keys: File,
+    vectors: File,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheInfo {
+    dims: usize,
+}
+
+impl EmbeddingCache {
+    /// Open the cache at `dir`, starting it over if it holds embeddings of
+    /// other dimensions.
+    pub fn open(dir: &Path, dims: usize, template: u32) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        let info_path = dir.join("cache.json");
+        let keys_path = dir.join("keys.bin");
+        let vectors_path = dir.join("vectors.bin");
+        let same_dims = fs::read(&info_path)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CacheInfo>(&bytes).ok())
+            .is_some_and(|info| info.dims == dims);
+        if !same_dims {
+            for path in [&keys_path, &vectors_path] {
+                if path.exists() {
+                    fs::remove_file(path)?;
+                }
+            }
+            fs::write(&info_path, serde_json::to_vec(&CacheInfo { dims })?)?;
+        }
+
+        let open = |path: &Path| {
+            OpenOptions::new()
+                .read(true)
+                .append(true)
+                .create(true)
+                .open(path)
+        };
+        let mut keys = open(&keys_path)?;
+        let vectors = open(&vectors_path)?;
+        let row_bytes = (dims * 4) as u64;
+        let mut bytes = Vec::new();
+        keys.read_to_end(&mut bytes)?;
+        // A write cut short leaves a partial row in one file or the other.
+        let count = (bytes.len() as u64 / 16).min(vectors.metadata()?.len() / row_bytes);
+        keys.set_len(count * 16)?;
+        vectors.set_len(count * row_bytes)?;
+        let rows = bytes
+            .chunks_exact(16)
+            .take(count as usize)
+            .enumerate()
+            .map(|(row, key)| (key.try_into().unwrap(), row as u64))
+            .collect();
+
+        Ok(Self {
+            dims,
+            template,
+            rows,
+            used: HashSet::new(),
+            dir: dir.to_path_buf(),
+            keys,
+            vectors,
+        })
+    }
+
+    fn key(&self, text: &str) -> [u8; 16] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.template.to_le_bytes());
+        hasher.update(text.as_bytes());
+        hasher.finalize()[..16].try_into().unwrap()
+    }
+
+    /// The cached embedding of `text`, if it has one.
+    pub fn get(&mut self, text: &str) -> Result<Option<Vec<f32>>> {
+        let key = self.key(text);
+        let Some(&row) = self.rows.get(&key) else {
+            return Ok(None);
+        };
+        self.used.insert(key);
+        let mut bytes = vec![0u8; self.dims * 4];
+        self.vectors
+            .seek(SeekFrom::Start(row * bytes.len() as u64))?;
+        self.vectors.read_exact(&mut bytes)?;
+        Ok(Some(
+            bytes
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes(b.try_into().unwrap()))
+                .collect(),
+        ))
+    }
+
+    /// Cache each text's embedding. Texts already cached are skipped.
+    pub fn insert(&mut self, entries: &[(&str, &[f32])]) -> Result<()> {
+        let mut keys = Vec::new();
+        let mut vectors = Vec::new();
+        for (text, embedding) in entries {
+            if embedding.len() != self.dims {
+                return Err(anyhow!(
+                    "embedding dimensions mismatch: expected {}, got {}",
+                    self.dims,
+                    embedding.len()
+                ));
+            }
+            let key = self.key(text);
+            self.used.insert(key);
+            if self.rows.contains_key(&key) {
+                continue;
+            }
+            self.rows.insert(key, self.rows.len() as u64);
+            keys.extend_from_slice(&key);
+            for x in *embedding {
+                vectors.extend_from_slice(&x.to_le_bytes());
+            }
+        }
+        // Vectors first: a key never points past the end of vectors.bin.
+        self.vectors.write_all(&vectors)?;
+        self.keys.write_all(&keys)?;
+        Ok(())
+    }
+
+    /// Drop the entries not looked up or inserted since the cache was
+    /// opened. Run after a full reindex, which looks up every chunk still in
+    /// the index. Returns the number of entries dropped.
+    pub fn retain_used(mut self) -> Result<usize> {
+        let mut rows: Vec<([u8; 16], u64)> = self
+            .rows
+            .iter()
+            .filter(|(key, _)| self.used.contains(*key))
+            .map(|(key, row)| (*key, *row))
+            .collect();
+        let dropped = self.rows.len() - rows.len();
+        if dropped == 0 {
+            return Ok(0);
+        }
+        rows.sort_unstable_by_key(|(_, row)| *row);
+        let row_bytes = self.dims * 4;
+        let mut keys = Vec::with_capacity(rows.len() * 16);
+        let mut vectors = vec![0u8; rows.len() * row_bytes];
+        for ((key, row), out) in rows.iter().zip(vectors.chunks_exact_mut(row_bytes)) {
+            keys.extend_from_slice(key);
+            self.vectors.seek(SeekFrom::Start(row * row_bytes as u64))?;
+            self.vectors.read_exact(out)?;
+        }
+        drop(self.keys);
+        drop(self.vectors);
+
+        // Swap in a rewritten directory: an interrupted prune leaves no
+        // cache, never keys paired with the wrong vectors.
+        let mut tmp = self.dir.clone().into_os_string();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::create_dir_all(&tmp)?;
+        fs::write(
+            tmp.join("cache.json"),
+            serde_json::to_vec(&CacheInfo { dims: self.dims })?,
+        )?;
+        fs::write(tmp.join("keys.bin"), &keys)?;
+        fs::write(tmp.join("vectors.bin"), &vectors)?;
+        fs::remove_dir_all(&self.dir)?;
+        fs::rename(&tmp, &self.dir)?;
+        Ok(dropped)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_cache_round_trip() {
+        let tmp = TempDir::new().unwrap();
+        {
+            let mut cache = EmbeddingCache::open(tmp.path(), 3, 0).unwrap();
+            assert!(cache.get("hello").unwrap().is_none());
+            cache
+                .insert(&[("hello", &[1.0, 2.0, 3.0]), ("world", &[4.0, 5.0, 6.0])])
+                .unwrap();
+            cache.insert(&[("hello", &[9.0, 9.0, 9.0])]).unwrap();
+            assert_eq!(cache.get("world").unwrap(), Some(vec![4.0, 5.0, 6.0]));
+        }
+
+        let mut cache = EmbeddingCache::open(tmp.path(), 3, 0).unwrap();
+        assert_eq!(cache.rows.len(), 2);
+        assert_eq!(cache.get("hello").unwrap(), Some(vec![1.0, 2.0, 3.0]));
+        // Another document prompt embeds the same text differently.
+        let mut prompted = EmbeddingCache::open(tmp.path(), 3, 1).unwrap();
+        assert!(prompted.get("hello").unwrap().is_none());
+
+        // A torn write is dropped; other dimensions start over.
+        OpenOptions::new()
+            .append(true)
+            .open(tmp.path().join("keys.bin"))
+            .unwrap()
+            .write_all(&[7; 16])
+            .unwrap();
+        assert_eq!(
+            EmbeddingCache::open(tmp.path(), 3, 0).unwrap().rows.len(),
+            2
+        );
+        assert_eq!(
+            EmbeddingCache::open(tmp.path(), 4, 0).unwrap().rows.len(),
+            0
+        );
+    }
+
+    #[test]
+    fn test_retain_used_drops_unused_entries() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("model-a");
+        EmbeddingCache::open(&dir, 2, 0)
+            .unwrap()
+            .insert(&[("kept", &[1.0, 2.0]), ("gone", &[3.0, 4.0])])
+            .unwrap();
+        EmbeddingCache::open(&tmp.path().join("model-b"), 2, 0).unwrap();
+
+        let mut cache = EmbeddingCache::open(&dir, 2, 0).unwrap();
+        assert!(cache.get("kept").unwrap().is_some());
+        cache.insert(&[("new", &[5.0, 6.0])]).unwrap();
+        assert_eq!(cache.retain_used().unwrap(), 1);
+        assert!(tmp.path().join("model-b").join("cache.json").exists());
+
+        let mut cache = EmbeddingCache::open(&dir, 2, 0).unwrap();
+        assert_eq!(cache.rows.len(), 2);
+        assert_eq!(cache.get("kept").unwrap(), Some(vec![1.0, 2.0]));
+        assert_eq!(cache.get("new").unwrap(), Some(vec![5.0, 6.0]));
+        assert!(cache.get("gone").unwrap().is_none());
+    }
+}
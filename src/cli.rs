@@ -1,14 +1,26 @@
+use crate::activity::{self, Period};
+use crate::cache::EmbeddingCache;
+use crate::commands::command_output;
 use crate::config::{Paths, UserConfig, default_claude_source};
-use crate::embed::{EmbedderHandle, ModelChoice};
-use crate::index::{QueryOptions, SearchIndex};
-use crate::ingest::{IngestOptions, ingest_all, ingest_if_stale};
+use crate::embed::{EmbedderHandle, ModelChoice, RerankerChoice, RerankerHandle};
+use crate::files::{PathPattern, is_modifying_call};
+use crate::fusion::{self, Explain, Fusion, FusionStrategy, Ranked, VectorRank};
+use crate::index::{Facets, QueryOptions, SearchIndex, plain_query_text};
+use crate::ingest::{
+    IngestOptions, embed_chunks, ingest_all, ingest_if_stale, update_session_vectors,
+};
+use crate::models::{ModelCache, ModelFiles};
+use crate::patch::{Baseline, FilePatch, changeset};
+use crate::replay::{Segment, replay_file};
+use crate::snippet::{Highlighter, Highlights, best_match, sentence_windows};
+use crate::topics;
 use crate::tui;
-use crate::types::SourceFilter;
-use crate::vector::VectorIndex;
+use crate::types::{Record, SourceFilter};
+use crate::vector::{Chunk, SessionVectors, VectorIndex, stored_vectors};
 use anyhow::{Result, anyhow};
 use chrono::SecondsFormat;
 use clap::{Args, Parser, Subcommand, ValueEnum};
-use regex::RegexBuilder;
+use regex::Regex;
 use serde::Serialize;
 use serde_json::Value;
 use std::collections::HashMap;
@@ -56,7 +68,7 @@ struct IndexArgs {
     /// Skip embedding generation (overrides config default)
     #[arg(long)]
     no_embeddings: bool,
-    /// Embedding model: minilm (fast), bge, nomic, gemma (default, best quality), potion (tiny)
+    /// Embedding model: minilm (fast), bge, nomic, gemma (default, best quality), potion (tiny), or a [models.<name>] entry in config.toml
     #[arg(long)]
     model: Option<String>,
     /// Path to memex data directory [default: ~/.memex]
@@ -94,13 +106,19 @@ EXAMPLES:
     },
     /// Generate embeddings for semantic search (requires existing index)
     Embed {
-        /// Embedding model: minilm (fast), bge, nomic, gemma (default, best quality), potion (tiny)
+        /// Embedding model: minilm (fast), bge, nomic, gemma (default, best quality), potion (tiny), or a [models.<name>] entry in config.toml
         #[arg(long)]
         model: Option<String>,
         /// Path to memex data directory [default: ~/.memex]
         #[arg(long)]
         root: Option<PathBuf>,
     },
+    /// Upgrade an index built by an older memex to the current schema
+    Migrate {
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
     /// Search indexed conversation history
     #[command(after_help = "\
 EXAMPLES:
@@ -108,6 +126,21 @@ EXAMPLES:
     memex search \"API design\" --source claude --limit 50
     memex search \"auth\" --since 2024-01-01T00:00:00Z --semantic
     memex search \"bug\" --fields score,session_id,snippet --json-array
+    memex search 'output:panicked tool:Bash since:7d -project:scratch'
+    memex search 'reqwst clint' --fuzzy   # Typos and prefixes: matches reqwest client
+    memex search \"how did we fix the flaky upload test\" --hybrid --rerank
+    memex search \"retry backoff\" --hybrid --fusion min-max --vector-weight 2 --explain
+    memex search 'error\\[E0\\d{3}\\]' --regex --role tool_result
+    memex search \"timeout\" --facets --json-array   # Last element holds the counts
+    memex search \"migrate the auth middleware\" --sessions --semantic
+
+QUERY SYNTAX:
+    tool:Bash  role:assistant  project:foo  session:<id>  source:codex
+    input:\"cargo test\"  output:panicked    search tool inputs / outputs
+    since:2024-01-01  until:2024-02  date:2024-03-15  since:7d
+    -term or -field:value to exclude, term^2 to boost, (a OR b) to group.
+    Text the syntax cannot parse, such as foo(bar), is searched as plain words.
+    parseFile, parse_file and \"parse file\" match the same identifier.
 
 TIMESTAMP FORMAT:
     RFC3339: 2024-01-15T10:30:00Z or 2024-01-15T10:30:00-05:00
@@ -115,7 +148,14 @@ TIMESTAMP FORMAT:
     Unix milliseconds: 1705315800000
 
 OUTPUT FIELDS (--fields):
-    score, ts, doc_id, project, role, session_id, source_path, text, snippet, matches")]
+    score, ts, doc_id, project, role, session_id, source_path, text, snippet, matches,
+    rerank_score (with --rerank: the cross-encoder's relevance, before recency)
+    explain (with --explain: bm25 and vector score/rank, base, rerank, recency, score)
+
+FUSION (--hybrid):
+    rrf (default), weighted-rrf, min-max, z-score, bm25. A query that is one
+    quoted phrase, such as '\"connection reset\"', always uses bm25 alone.
+    --with-pair adds a pair field with the matching tool_use or tool_result.")]
     Search {
         /// Search query (keywords or natural language for semantic search)
         query: String,
@@ -140,6 +180,44 @@ OUTPUT FIELDS (--fields):
         /// Use hybrid search combining BM25 keyword and semantic scores
         #[arg(long)]
         hybrid: bool,
+        /// Rank whole sessions by their mean embedding (with --semantic)
+        #[arg(
+            long,
+            requires = "semantic",
+            conflicts_with_all = ["top_n_per_session", "unique_session", "with_pair", "facets", "explain"]
+        )]
+        sessions: bool,
+        /// Also match misspelled words and prefixes (edit distance 0-2, default 1)
+        #[arg(
+            long,
+            value_name = "DISTANCE",
+            num_args = 0..=1,
+            require_equals = true,
+            default_missing_value = "1",
+            value_parser = clap::value_parser!(u8).range(0..=2)
+        )]
+        fuzzy: Option<u8>,
+        /// How --hybrid combines BM25 and vector rankings [default: rrf, or fusion in config.toml]
+        #[arg(long, value_enum, requires = "hybrid")]
+        fusion: Option<FusionStrategy>,
+        /// Weight of the BM25 ranking for weighted-rrf, min-max and z-score fusion
+        #[arg(long, value_name = "WEIGHT", requires = "hybrid")]
+        bm25_weight: Option<f32>,
+        /// Weight of the vector ranking for weighted-rrf, min-max and z-score fusion
+        #[arg(long, value_name = "WEIGHT", requires = "hybrid")]
+        vector_weight: Option<f32>,
+        /// Add each hit's component scores, ranks and recency factor
+        #[arg(long, conflicts_with = "regex")]
+        explain: bool,
+        /// Rescore the top hybrid candidates with a cross-encoder (see rerank_* in config.toml)
+        #[arg(long, requires = "hybrid")]
+        rerank: bool,
+        /// Skip reranking even when config.toml enables it
+        #[arg(long)]
+        no_rerank: bool,
+        /// Treat the query as a regular expression over record text (scored by match count)
+        #[arg(long, conflicts_with_all = ["semantic", "hybrid", "fuzzy"])]
+        regex: bool,
         /// Minimum score threshold to include in results
         #[arg(long)]
         min_score: Option<f32>,
@@ -170,9 +248,93 @@ OUTPUT FIELDS (--fields):
         /// Comma-separated list of fields to include in output
         #[arg(long, value_name = "FIELDS")]
         fields: Option<String>,
+        /// Maximum length of the snippet around the best match
+        #[arg(long, value_name = "CHARS", default_value_t = 400)]
+        snippet_chars: usize,
+        /// Pick semantic hits' snippets by embedding their sentences, not just
+        /// the closest chunk (slower: up to 32 extra embeddings per search)
+        #[arg(long)]
+        semantic_snippets: bool,
         /// Sort results by score or timestamp
         #[arg(long, value_enum, default_value = "score")]
         sort: SortBy,
+        /// Include the tool_result for tool_use hits (and the tool_use for results)
+        #[arg(long)]
+        with_pair: bool,
+        /// Append hit counts by project, role, tool, source, session and month
+        #[arg(long, conflicts_with = "regex")]
+        facets: bool,
+        /// Show verbose output with inline text preview
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Find records like a given record or session, without a query
+    #[command(after_help = "\
+EXAMPLES:
+    memex similar 12345                         # Records like doc 12345, other sessions
+    memex similar --session <session_id> --unique-session
+    memex similar 12345 --text --role assistant # Also match shared terms
+    memex similar --session <session_id> --since 2025-01-01T00:00:00Z -v
+
+The seed's stored embedding (a session uses the mean of its records') is the
+query for the vector index. --text adds tantivy's MoreLikeThis over the seed's
+text, fused by reciprocal rank; without embeddings it is the only ranking.
+Records from the seed's own session are left out unless --include-seed-session.")]
+    Similar {
+        /// Doc id to find records like (from search results)
+        #[arg(required_unless_present = "session", conflicts_with = "session")]
+        doc_id: Option<u64>,
+        /// Find records like this whole session instead
+        #[arg(long)]
+        session: Option<String>,
+        /// Also rank by distinctive terms shared with the seed text
+        #[arg(long)]
+        text: bool,
+        /// Keep hits from the seed's own session
+        #[arg(long)]
+        include_seed_session: bool,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by role (user, assistant, tool_use, tool_result)
+        #[arg(long)]
+        role: Option<String>,
+        /// Filter by tool name (e.g., Read, Edit, Bash)
+        #[arg(long)]
+        tool: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Only include results after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only include results before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Minimum score threshold to include in results
+        #[arg(long)]
+        min_score: Option<f32>,
+        /// Maximum number of results to return
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+        /// Limit results per session (useful for getting variety)
+        #[arg(long = "top-n-per-session", value_name = "N")]
+        top_n_per_session: Option<usize>,
+        /// Return at most one result per session (shorthand for --top-n-per-session 1)
+        #[arg(long)]
+        unique_session: bool,
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Comma-separated list of fields to include in output
+        #[arg(long, value_name = "FIELDS")]
+        fields: Option<String>,
+        /// Maximum length of the text preview
+        #[arg(long, value_name = "CHARS", default_value_t = 400)]
+        snippet_chars: usize,
         /// Show verbose output with inline text preview
         #[arg(short, long)]
         verbose: bool,
@@ -180,6 +342,184 @@ OUTPUT FIELDS (--fields):
         #[arg(long)]
         root: Option<PathBuf>,
     },
+    /// List past sessions most like a given session
+    #[command(after_help = "\
+EXAMPLES:
+    memex related <session_id>
+    memex related <session_id> --project memex --since 2025-01-01T00:00:00Z -v
+
+Sessions are compared by their mean message embedding, built by `memex index`
+(with embeddings on) or `memex embed`. A session matches the filters when any
+of its records does.
+
+OUTPUT FIELDS (--fields):
+    score, session_id, project, source, start, end, records, prompts, title, source_path")]
+    Related {
+        /// Session to find related sessions for
+        session_id: String,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Only include sessions active after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only include sessions active before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Minimum score threshold to include in results
+        #[arg(long)]
+        min_score: Option<f32>,
+        /// Maximum number of sessions to return
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Comma-separated list of fields to include in output
+        #[arg(long, value_name = "FIELDS")]
+        fields: Option<String>,
+        /// Show verbose output, one line per session
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Count hits for a query by project, role, tool, source, session and month
+    #[command(after_help = "\
+EXAMPLES:
+    memex facets \"flaky test\"
+    memex facets 'tool:Bash output:panicked' --since 2024-06-01T00:00:00Z
+    memex facets --project memex -v          # Every record, counted
+    memex facets \"auth\" --size 5 | jq '.project'
+
+Takes the same query syntax as memex search. Prints one JSON object with the
+total hit count and, per group, the largest buckets as {value, count}.")]
+    Facets {
+        /// Search query (empty counts every record matching the filters)
+        #[arg(default_value = "")]
+        query: String,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by role (user, assistant, tool_use, tool_result)
+        #[arg(long)]
+        role: Option<String>,
+        /// Filter by tool name (e.g., Read, Edit, Bash)
+        #[arg(long)]
+        tool: Option<String>,
+        /// Filter by session ID
+        #[arg(long)]
+        session: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Only count hits after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only count hits before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Buckets to keep per group (months are always all kept)
+        #[arg(long, default_value_t = 10)]
+        size: usize,
+        /// Show human-readable output
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Report sessions, prompts, tool calls and active hours over time
+    #[command(after_help = "\
+EXAMPLES:
+    memex activity -v                          # Last 90 days, by day
+    memex activity --by week --since 2025-01-01T00:00:00Z -v
+    memex activity --project memex --source claude
+    memex activity 'tool:Bash' --by week       # Only records matching a query
+
+Prints one JSON object: totals, one entry per day or week (with per-project and
+per-source counts), projects and sources ranked by active hours, and the
+longest sessions. -v draws sparklines, a project heatmap and tables instead.
+A session counts in the period of its first record; active hours are distinct
+clock hours (UTC) with any record.")]
+    Activity {
+        /// Only count records matching this query (same syntax as search)
+        #[arg(default_value = "")]
+        query: String,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Start of the report (RFC3339 or unix seconds/ms) [default: 90 days ago]
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// End of the report (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Bucket size
+        #[arg(long, value_enum, default_value = "day")]
+        by: Period,
+        /// Number of longest sessions to list
+        #[arg(long, default_value_t = 10)]
+        top: usize,
+        /// Show human-readable output
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Cluster sessions into recurring themes
+    #[command(after_help = "\
+EXAMPLES:
+    memex topics -v
+    memex topics --project memex --since 2025-01-01T00:00:00Z -v
+    memex topics --clusters 12 --terms 8 --members 20
+
+Groups sessions by their mean message embedding (built by `memex index` with
+embeddings on, or `memex embed`) using k-means, and labels each topic with the
+terms that most set its sessions' prompts and replies apart from the rest of the
+index (BM25 weighting). Prints one JSON object per topic, largest first: terms,
+size, first and last activity, and its sessions closest to the center first.
+A session matches the filters when any of its records does.")]
+    Topics {
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Only include sessions active after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only include sessions active before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Number of topics [default: about sqrt(sessions / 2), 2 to 20]
+        #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+        clusters: Option<u64>,
+        /// Label terms per topic
+        #[arg(long, default_value_t = 6)]
+        terms: usize,
+        /// Sessions listed per topic
+        #[arg(long, default_value_t = 5)]
+        members: usize,
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Show human-readable output
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
     /// Interactive terminal UI for browsing sessions
     Tui {
         /// Path to memex data directory [default: ~/.memex]
@@ -195,6 +535,9 @@ OUTPUT FIELDS (--fields):
     Session {
         /// Session ID (from search results or TUI)
         session_id: String,
+        /// Render each tool call together with its result
+        #[arg(long)]
+        with_pair: bool,
         /// Show human-readable output with timestamps and role labels
         #[arg(short, long)]
         verbose: bool,
@@ -206,6 +549,9 @@ OUTPUT FIELDS (--fields):
     Show {
         /// Document ID (from search results)
         doc_id: u64,
+        /// Include the tool_result for a tool_use (or the tool_use for a result)
+        #[arg(long)]
+        with_pair: bool,
         /// Pretty-print JSON output
         #[arg(short, long)]
         verbose: bool,
@@ -213,12 +559,186 @@ OUTPUT FIELDS (--fields):
         #[arg(long)]
         root: Option<PathBuf>,
     },
+    /// List the sessions and turns that read or modified a file
+    #[command(after_help = "\
+EXAMPLES:
+    memex files src/config.rs                     # Sessions that read or edited it
+    memex files 'src/**/*.toml' --modified-only   # Only calls that changed a matching file
+    memex files /etc/nginx/nginx.conf -v          # Human-readable, grouped by session
+
+PATTERNS:
+    Relative paths and globs match the end of the recorded path, so
+    src/*.rs matches /home/me/proj/src/main.rs. Absolute patterns are anchored.
+    Globs support *, **, ?, [abc] and {a,b}.")]
+    Files {
+        /// File path or glob
+        pattern: String,
+        /// Only include calls that modified the file (Edit, Write, MultiEdit, apply_patch)
+        #[arg(long)]
+        modified_only: bool,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Only include calls after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only include calls before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Maximum number of turns to return
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Show human-readable output grouped by session
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Print the changes a session made to each file as a unified diff
+    #[command(after_help = "\
+EXAMPLES:
+    memex diff abc123                    # Every file the session changed
+    memex diff abc123 --path 'src/**'    # Only matching files
+    memex diff abc123 | patch -d / -p1   # Re-apply changes to absolute paths
+    memex diff abc123 --json             # One JSON object per file
+
+PATHS:
+    Files are named as the agent's tool named them. Claude's are absolute, so
+    apply them from anywhere with `patch -d / -p1`; Codex's are relative to
+    the session's working directory, so apply them there with `patch -p0`.
+
+BASELINES:
+    When the session created a file or the agent's tool reported its contents
+    before the first edit, the edits are replayed into one cumulative diff.
+    Otherwise each patch is printed in the order it was applied.")]
+    Diff {
+        /// Session ID (from search results or TUI)
+        session_id: String,
+        /// Only include files matching this path or glob
+        #[arg(long)]
+        path: Option<String>,
+        /// Output one JSON object per file instead of a unified diff
+        #[arg(long)]
+        json: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Reconstruct a file as a session's agent last saw or wrote it
+    #[command(after_help = "\
+EXAMPLES:
+    memex file-at abc123 src/config.rs            # As of the end of the session
+    memex file-at abc123 src/config.rs --turn 40  # As of turn 40
+    memex file-at abc123 config.toml --json       # Known segments, gaps and notes
+
+GAPS:
+    Contents are replayed from Write calls, Edit strings, captured patches and
+    Read results. Lines the session never saw are shown as gap markers (or
+    listed in --json output); the exit status is 0 only when the file is
+    known in full.")]
+    FileAt {
+        /// Session ID (from search results or TUI)
+        session_id: String,
+        /// File path or glob (must match exactly one file in the session)
+        path: String,
+        /// Replay up to and including this turn
+        #[arg(long)]
+        turn: Option<u32>,
+        /// Output JSON with known segments, gaps and notes
+        #[arg(long)]
+        json: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Search shell commands run by agents, like a shell history across sessions
+    #[command(after_help = "\
+EXAMPLES:
+    memex commands-run cargo                      # Commands containing 'cargo'
+    memex commands-run 'git push' --prefix        # Commands starting with 'git push'
+    memex commands-run '^docker (run|build)' --regex --since 2025-01-01
+    memex commands-run --failed -v                # Recent failing commands with output
+    memex commands-run kubectl --plain | fzf      # Pick a command interactively
+
+OUTPUT:
+    JSONL by default: one line per command with its exit code and the tail of
+    its output. --plain prints each distinct command once, newest first, with
+    newlines escaped; --print0 prints them NUL-separated and unescaped.")]
+    #[command(name = "commands-run")]
+    ShellHistory {
+        /// Text to look for in the command (substring by default)
+        pattern: Option<String>,
+        /// Match commands that start with PATTERN
+        #[arg(long, conflicts_with = "regex")]
+        prefix: bool,
+        /// Treat PATTERN as a regular expression
+        #[arg(long)]
+        regex: bool,
+        /// Only include commands that exited non-zero
+        #[arg(long)]
+        failed: bool,
+        /// Filter by project name
+        #[arg(long)]
+        project: Option<String>,
+        /// Filter by source: claude or codex
+        #[arg(long)]
+        source: Option<SourceFilter>,
+        /// Filter by session ID
+        #[arg(long)]
+        session: Option<String>,
+        /// Only include commands after this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        since: Option<String>,
+        /// Only include commands before this timestamp (RFC3339 or unix seconds/ms)
+        #[arg(long, value_name = "TIMESTAMP")]
+        until: Option<String>,
+        /// Maximum number of commands to return
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Print each distinct command once per line (for fzf and shell pipelines)
+        #[arg(long, conflicts_with_all = ["print0", "json_array", "verbose"])]
+        plain: bool,
+        /// Print each distinct command once, NUL-separated
+        #[arg(long, conflicts_with_all = ["json_array", "verbose"])]
+        print0: bool,
+        /// Characters of output to keep (from the end)
+        #[arg(long, default_value_t = 400)]
+        output_chars: usize,
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Show human-readable output
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
     /// Show index statistics (document count, vector count, storage paths)
     Stats {
         /// Path to memex data directory [default: ~/.memex]
         #[arg(long)]
         root: Option<PathBuf>,
     },
+    /// List, download, verify and remove embedding and reranking models
+    #[command(after_help = "\
+EXAMPLES:
+    memex models list -v
+    memex models pull gemma bge-reranker-base
+    memex models verify
+    memex models rm --unused
+    memex models path gemma")]
+    Models {
+        #[command(subcommand)]
+        action: ModelsCommand,
+    },
     /// Install the memex-search skill for Claude, Codex, and/or Opencode
     Setup {
         /// Overwrite existing skills/prompts (useful after memex update)
@@ -299,30 +819,88 @@ enum IndexServiceCommand {
     },
 }
 
-pub fn run() -> Result<()> {
-    let cli = Cli::parse();
-    let should_check = !matches!(cli.command, Commands::Tui { .. } | Commands::Update { .. });
-    if should_check {
-        check_for_update_async(None);
-    }
-    match cli.command {
-        Commands::Index {
-            index,
-            watch,
-            watch_interval,
-        } => {
-            if watch {
-                run_index_loop(&index, watch_interval)?;
-            } else {
-                run_index_args(&index, false)?;
-            }
-        }
-        Commands::Reindex { index } => {
-            run_index_args(&index, true)?;
+#[derive(Subcommand)]
+enum ModelsCommand {
+    /// Show each built-in model, whether it is downloaded, its size and location
+    List {
+        /// Output results as a single JSON array instead of newline-delimited JSON
+        #[arg(long)]
+        json_array: bool,
+        /// Show human-readable output
+        #[arg(short, long)]
+        verbose: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Download models now, even with offline = true [default: the configured model]
+    Pull {
+        /// Model names from `memex models list`
+        names: Vec<String>,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Check downloaded weights against their sha256 checksums [default: all downloaded]
+    Verify {
+        /// Model names from `memex models list`
+        names: Vec<String>,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Delete downloaded models
+    Rm {
+        /// Model names from `memex models list`
+        #[arg(required_unless_present = "unused")]
+        names: Vec<String>,
+        /// Delete every model except the configured embedding and reranking models
+        #[arg(long, conflicts_with = "names")]
+        unused: bool,
+        /// Delete named models even if config.toml selects them
+        #[arg(long)]
+        force: bool,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+    /// Print the model directory, or where a model's files are
+    Path {
+        /// Model name from `memex models list`
+        name: Option<String>,
+        /// Path to memex data directory [default: ~/.memex]
+        #[arg(long)]
+        root: Option<PathBuf>,
+    },
+}
+
+pub fn run() -> Result<()> {
+    let cli = Cli::parse();
+    let should_check = !matches!(cli.command, Commands::Tui { .. } | Commands::Update { .. });
+    if should_check {
+        check_for_update_async(None);
+    }
+    match cli.command {
+        Commands::Index {
+            index,
+            watch,
+            watch_interval,
+        } => {
+            if watch {
+                run_index_loop(&index, watch_interval)?;
+            } else {
+                run_index_args(&index, false)?;
+            }
+        }
+        Commands::Reindex { index } => {
+            run_index_args(&index, true)?;
         }
         Commands::Embed { model, root } => {
             run_embed(model, root)?;
         }
+        Commands::Migrate { root } => {
+            run_migrate(root)?;
+        }
         Commands::Search {
             query,
             project,
@@ -332,6 +910,15 @@ pub fn run() -> Result<()> {
             source,
             semantic,
             hybrid,
+            sessions,
+            fuzzy,
+            fusion,
+            bm25_weight,
+            vector_weight,
+            explain,
+            rerank,
+            no_rerank,
+            regex,
             min_score,
             recency_weight,
             recency_half_life_days,
@@ -342,7 +929,11 @@ pub fn run() -> Result<()> {
             unique_session,
             json_array,
             fields,
+            snippet_chars,
+            semantic_snippets,
             sort,
+            with_pair,
+            facets,
             verbose,
             root,
         } => {
@@ -355,6 +946,15 @@ pub fn run() -> Result<()> {
                 source,
                 semantic,
                 hybrid,
+                sessions,
+                fuzzy,
+                fusion,
+                bm25_weight,
+                vector_weight,
+                explain,
+                rerank,
+                no_rerank,
+                regex,
                 min_score,
                 recency_weight,
                 recency_half_life_days,
@@ -365,16 +965,208 @@ pub fn run() -> Result<()> {
                 unique_session,
                 json_array,
                 fields,
+                snippet_chars,
+                semantic_snippets,
                 sort,
+                with_pair,
+                facets,
                 verbose,
                 root,
             )?;
         }
+        Commands::Similar {
+            doc_id,
+            session,
+            text,
+            include_seed_session,
+            project,
+            role,
+            tool,
+            source,
+            since,
+            until,
+            min_score,
+            limit,
+            top_n_per_session,
+            unique_session,
+            json_array,
+            fields,
+            snippet_chars,
+            verbose,
+            root,
+        } => {
+            let seed = match (doc_id, session) {
+                (Some(doc_id), _) => Seed::Doc(doc_id),
+                (None, Some(session)) => Seed::Session(session),
+                (None, None) => return Err(anyhow!("give a doc id or --session")),
+            };
+            let options = QueryOptions {
+                query: String::new(),
+                project,
+                role,
+                tool,
+                session_id: None,
+                source,
+                since: parse_ts_millis(since)?,
+                until: parse_ts_millis(until)?,
+                fuzzy: None,
+                limit,
+            };
+            let top_n_per_session = if unique_session && top_n_per_session.is_none() {
+                Some(1)
+            } else {
+                top_n_per_session
+            };
+            let render = RenderOptions {
+                verbose,
+                highlighter: None,
+                snippet_chars,
+                semantic_snippets: false,
+                windows: HashMap::new(),
+                rerank_scores: HashMap::new(),
+                explain: None,
+                json_array: json_array && !verbose,
+                fields: parse_fields(fields)?,
+                sort: SortBy::Score,
+                min_score,
+                top_n_per_session,
+                limit,
+                with_pair: false,
+                facets: None,
+            };
+            run_similar(seed, text, include_seed_session, &options, &render, root)?;
+        }
+        Commands::Related {
+            session_id,
+            project,
+            source,
+            since,
+            until,
+            min_score,
+            limit,
+            json_array,
+            fields,
+            verbose,
+            root,
+        } => {
+            let options = QueryOptions {
+                query: String::new(),
+                project,
+                role: None,
+                tool: None,
+                session_id: None,
+                source,
+                since: parse_ts_millis(since)?,
+                until: parse_ts_millis(until)?,
+                fuzzy: None,
+                limit,
+            };
+            let render = RenderOptions {
+                verbose,
+                highlighter: None,
+                snippet_chars: SESSION_TITLE_CHARS,
+                semantic_snippets: false,
+                windows: HashMap::new(),
+                rerank_scores: HashMap::new(),
+                explain: None,
+                json_array: json_array && !verbose,
+                fields: parse_fields(fields)?,
+                sort: SortBy::Score,
+                min_score,
+                top_n_per_session: None,
+                limit,
+                with_pair: false,
+                facets: None,
+            };
+            run_related(&session_id, &options, &render, root)?;
+        }
+        Commands::Facets {
+            query,
+            project,
+            role,
+            tool,
+            session,
+            source,
+            since,
+            until,
+            size,
+            verbose,
+            root,
+        } => {
+            let options = QueryOptions {
+                query,
+                project,
+                role,
+                tool,
+                session_id: session,
+                source,
+                since: parse_ts_millis(since)?,
+                until: parse_ts_millis(until)?,
+                fuzzy: None,
+                limit: 0,
+            };
+            run_facets(options, size, verbose, root)?;
+        }
+        Commands::Activity {
+            query,
+            project,
+            source,
+            since,
+            until,
+            by,
+            top,
+            verbose,
+            root,
+        } => {
+            let options = QueryOptions {
+                query,
+                project,
+                role: None,
+                tool: None,
+                session_id: None,
+                source,
+                since: parse_ts_millis(since)?,
+                until: parse_ts_millis(until)?,
+                fuzzy: None,
+                limit: 0,
+            };
+            run_activity(options, by, top, verbose, root)?;
+        }
+        Commands::Topics {
+            project,
+            source,
+            since,
+            until,
+            clusters,
+            terms,
+            members,
+            json_array,
+            verbose,
+            root,
+        } => run_topics(
+            project, source, since, until, clusters, terms, members, json_array, verbose, root,
+        )?,
         Commands::Tui { root } => {
             let (update_tx, update_rx) = std::sync::mpsc::channel();
             check_for_update_async(Some(update_tx));
             tui::run(root, Some(update_rx))?;
         }
+        Commands::Models { action } => match action {
+            ModelsCommand::List {
+                json_array,
+                verbose,
+                root,
+            } => run_models_list(json_array, verbose, root)?,
+            ModelsCommand::Pull { names, root } => run_models_pull(names, root)?,
+            ModelsCommand::Verify { names, root } => run_models_verify(names, root)?,
+            ModelsCommand::Rm {
+                names,
+                unused,
+                force,
+                root,
+            } => run_models_rm(names, unused, force, root)?,
+            ModelsCommand::Path { name, root } => run_models_path(name, root)?,
+        },
         Commands::IndexService { action } => match action {
             IndexServiceCommand::Enable {
                 index,
@@ -410,17 +1202,105 @@ pub fn run() -> Result<()> {
         },
         Commands::Session {
             session_id,
+            with_pair,
             verbose,
             root,
         } => {
-            run_session(session_id, verbose, root)?;
+            run_session(session_id, with_pair, verbose, root)?;
         }
         Commands::Show {
             doc_id,
+            with_pair,
+            verbose,
+            root,
+        } => {
+            run_show(doc_id, with_pair, verbose, root)?;
+        }
+        Commands::Files {
+            pattern,
+            modified_only,
+            project,
+            source,
+            since,
+            until,
+            limit,
+            json_array,
+            verbose,
+            root,
+        } => {
+            run_files(
+                pattern,
+                modified_only,
+                project,
+                source,
+                since,
+                until,
+                limit,
+                json_array,
+                verbose,
+                root,
+            )?;
+        }
+        Commands::Diff {
+            session_id,
+            path,
+            json,
+            root,
+        } => {
+            run_diff(session_id, path, json, root)?;
+        }
+        Commands::FileAt {
+            session_id,
+            path,
+            turn,
+            json,
+            root,
+        } => {
+            run_file_at(session_id, path, turn, json, root)?;
+        }
+        Commands::ShellHistory {
+            pattern,
+            prefix,
+            regex,
+            failed,
+            project,
+            source,
+            session,
+            since,
+            until,
+            limit,
+            plain,
+            print0,
+            output_chars,
+            json_array,
             verbose,
             root,
         } => {
-            run_show(doc_id, verbose, root)?;
+            let matcher = CommandMatcher::new(pattern.as_deref(), prefix, regex)?;
+            let options = QueryOptions {
+                query: String::new(),
+                project,
+                role: None,
+                tool: None,
+                session_id: session,
+                source,
+                since: parse_ts_millis(since)?,
+                until: parse_ts_millis(until)?,
+                fuzzy: None,
+                limit,
+            };
+            let output = if plain {
+                CommandOutput::Plain
+            } else if print0 {
+                CommandOutput::Print0
+            } else if verbose {
+                CommandOutput::Verbose
+            } else if json_array {
+                CommandOutput::JsonArray
+            } else {
+                CommandOutput::Jsonl
+            };
+            run_commands(matcher, failed, options, output_chars, output, root)?;
         }
         Commands::Stats { root } => {
             run_stats(root)?;
@@ -488,13 +1368,18 @@ fn run_index(
         "embeddings",
     )?;
     if reindex && paths.root.exists() {
-        std::fs::remove_dir_all(&paths.root)?;
+        paths.clear_for_reindex()?;
     }
     paths.ensure_dirs()?;
+    if crate::index::needs_migration(&paths.index) {
+        migrate_index(&paths)?;
+    }
     let index = SearchIndex::open_or_create(&paths.index)?;
-    let vector_exists = paths.vectors.join("meta.json").exists()
-        && paths.vectors.join("vectors.f32").exists()
-        && paths.vectors.join("doc_ids.u64").exists();
+    crate::vector::migrate_legacy_store(&paths.vectors, &config.resolve_model(None)?)?;
+    let vector_exists = VectorIndex::is_current(
+        &crate::vector::model_store(&paths.vectors, &model_choice),
+        model_choice.template_version(),
+    )?;
     let backfill_embeddings = embeddings && !vector_exists && index.doc_count()? > 0;
 
     let opts = IngestOptions {
@@ -504,7 +1389,9 @@ fn run_index(
         include_opencode: opencode,
         embeddings,
         backfill_embeddings,
+        prune_embed_cache: reindex,
         model: model_choice,
+        models: config.model_cache()?,
     };
 
     let report = ingest_all(&paths, &index, &opts)?;
@@ -525,6 +1412,32 @@ fn run_index(
     Ok(())
 }
 
+fn run_migrate(root: Option<PathBuf>) -> Result<()> {
+    let paths = Paths::new(root)?;
+    if !paths.index.join("meta.json").exists() {
+        return Err(anyhow!("no index at {}", paths.index.display()));
+    }
+    if !crate::index::needs_migration(&paths.index) {
+        println!(
+            "index is already at schema version {}",
+            crate::index::SCHEMA_VERSION
+        );
+        return Ok(());
+    }
+    migrate_index(&paths)
+}
+
+fn migrate_index(paths: &Paths) -> Result<()> {
+    let from = crate::index::schema_version(&paths.index);
+    eprintln!(
+        "migrating index from schema version {from} to {}...",
+        crate::index::SCHEMA_VERSION
+    );
+    let count = crate::index::migrate(&paths.index)?;
+    eprintln!("migrated {count} records");
+    Ok(())
+}
+
 fn run_embed(model: Option<String>, root: Option<PathBuf>) -> Result<()> {
     const BATCH_SIZE: usize = 256;
 
@@ -535,30 +1448,50 @@ fn run_embed(model: Option<String>, root: Option<PathBuf>) -> Result<()> {
     let model_choice = config.resolve_model(model)?;
 
     let index = SearchIndex::open_or_create(&paths.index)?;
-    let mut embedder = EmbedderHandle::with_model(model_choice)?;
-    let mut vector = VectorIndex::open_or_create(&paths.vectors, embedder.dims)?;
+    let mut embedder = EmbedderHandle::with_model(&model_choice, &config.model_cache()?)?;
+    crate::vector::migrate_legacy_store(&paths.vectors, &config.resolve_model(None)?)?;
+    let store = crate::vector::model_store(&paths.vectors, &model_choice);
+    let template = model_choice.template_version();
+    if VectorIndex::exists(&store) && !VectorIndex::is_current(&store, template)? {
+        println!(
+            "{} embeds documents with a new prompt; embedding everything again",
+            model_choice.id()
+        );
+    }
+    let mut vector =
+        VectorIndex::open_or_create(&store, model_choice.id(), embedder.dims, template)?;
+    let mut cache = EmbeddingCache::open(
+        &paths.embed_cache.join(model_choice.id()),
+        embedder.dims,
+        template,
+    )?;
 
     let progress = std::sync::Arc::new(crate::progress::Progress::new([0; 4], [0; 4], true));
     progress.set_embed_ready();
 
     let mut embedded_counts = [0u64; 4];
     let mut embedded_total = 0u64;
+    let mut cached_chunks = 0usize;
     let mut batch: Vec<(u64, String, crate::types::SourceKind)> = Vec::with_capacity(BATCH_SIZE);
 
     let flush_batch = |batch: &mut Vec<(u64, String, crate::types::SourceKind)>,
                        embedder: &mut EmbedderHandle,
                        vector: &mut VectorIndex,
+                       cache: &mut EmbeddingCache,
                        progress: &crate::progress::Progress,
                        embedded_counts: &mut [u64; 4],
-                       embedded_total: &mut u64| {
+                       embedded_total: &mut u64,
+                       cached_chunks: &mut usize| {
         if batch.is_empty() {
             return Ok(());
         }
-        let texts: Vec<&str> = batch.iter().map(|(_, text, _)| text.as_str()).collect();
-        let embeddings = embedder.embed_texts(&texts)?;
+        let docs: Vec<(u64, &str)> = batch
+            .iter()
+            .map(|(doc_id, text, _)| (*doc_id, text.as_str()))
+            .collect();
+        *cached_chunks += embed_chunks(embedder, vector, cache, &docs)?;
 
-        for ((doc_id, _, source), vec) in batch.iter().zip(embeddings.iter()) {
-            vector.add(*doc_id, vec)?;
+        for (_, _, source) in batch.iter() {
             progress.add_embedded(*source, 1);
             embedded_counts[source.idx()] += 1;
             *embedded_total += 1;
@@ -571,24 +1504,23 @@ fn run_embed(model: Option<String>, root: Option<PathBuf>) -> Result<()> {
         if record.text.is_empty() || !is_embedding_role(&record.role) {
             return Ok(());
         }
-        if vector.contains(record.doc_id) {
+        if vector.covers(record.doc_id, &record.text) {
             return Ok(());
         }
-        let text = truncate_for_embedding(record.text);
-        if !text.is_empty() {
-            progress.add_embed_total(record.source, 1);
-            batch.push((record.doc_id, text, record.source));
-
-            if batch.len() >= BATCH_SIZE {
-                flush_batch(
-                    &mut batch,
-                    &mut embedder,
-                    &mut vector,
-                    &progress,
-                    &mut embedded_counts,
-                    &mut embedded_total,
-                )?;
-            }
+        progress.add_embed_total(record.source, 1);
+        batch.push((record.doc_id, record.text, record.source));
+
+        if batch.len() >= BATCH_SIZE {
+            flush_batch(
+                &mut batch,
+                &mut embedder,
+                &mut vector,
+                &mut cache,
+                &progress,
+                &mut embedded_counts,
+                &mut embedded_total,
+                &mut cached_chunks,
+            )?;
         }
         Ok(())
     })?;
@@ -598,21 +1530,29 @@ fn run_embed(model: Option<String>, root: Option<PathBuf>) -> Result<()> {
         &mut batch,
         &mut embedder,
         &mut vector,
+        &mut cache,
         &progress,
         &mut embedded_counts,
         &mut embedded_total,
+        &mut cached_chunks,
     )?;
 
     vector.save()?;
+    let sessions_updated =
+        update_session_vectors(&index, &mut vector, &crate::vector::sessions_dir(&store))?;
     progress.finish();
     println!(
-        "embedded {} vectors (claude {}, codex {}, history {}, opencode {})",
+        "embedded {} vectors (claude {}, codex {}, history {}, opencode {}), updated {} sessions",
         embedded_total,
         embedded_counts[crate::types::SourceKind::Claude.idx()],
         embedded_counts[crate::types::SourceKind::CodexSession.idx()],
         embedded_counts[crate::types::SourceKind::CodexHistory.idx()],
         embedded_counts[crate::types::SourceKind::Opencode.idx()],
+        sessions_updated,
     );
+    if cached_chunks > 0 {
+        println!("reused {cached_chunks} cached embeddings");
+    }
 
     std::io::stdout().flush().ok();
     std::process::exit(0);
@@ -628,6 +1568,15 @@ fn run_search(
     source: Option<SourceFilter>,
     semantic: bool,
     hybrid: bool,
+    sessions: bool,
+    fuzzy: Option<u8>,
+    fusion: Option<FusionStrategy>,
+    bm25_weight: Option<f32>,
+    vector_weight: Option<f32>,
+    explain: bool,
+    rerank: bool,
+    no_rerank: bool,
+    regex: bool,
     min_score: Option<f32>,
     recency_weight: f32,
     recency_half_life_days: f32,
@@ -638,22 +1587,50 @@ fn run_search(
     unique_session: bool,
     json_array: bool,
     fields: Option<String>,
+    snippet_chars: usize,
+    semantic_snippets: bool,
     sort: SortBy,
+    with_pair: bool,
+    facets: bool,
     verbose: bool,
     root: Option<PathBuf>,
 ) -> Result<()> {
     let paths = Paths::new(root)?;
     let config = UserConfig::load(&paths)?;
+    if with_pair {
+        note_if_predates_tool_details(&paths, "tool call ids");
+    }
     let model_choice = config.resolve_model(None)?;
+    let models = config.model_cache()?;
     let auto_index_on_search = config.auto_index_on_search_default();
     let embeddings_default = config.embeddings_default();
     let scan_cache_ttl = config.scan_cache_ttl();
+    let fusion = Fusion {
+        strategy: match fusion {
+            Some(strategy) => strategy,
+            None => config.fusion_strategy()?,
+        },
+        bm25_weight: bm25_weight.unwrap_or_else(|| config.fusion_bm25_weight()),
+        vector_weight: vector_weight.unwrap_or_else(|| config.fusion_vector_weight()),
+        rrf_k: config.fusion_rrf_k(),
+    };
+    let rerank = resolve_flag(config.rerank_default(), rerank, no_rerank, "rerank")?;
+    let rerank = if hybrid && rerank {
+        Some(Reranking {
+            model: config.resolve_reranker()?,
+            candidates: config.rerank_candidates(),
+        })
+    } else {
+        None
+    };
     if auto_index_on_search {
         paths.ensure_dirs()?;
         let index = SearchIndex::open_or_create(&paths.index)?;
-        let vector_exists = paths.vectors.join("meta.json").exists()
-            && paths.vectors.join("vectors.f32").exists()
-            && paths.vectors.join("doc_ids.u64").exists();
+        crate::vector::migrate_legacy_store(&paths.vectors, &model_choice)?;
+        let vector_exists = VectorIndex::is_current(
+            &crate::vector::model_store(&paths.vectors, &model_choice),
+            model_choice.template_version(),
+        )?;
         let backfill_embeddings = embeddings_default && !vector_exists && index.doc_count()? > 0;
         let opts = IngestOptions {
             claude_source: default_claude_source(),
@@ -662,14 +1639,16 @@ fn run_search(
             include_opencode: true,
             embeddings: embeddings_default,
             backfill_embeddings,
-            model: model_choice,
+            prune_embed_cache: false,
+            model: model_choice.clone(),
+            models: config.model_cache()?,
         };
         // Skip indexing if we recently scanned (within TTL)
         let _ = ingest_if_stale(&paths, &index, &opts, scan_cache_ttl)?;
     }
     let index = SearchIndex::open_or_create(&paths.index)?;
 
-    let options = QueryOptions {
+    let mut options = QueryOptions {
         query,
         project,
         role,
@@ -678,24 +1657,48 @@ fn run_search(
         source,
         since: parse_ts_millis(since)?,
         until: parse_ts_millis(until)?,
+        fuzzy,
         limit,
     };
-    let matchers = build_matchers(&options.query)?;
+    let pattern = if regex {
+        let pattern =
+            Regex::new(&options.query).map_err(|err| anyhow!("invalid --regex pattern: {err}"))?;
+        Some(pattern)
+    } else {
+        options.extract_filters();
+        None
+    };
+    let highlighter = match &pattern {
+        Some(pattern) => Some(Highlighter::Regex(pattern.clone())),
+        None => Highlighter::for_query(&index, &options, snippet_chars)?,
+    };
+    let facets = if facets {
+        Some(index.facets(&options, FACET_SIZE)?)
+    } else {
+        None
+    };
     let fields = parse_fields(fields)?;
     let top_n_per_session = if unique_session && top_n_per_session.is_none() {
         Some(1)
     } else {
         top_n_per_session
     };
-    let render = RenderOptions {
+    let mut render = RenderOptions {
         verbose,
-        matchers,
+        highlighter: highlighter.map(std::sync::Arc::new),
+        snippet_chars,
+        semantic_snippets,
+        windows: HashMap::new(),
+        rerank_scores: HashMap::new(),
+        explain: explain.then(HashMap::new),
         json_array: json_array && !verbose,
         fields,
         sort,
         min_score,
         top_n_per_session,
         limit,
+        with_pair,
+        facets,
     };
 
     let candidate_limit = if top_n_per_session.is_some() || options.source.is_some() {
@@ -704,6 +1707,22 @@ fn run_search(
         limit
     };
 
+    if sessions {
+        return run_session_search(
+            &index,
+            &options,
+            &SearchContext {
+                render: &render,
+                paths: &paths,
+                model_choice: &model_choice,
+                models: models.clone(),
+                recency_weight,
+                recency_half_life_days,
+                fusion,
+                rerank: None,
+            },
+        );
+    }
     if hybrid {
         return run_hybrid_search(
             &index,
@@ -712,9 +1731,12 @@ fn run_search(
             &SearchContext {
                 render: &render,
                 paths: &paths,
-                model_choice,
+                model_choice: &model_choice,
+                models: models.clone(),
                 recency_weight,
                 recency_half_life_days,
+                fusion,
+                rerank,
             },
         );
     }
@@ -726,45 +1748,119 @@ fn run_search(
             &SearchContext {
                 render: &render,
                 paths: &paths,
-                model_choice,
+                model_choice: &model_choice,
+                models: models.clone(),
                 recency_weight,
                 recency_half_life_days,
+                fusion,
+                rerank: None,
             },
         );
     }
-    let results = index.search(&options)?;
+    let results = if let Some(pattern) = &pattern {
+        index.regex_search(pattern, &options)?
+    } else {
+        index.search(&options)?
+    };
     let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+    if let Some(explain) = &mut render.explain {
+        for (rank, (score, record)) in results.iter().enumerate() {
+            let bm25 = Ranked {
+                doc_id: record.doc_id,
+                rank: rank + 1,
+                score: *score,
+            };
+            explain.insert(
+                record.doc_id,
+                Explain {
+                    bm25: Some(bm25),
+                    ..explained(
+                        *score,
+                        record.ts,
+                        now_ms,
+                        recency_weight,
+                        recency_half_life_days,
+                    )
+                },
+            );
+        }
+    }
     let mut reranked =
         apply_recency_to_results(results, now_ms, recency_weight, recency_half_life_days);
     reranked.retain(|(_, record)| matches_filters(record, &options));
     let reranked = apply_post_processing(reranked, &render);
-    render_results(reranked, &render)?;
+    render_results(&index, reranked, &render)?;
     Ok(())
 }
 
 struct SearchContext<'a> {
     render: &'a RenderOptions,
     paths: &'a Paths,
-    model_choice: ModelChoice,
+    model_choice: &'a ModelChoice,
+    models: ModelCache,
     recency_weight: f32,
     recency_half_life_days: f32,
+    fusion: Fusion,
+    rerank: Option<Reranking>,
+}
+
+impl SearchContext<'_> {
+    /// The vector store of the model searches embed queries with. Vectors
+    /// embedded with an older document prompt would not match its queries.
+    fn vector_store(&self) -> Result<PathBuf> {
+        let store = crate::vector::model_store(&self.paths.vectors, self.model_choice);
+        if VectorIndex::exists(&store)
+            && !VectorIndex::is_current(&store, self.model_choice.template_version())?
+        {
+            return Err(anyhow!(
+                "vectors in {} were embedded with an older {} prompt; run `memex embed` to update them",
+                store.display(),
+                self.model_choice.id()
+            ));
+        }
+        Ok(store)
+    }
+}
+
+/// The vector store of the model in config.toml (or `MEMEX_MODEL`), for
+/// commands that read stored vectors without embedding anything.
+fn configured_store(paths: &Paths) -> Result<PathBuf> {
+    let model = UserConfig::load(paths)?.resolve_model(None)?;
+    Ok(crate::vector::model_store(&paths.vectors, &model))
+}
+
+/// Cross-encoder stage applied to fused hybrid results.
+struct Reranking {
+    model: RerankerChoice,
+    /// Fused candidates to rescore; the rest are dropped.
+    candidates: usize,
 }
 
+/// Bytes of a record's text the reranker sees.
+const RERANK_TEXT_BYTES: usize = 2000;
+
 fn run_semantic_search(
     index: &SearchIndex,
     options: &QueryOptions,
     limit: usize,
     ctx: &SearchContext,
 ) -> Result<()> {
-    let vector = VectorIndex::open(&ctx.paths.vectors)?;
-    let mut embedder = EmbedderHandle::with_model(ctx.model_choice)?;
-    let embeddings = embedder.embed_texts(&[options.query.as_str()])?;
+    let vector = VectorIndex::open(&ctx.vector_store()?)?;
+    let mut embedder = EmbedderHandle::with_model(ctx.model_choice, &ctx.models)?;
+    let embeddings = embedder.embed_queries(&[plain_query_text(&options.query).as_str()])?;
     let embedding = embeddings
         .first()
         .ok_or_else(|| anyhow!("embedding missing"))?;
     let mut results = Vec::new();
+    let mut render = ctx.render.clone();
     let now_ms = chrono::Utc::now().timestamp_millis() as u64;
-    for (doc_id, distance) in vector.search(embedding, limit)? {
+    let nearest = nearest_vectors(index, &vector, embedding, options, limit)?;
+    let mut spans = HashMap::new();
+    for (rank, (chunk, distance)) in nearest.into_iter().enumerate() {
+        let doc_id = chunk.doc_id;
+        if !chunk.span.is_empty() {
+            spans.insert(doc_id, chunk.span);
+        }
         if let Some(record) = index.get_by_doc_id(doc_id)?
             && matches_filters(&record, options)
         {
@@ -776,102 +1872,517 @@ fn run_semantic_search(
                 ctx.recency_weight,
                 ctx.recency_half_life_days,
             );
+            if let Some(explain) = &mut render.explain {
+                explain.insert(
+                    doc_id,
+                    Explain {
+                        vector: Some(VectorRank {
+                            rank: rank + 1,
+                            distance,
+                        }),
+                        ..explained(
+                            base,
+                            record.ts,
+                            now_ms,
+                            ctx.recency_weight,
+                            ctx.recency_half_life_days,
+                        )
+                    },
+                );
+            }
             results.push((score, record));
         }
     }
-    let results = apply_post_processing(results, ctx.render);
-    render_results(results, ctx.render)?;
+    let results = apply_post_processing(results, &render);
+    let render = with_semantic_windows(&mut embedder, embedding, &results, &spans, &render, false)?;
+    render_results(index, results, &render)?;
     Ok(())
 }
 
-fn run_hybrid_search(
+/// `search --sessions`: sessions ranked by their mean embedding's similarity
+/// to the query.
+fn run_session_search(
     index: &SearchIndex,
     options: &QueryOptions,
-    limit: usize,
     ctx: &SearchContext,
 ) -> Result<()> {
-    let vector = VectorIndex::open(&ctx.paths.vectors)?;
-    let mut embedder = EmbedderHandle::with_model(ctx.model_choice)?;
-
-    let bm25_k = (limit * 5).clamp(50, 500);
-    let vector_k = (limit * 5).clamp(50, 500);
+    let sessions = SessionVectors::open(&crate::vector::sessions_dir(&ctx.vector_store()?))?;
+    let mut embedder = EmbedderHandle::with_model(ctx.model_choice, &ctx.models)?;
+    let embeddings = embedder.embed_queries(&[plain_query_text(&options.query).as_str()])?;
+    let embedding = embeddings
+        .first()
+        .ok_or_else(|| anyhow!("embedding missing"))?;
+    let hits = rank_sessions(
+        index,
+        &sessions,
+        embedding,
+        options,
+        None,
+        (ctx.recency_weight, ctx.recency_half_life_days),
+        ctx.render,
+    )?;
+    render_sessions(hits, ctx.render)
+}
 
-    let bm25_results = index.search(&QueryOptions {
-        limit: bm25_k,
-        ..options.clone()
+fn run_related(
+    session_id: &str,
+    options: &QueryOptions,
+    render: &RenderOptions,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    if index.records_by_session_id(session_id)?.is_empty() {
+        return Err(anyhow!("session {session_id} not found"));
+    }
+    let sessions = SessionVectors::open(&crate::vector::sessions_dir(&configured_store(&paths)?))?;
+    let embedding = sessions.get(session_id)?.ok_or_else(|| {
+        anyhow!("session {session_id} has no embedding; run `memex embed` to add it")
     })?;
+    let hits = rank_sessions(
+        &index,
+        &sessions,
+        &embedding,
+        options,
+        Some(session_id),
+        (0.0, 0.0),
+        render,
+    )?;
+    render_sessions(hits, render)
+}
 
-    let embeddings = embedder.embed_texts(&[options.query.as_str()])?;
-    let embedding = embeddings
-        .first()
-        .ok_or_else(|| anyhow!("embedding missing"))?;
-    let vector_results = vector.search(embedding, vector_k)?;
+/// Characters of a session's first prompt shown as its title.
+const SESSION_TITLE_CHARS: usize = 200;
 
-    let mut records: HashMap<u64, crate::types::Record> = HashMap::new();
-    let mut scores: HashMap<u64, f32> = HashMap::new();
-    let rrf_k = 60.0;
+/// A session ranked as a unit, by `search --sessions` and `memex related`.
+#[derive(Serialize)]
+struct SessionHit {
+    score: f32,
+    session_id: String,
+    project: String,
+    source: String,
+    start: String,
+    end: String,
+    records: usize,
+    prompts: usize,
+    /// The session's first user prompt.
+    title: String,
+    source_path: String,
+    #[serde(skip)]
+    end_ms: u64,
+}
+
+/// Sessions nearest `embedding` with at least one record passing the
+/// filters, scored by similarity and the recency of their last record.
+fn rank_sessions(
+    index: &SearchIndex,
+    sessions: &SessionVectors,
+    embedding: &[f32],
+    options: &QueryOptions,
+    exclude: Option<&str>,
+    (recency_weight, half_life_days): (f32, f32),
+    render: &RenderOptions,
+) -> Result<Vec<SessionHit>> {
+    let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+    let candidates = (render.limit * 5).max(50);
+    let mut hits = Vec::new();
+    for (session_id, distance) in sessions.search(embedding, candidates)? {
+        if exclude == Some(session_id.as_str()) {
+            continue;
+        }
+        let mut records = index.records_by_session_id(&session_id)?;
+        if !records
+            .iter()
+            .any(|record| matches_filters(record, options))
+        {
+            continue;
+        }
+        records.sort_by_key(|record| record.ts);
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            continue;
+        };
+        let score = score_from_distance(distance)
+            * recency_multiplier(last.ts, now_ms, recency_weight, half_life_days);
+        let prompts: Vec<&Record> = records
+            .iter()
+            .filter(|record| record.role == "user")
+            .collect();
+        hits.push(SessionHit {
+            score,
+            project: first.project.clone(),
+            source: first.source.label().to_string(),
+            start: format_ts(first.ts),
+            end: format_ts(last.ts),
+            records: records.len(),
+            title: prompts
+                .first()
+                .map(|record| summarize(&record.text, render.snippet_chars))
+                .unwrap_or_default(),
+            prompts: prompts.len(),
+            source_path: first.source_path.clone(),
+            end_ms: last.ts,
+            session_id,
+        });
+    }
+
+    if let Some(min_score) = render.min_score {
+        hits.retain(|hit| hit.score >= min_score);
+    }
+    match render.sort {
+        SortBy::Score => hits.sort_by(|a, b| b.score.total_cmp(&a.score)),
+        SortBy::Ts => hits.sort_by_key(|hit| std::cmp::Reverse(hit.end_ms)),
+    }
+    hits.truncate(render.limit);
+    Ok(hits)
+}
 
-    for (rank, (_, record)) in bm25_results.into_iter().enumerate() {
+fn render_sessions(hits: Vec<SessionHit>, render: &RenderOptions) -> Result<()> {
+    if render.verbose {
+        for hit in hits {
+            println!(
+                "[{:.3}] {} {} {} {} records {}",
+                hit.score,
+                hit.end,
+                hit.session_id,
+                hit.project,
+                hit.records,
+                summarize(&hit.title, 120)
+            );
+        }
+        return Ok(());
+    }
+    let mut output = Vec::new();
+    for hit in hits {
+        let mut value = serde_json::to_value(hit)?;
+        if let (Some(fields), Value::Object(map)) = (&render.fields, &mut value) {
+            map.retain(|key, _| fields.contains(key));
+        }
+        if render.json_array {
+            output.push(value);
+        } else {
+            println!("{}", serde_json::to_string(&value)?);
+        }
+    }
+    if render.json_array {
+        println!("{}", serde_json::to_string(&output)?);
+    }
+    Ok(())
+}
+
+/// Nearest messages to `embedding` among the records passing `options`'
+/// filters, so filtered queries still fill `limit`, each with its closest
+/// chunk.
+fn nearest_vectors(
+    index: &SearchIndex,
+    vector: &VectorIndex,
+    embedding: &[f32],
+    options: &QueryOptions,
+    limit: usize,
+) -> Result<Vec<(Chunk, f32)>> {
+    match index.filtered_doc_ids(options)? {
+        Some(allowed) => vector.search_within(embedding, limit, &allowed),
+        None => vector.search(embedding, limit),
+    }
+}
+
+fn run_hybrid_search(
+    index: &SearchIndex,
+    options: &QueryOptions,
+    limit: usize,
+    ctx: &SearchContext,
+) -> Result<()> {
+    let bm25_k = (limit * 5).clamp(50, 500);
+    let vector_k = (limit * 5).clamp(50, 500);
+    // A quoted phrase asks for exactly those words; vectors would only dilute it.
+    let quoted = fusion::is_quoted(&options.query);
+    let fusion = if quoted {
+        Fusion {
+            strategy: FusionStrategy::Bm25,
+            ..ctx.fusion
+        }
+    } else {
+        ctx.fusion
+    };
+
+    let mut records: HashMap<u64, crate::types::Record> = HashMap::new();
+    let mut bm25 = Vec::new();
+    let bm25_results = index.search(&QueryOptions {
+        limit: bm25_k,
+        ..options.clone()
+    })?;
+    for (rank, (score, record)) in bm25_results.into_iter().enumerate() {
         if !matches_filters(&record, options) {
             continue;
         }
-        let r = rank as f32 + 1.0;
-        scores
-            .entry(record.doc_id)
-            .and_modify(|v| *v += 1.0 / (rrf_k + r))
-            .or_insert(1.0 / (rrf_k + r));
+        bm25.push(Ranked {
+            doc_id: record.doc_id,
+            rank: rank + 1,
+            score,
+        });
         records.insert(record.doc_id, record);
     }
 
-    for (rank, (doc_id, _distance)) in vector_results.into_iter().enumerate() {
-        if let Some(record) = index.get_by_doc_id(doc_id)? {
-            if !matches_filters(&record, options) {
-                continue;
+    let mut semantic = None;
+    let mut nearest = Vec::new();
+    let mut distances: HashMap<u64, f32> = HashMap::new();
+    let mut spans = HashMap::new();
+    if fusion.strategy != FusionStrategy::Bm25 {
+        let vector = VectorIndex::open(&ctx.vector_store()?)?;
+        let mut embedder = EmbedderHandle::with_model(ctx.model_choice, &ctx.models)?;
+        let embedding = embedder
+            .embed_queries(&[plain_query_text(&options.query).as_str()])?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("embedding missing"))?;
+        let vector_results = nearest_vectors(index, &vector, &embedding, options, vector_k)?;
+        for (rank, (chunk, distance)) in vector_results.into_iter().enumerate() {
+            let doc_id = chunk.doc_id;
+            if let std::collections::hash_map::Entry::Vacant(entry) = records.entry(doc_id) {
+                let Some(record) = index.get_by_doc_id(doc_id)? else {
+                    continue;
+                };
+                if !matches_filters(&record, options) {
+                    continue;
+                }
+                entry.insert(record);
+            }
+            nearest.push(Ranked {
+                doc_id,
+                rank: rank + 1,
+                score: score_from_distance(distance),
+            });
+            distances.insert(doc_id, distance);
+            if !chunk.span.is_empty() {
+                spans.insert(doc_id, chunk.span);
             }
-            let r = rank as f32 + 1.0;
-            scores
-                .entry(doc_id)
-                .and_modify(|v| *v += 1.0 / (rrf_k + r))
-                .or_insert(1.0 / (rrf_k + r));
-            records.entry(doc_id).or_insert(record);
         }
+        semantic = Some((embedder, embedding));
     }
 
     let now_ms = chrono::Utc::now().timestamp_millis() as u64;
-    let merged: Vec<(f32, crate::types::Record)> = scores
+    let mut render = ctx.render.clone();
+    let merged: Vec<(f32, crate::types::Record)> = fusion
+        .fuse(&bm25, &nearest)
         .into_iter()
-        .filter_map(|(doc_id, score)| {
-            records.remove(&doc_id).map(|r| {
-                (
-                    apply_recency(
-                        score,
-                        r.ts,
-                        now_ms,
-                        ctx.recency_weight,
-                        ctx.recency_half_life_days,
-                    ),
-                    r,
-                )
-            })
+        .filter_map(|hit| {
+            let record = records.remove(&hit.doc_id)?;
+            if let Some(explain) = &mut render.explain {
+                let vector = hit.vector.map(|ranked| VectorRank {
+                    rank: ranked.rank,
+                    distance: distances[&hit.doc_id],
+                });
+                explain.insert(
+                    hit.doc_id,
+                    Explain {
+                        strategy: Some(fusion.strategy),
+                        bm25: hit.bm25,
+                        vector,
+                        ..explained(
+                            hit.score,
+                            record.ts,
+                            now_ms,
+                            ctx.recency_weight,
+                            ctx.recency_half_life_days,
+                        )
+                    },
+                );
+            }
+            let score = apply_recency(
+                hit.score,
+                record.ts,
+                now_ms,
+                ctx.recency_weight,
+                ctx.recency_half_life_days,
+            );
+            Some((score, record))
         })
         .collect();
-    let merged = apply_post_processing(merged, ctx.render);
-    render_results(merged, ctx.render)?;
+    let merged = match &ctx.rerank {
+        Some(rerank) => rerank_results(
+            &options.query,
+            merged,
+            rerank,
+            limit,
+            now_ms,
+            ctx,
+            &mut render,
+        )?,
+        None => merged,
+    };
+    let merged = apply_post_processing(merged, &render);
+    let render = match &mut semantic {
+        Some((embedder, embedding)) => {
+            with_semantic_windows(embedder, embedding, &merged, &spans, &render, true)?
+        }
+        None => render,
+    };
+    render_results(index, merged, &render)?;
     Ok(())
 }
 
+/// Rescore the best fused `results` with a cross-encoder. A hit's score becomes
+/// its sigmoid relevance with recency applied; the raw relevance is recorded in
+/// `render` for output.
+fn rerank_results(
+    query: &str,
+    mut results: Vec<(f32, Record)>,
+    rerank: &Reranking,
+    limit: usize,
+    now_ms: u64,
+    ctx: &SearchContext,
+    render: &mut RenderOptions,
+) -> Result<Vec<(f32, Record)>> {
+    results.sort_by(|a, b| b.0.total_cmp(&a.0));
+    results.truncate(rerank.candidates.max(limit));
+    let mut reranker = RerankerHandle::with_model(rerank.model, &ctx.models)?;
+    let texts: Vec<&str> = results
+        .iter()
+        .map(|(_, record)| text_prefix(&record.text, RERANK_TEXT_BYTES))
+        .collect();
+    let relevance = reranker.score(&plain_query_text(query), &texts)?;
+
+    let results = results
+        .into_iter()
+        .zip(relevance)
+        .map(|((_, record), logit)| {
+            let relevance = 1.0 / (1.0 + (-logit).exp());
+            render.rerank_scores.insert(record.doc_id, relevance);
+            let score = apply_recency(
+                relevance,
+                record.ts,
+                now_ms,
+                ctx.recency_weight,
+                ctx.recency_half_life_days,
+            );
+            (score, record)
+        })
+        .collect();
+    Ok(results)
+}
+
+/// The explanation for a hit scored `base` before recency. The caller fills
+/// in the components; the final score is filled in at render time.
+fn explained(
+    base: f32,
+    ts: u64,
+    now_ms: u64,
+    recency_weight: f32,
+    recency_half_life_days: f32,
+) -> Explain {
+    Explain {
+        base,
+        recency: recency_multiplier(ts, now_ms, recency_weight, recency_half_life_days),
+        ..Explain::default()
+    }
+}
+
+/// `render` with a snippet window per hit, inside the hit's closest chunk in
+/// `spans` when it has one: that chunk's first sentence window, or with
+/// `--semantic-snippets` the window whose embedding is closest to the
+/// query's. Embedded windows are capped per search; hits past the cap keep
+/// the chunk's first window. With `lexical_first`, hits the query terms
+/// highlight keep their lexical snippet.
+fn with_semantic_windows(
+    embedder: &mut EmbedderHandle,
+    query: &[f32],
+    results: &[(f32, Record)],
+    spans: &HashMap<u64, std::ops::Range<usize>>,
+    render: &RenderOptions,
+    lexical_first: bool,
+) -> Result<RenderOptions> {
+    const MAX_WINDOWS: usize = 32;
+
+    let mut render = render.clone();
+    if !render.verbose && !wants_field(&render.fields, "snippet") {
+        return Ok(render);
+    }
+    let mut budget = if render.semantic_snippets {
+        MAX_WINDOWS
+    } else {
+        0
+    };
+    let mut owners = Vec::new();
+    let mut windows = Vec::new();
+    for (_, record) in results {
+        if lexical_first
+            && render.highlighter.as_ref().is_some_and(|highlighter| {
+                !highlighter
+                    .highlight(&record.text, render.snippet_chars)
+                    .matches
+                    .is_empty()
+            })
+        {
+            continue;
+        }
+        let span = spans
+            .get(&record.doc_id)
+            .filter(|span| record.text.get((*span).clone()).is_some())
+            .cloned()
+            .unwrap_or(0..record.text.len());
+        let candidates: Vec<std::ops::Range<usize>> =
+            sentence_windows(&record.text[span.clone()], render.snippet_chars)
+                .into_iter()
+                .map(|window| window.start + span.start..window.end + span.start)
+                .collect();
+        if candidates.len() < 2 || candidates.len() > budget {
+            if let Some(window) = candidates.first()
+                && span.start > 0
+            {
+                render.windows.insert(record.doc_id, window.clone());
+            }
+            continue;
+        }
+        budget -= candidates.len();
+        for window in candidates {
+            owners.push(record);
+            windows.push(window);
+        }
+    }
+    if windows.is_empty() {
+        return Ok(render);
+    }
+
+    let texts: Vec<&str> = owners
+        .iter()
+        .zip(&windows)
+        .map(|(record, window)| &record.text[window.clone()])
+        .collect();
+    let embeddings = embedder.embed_documents(&texts)?;
+    let mut start = 0;
+    while start < owners.len() {
+        let doc_id = owners[start].doc_id;
+        let end = owners[start..]
+            .iter()
+            .position(|record| record.doc_id != doc_id)
+            .map_or(owners.len(), |n| start + n);
+        if let Some(best) = best_match(query, &embeddings[start..end]) {
+            render.windows.insert(doc_id, windows[start + best].clone());
+        }
+        start = end;
+    }
+    Ok(render)
+}
+
 fn score_from_distance(distance: f32) -> f32 {
     1.0 / (1.0 + distance)
 }
 
 fn apply_recency(score: f32, ts: u64, now_ms: u64, weight: f32, half_life_days: f32) -> f32 {
-    if score <= 0.0 || weight <= 0.0 || half_life_days <= 0.0 || ts == 0 {
+    if score <= 0.0 {
         return score;
     }
+    score * recency_multiplier(ts, now_ms, weight, half_life_days)
+}
+
+/// The factor `apply_recency` scales a positive score by.
+fn recency_multiplier(ts: u64, now_ms: u64, weight: f32, half_life_days: f32) -> f32 {
+    if weight <= 0.0 || half_life_days <= 0.0 || ts == 0 {
+        return 1.0;
+    }
     let age_ms = now_ms.saturating_sub(ts);
     let age_days = age_ms as f32 / (1000.0 * 60.0 * 60.0 * 24.0);
     let decay = (-std::f32::consts::LN_2 * age_days / half_life_days).exp();
-    score * (1.0 + weight * decay)
+    1.0 + weight * decay
 }
 
 fn matches_filters(record: &crate::types::Record, options: &QueryOptions) -> bool {
@@ -916,13 +2427,51 @@ fn matches_filters(record: &crate::types::Record, options: &QueryOptions) -> boo
 #[derive(Clone)]
 struct RenderOptions {
     verbose: bool,
-    matchers: Vec<regex::Regex>,
+    highlighter: Option<std::sync::Arc<Highlighter>>,
+    snippet_chars: usize,
+    /// Choose snippet windows by embedding each hit's sentences.
+    semantic_snippets: bool,
+    /// Snippet windows chosen by vector similarity, by doc id.
+    windows: HashMap<u64, std::ops::Range<usize>>,
+    /// Cross-encoder relevance by doc id, when results were reranked.
+    rerank_scores: HashMap<u64, f32>,
+    /// Score breakdowns by doc id, with --explain.
+    explain: Option<HashMap<u64, Explain>>,
     json_array: bool,
     fields: Option<HashSet<String>>,
     sort: SortBy,
     min_score: Option<f32>,
     top_n_per_session: Option<usize>,
     limit: usize,
+    with_pair: bool,
+    /// Hit counts to print after the results.
+    facets: Option<Facets>,
+}
+
+impl RenderOptions {
+    /// The score breakdown for a hit scored `score`, with --explain.
+    fn explain(&self, score: f32, record: &Record) -> Option<Explain> {
+        let explain = self.explain.as_ref()?;
+        Some(Explain {
+            rerank: self.rerank_scores.get(&record.doc_id).copied(),
+            score,
+            ..explain.get(&record.doc_id).cloned().unwrap_or_default()
+        })
+    }
+
+    /// Highlights for a hit; a window chosen by embedding similarity wins over
+    /// the query's.
+    fn highlights(&self, record: &Record) -> Highlights {
+        let mut highlights = self
+            .highlighter
+            .as_ref()
+            .map(|highlighter| highlighter.highlight(&record.text, self.snippet_chars))
+            .unwrap_or_default();
+        if let Some(window) = self.windows.get(&record.doc_id) {
+            highlights.window = Some(window.clone());
+        }
+        highlights
+    }
 }
 
 #[derive(Serialize)]
@@ -946,38 +2495,77 @@ struct SearchHit {
     text: String,
     snippet: String,
     matches: Vec<MatchSpan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rerank_score: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    explain: Option<Explain>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pair: Option<Record>,
 }
 
-fn render_results(results: Vec<(f32, crate::types::Record)>, render: &RenderOptions) -> Result<()> {
+fn render_results(
+    index: &SearchIndex,
+    results: Vec<(f32, crate::types::Record)>,
+    render: &RenderOptions,
+) -> Result<()> {
     if render.verbose {
         for (score, record) in results {
             let ts = format_ts(record.ts);
-            let text = summarize(&record.text, 200);
+            let text = snippet_text(&record.text, render.highlights(&record).window, 200);
             println!(
                 "[{score:.3}] {} {} {} {} {} {}",
                 ts, record.doc_id, record.project, record.role, record.session_id, text
             );
+            if let Some(explain) = render.explain(score, &record) {
+                println!("    explain {}", serde_json::to_string(&explain)?);
+            }
+            if render.with_pair
+                && let Some(pair) = index.paired_record(&record)?
+            {
+                println!(
+                    "    {} {} {}",
+                    pair.doc_id,
+                    pair.role,
+                    summarize(&pair.text, 200)
+                );
+            }
+        }
+        if let Some(facets) = &render.facets {
+            println!();
+            print_facets(facets, 5);
         }
         return Ok(());
     }
 
     let mut output = Vec::new();
     for (score, record) in results {
+        let pair = if render.with_pair {
+            index.paired_record(&record)?
+        } else {
+            None
+        };
         let ts = format_ts(record.ts);
         let text_ref = record.text.as_str();
         let wants_snippet = wants_field(&render.fields, "snippet");
         let wants_matches = wants_field(&render.fields, "matches");
         let wants_text = wants_field(&render.fields, "text");
+        let highlights = if wants_snippet || wants_matches {
+            render.highlights(&record)
+        } else {
+            Highlights::default()
+        };
         let snippet = if wants_snippet {
-            summarize(text_ref, 400)
+            snippet_text(text_ref, highlights.window, render.snippet_chars)
         } else {
             String::new()
         };
         let matches = if wants_matches {
-            collect_matches(text_ref, &render.matchers, 8)
+            match_spans(text_ref, &highlights.matches, 8)
         } else {
             Vec::new()
         };
+        let rerank_score = render.rerank_scores.get(&record.doc_id).copied();
+        let explain = render.explain(score, &record);
         let text = if wants_text {
             record.text
         } else {
@@ -1016,6 +2604,17 @@ fn render_results(results: Vec<(f32, crate::types::Record)>, render: &RenderOpti
             if fields.contains("matches") {
                 map.insert("matches".to_string(), serde_json::to_value(matches)?);
             }
+            if fields.contains("rerank_score")
+                && let Some(rerank_score) = rerank_score
+            {
+                map.insert("rerank_score".to_string(), Value::from(rerank_score));
+            }
+            if let Some(explain) = explain {
+                map.insert("explain".to_string(), serde_json::to_value(explain)?);
+            }
+            if let Some(pair) = pair {
+                map.insert("pair".to_string(), serde_json::to_value(pair)?);
+            }
             Value::Object(map)
         } else {
             serde_json::to_value(SearchHit {
@@ -1029,100 +2628,1355 @@ fn render_results(results: Vec<(f32, crate::types::Record)>, render: &RenderOpti
                 text,
                 snippet,
                 matches,
+                rerank_score,
+                explain,
+                pair,
             })?
         };
-        if render.json_array {
-            output.push(value);
+        if render.json_array {
+            output.push(value);
+        } else {
+            println!("{}", serde_json::to_string(&value)?);
+        }
+    }
+
+    let facets = render
+        .facets
+        .as_ref()
+        .map(|facets| serde_json::json!({ "facets": facets }));
+    if render.json_array {
+        output.extend(facets);
+        println!("{}", serde_json::to_string(&output)?);
+    } else if let Some(facets) = facets {
+        println!("{}", serde_json::to_string(&facets)?);
+    }
+    Ok(())
+}
+
+/// Buckets per group `search --facets` reports.
+const FACET_SIZE: usize = 10;
+
+fn run_facets(
+    mut options: QueryOptions,
+    size: usize,
+    verbose: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    options.extract_filters();
+    let facets = index.facets(&options, size)?;
+    if verbose {
+        print_facets(&facets, size);
+    } else {
+        println!("{}", serde_json::to_string(&facets)?);
+    }
+    Ok(())
+}
+
+/// What `memex similar` finds records like.
+enum Seed {
+    Doc(u64),
+    Session(String),
+}
+
+/// Bytes of seed text `memex similar --text` draws terms from.
+const SIMILAR_TEXT_BYTES: usize = 20_000;
+
+fn run_similar(
+    seed: Seed,
+    text: bool,
+    include_seed_session: bool,
+    options: &QueryOptions,
+    render: &RenderOptions,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let results = similar_records(
+        &paths,
+        &index,
+        &seed,
+        text,
+        include_seed_session,
+        options,
+        render.limit,
+    )?;
+    let results = apply_post_processing(results, render);
+    render_results(&index, results, render)?;
+    Ok(())
+}
+
+/// Records like the seed, best first: its nearest vectors in the configured
+/// model's store, fused with the records sharing its distinctive terms when
+/// `text` is set or there are no vectors to search.
+fn similar_records(
+    paths: &Paths,
+    index: &SearchIndex,
+    seed: &Seed,
+    text: bool,
+    include_seed_session: bool,
+    options: &QueryOptions,
+    limit: usize,
+) -> Result<Vec<(f32, Record)>> {
+    let seeds = match seed {
+        Seed::Doc(doc_id) => vec![
+            index
+                .get_by_doc_id(*doc_id)?
+                .ok_or_else(|| anyhow!("doc {doc_id} not found"))?,
+        ],
+        Seed::Session(session_id) => {
+            let records = index.records_by_session_id(session_id)?;
+            if records.is_empty() {
+                return Err(anyhow!("session {session_id} not found"));
+            }
+            records
+        }
+    };
+    let seed_ids: HashSet<u64> = seeds.iter().map(|record| record.doc_id).collect();
+    let seed_sessions: HashSet<&str> = seeds.iter().map(|r| r.session_id.as_str()).collect();
+    let keep = |record: &Record| {
+        !seed_ids.contains(&record.doc_id)
+            && (include_seed_session || !seed_sessions.contains(record.session_id.as_str()))
+            && matches_filters(record, options)
+    };
+    let candidate_limit = (limit * 5).max(50) + seeds.len();
+
+    let mut records: HashMap<u64, Record> = HashMap::new();
+    let mut nearest = Vec::new();
+    // Without a store there is nothing to compare; a store that fails to
+    // load is an error, not a reason to quietly search by text.
+    let store = configured_store(paths)?;
+    let vector = if VectorIndex::exists(&store) {
+        Some(VectorIndex::open(&store)?)
+    } else {
+        None
+    };
+    let centroid = match &vector {
+        Some(vector) => seed_centroid(vector, &seeds)?,
+        None => None,
+    };
+    if let (Some(vector), Some(centroid)) = (&vector, &centroid) {
+        let neighbors = nearest_vectors(index, vector, centroid, options, candidate_limit)?;
+        for (rank, (chunk, distance)) in neighbors.into_iter().enumerate() {
+            let doc_id = chunk.doc_id;
+            let Some(record) = index.get_by_doc_id(doc_id)? else {
+                continue;
+            };
+            if !keep(&record) {
+                continue;
+            }
+            nearest.push(Ranked {
+                doc_id,
+                rank: rank + 1,
+                score: score_from_distance(distance),
+            });
+            records.insert(doc_id, record);
+        }
+    }
+
+    let mut shared = Vec::new();
+    if text || centroid.is_none() {
+        let seed_text = seeds
+            .iter()
+            .map(|record| record.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let results = index.more_like_this(
+            text_prefix(&seed_text, SIMILAR_TEXT_BYTES),
+            &QueryOptions {
+                limit: candidate_limit,
+                ..options.clone()
+            },
+        )?;
+        for (rank, (score, record)) in results.into_iter().enumerate() {
+            if !keep(&record) {
+                continue;
+            }
+            shared.push(Ranked {
+                doc_id: record.doc_id,
+                rank: rank + 1,
+                score,
+            });
+            records.insert(record.doc_id, record);
+        }
+    }
+
+    // A single ranking keeps its own scores; two are fused by rank.
+    let ranked: Vec<(u64, f32)> = if nearest.is_empty() || shared.is_empty() {
+        nearest
+            .into_iter()
+            .chain(shared)
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect()
+    } else {
+        Fusion::default()
+            .fuse(&shared, &nearest)
+            .into_iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect()
+    };
+    Ok(ranked
+        .into_iter()
+        .filter_map(|(doc_id, score)| Some((score, records.remove(&doc_id)?)))
+        .collect())
+}
+
+/// Mean of the seeds' unit-length embeddings, or `None` when none of them
+/// has one.
+fn seed_centroid(vector: &VectorIndex, seeds: &[Record]) -> Result<Option<Vec<f32>>> {
+    let mut sum: Option<Vec<f32>> = None;
+    for record in seeds {
+        let Some(embedding) = vector.get(record.doc_id)? else {
+            continue;
+        };
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let sum = sum.get_or_insert_with(|| vec![0.0; embedding.len()]);
+        for (total, x) in sum.iter_mut().zip(&embedding) {
+            *total += x / norm;
+        }
+    }
+    Ok(sum)
+}
+
+/// Default length of an activity report.
+const ACTIVITY_DAYS: u64 = 90;
+
+fn run_activity(
+    mut options: QueryOptions,
+    period: Period,
+    top: usize,
+    verbose: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    options.extract_filters();
+    if options.since.is_none() {
+        let now_ms = chrono::Utc::now().timestamp_millis() as u64;
+        options.since = Some(now_ms.saturating_sub(ACTIVITY_DAYS * 86_400_000));
+    }
+    let report = activity::report(index.activity_events(&options)?, period, top);
+    if verbose {
+        print!("{}", report.render());
+    } else {
+        println!("{}", serde_json::to_string(&report)?);
+    }
+    Ok(())
+}
+
+/// Bytes of each session's prompts and replies read for topic labels.
+const TOPIC_TEXT_BYTES: usize = 20_000;
+/// k-means rounds; clusters of session means settle well before this.
+const TOPIC_ITERATIONS: usize = 50;
+
+#[allow(clippy::too_many_arguments)]
+fn run_topics(
+    project: Option<String>,
+    source: Option<SourceFilter>,
+    since: Option<String>,
+    until: Option<String>,
+    clusters: Option<u64>,
+    terms: usize,
+    members: usize,
+    json_array: bool,
+    verbose: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let options = QueryOptions {
+        query: String::new(),
+        project,
+        role: None,
+        tool: None,
+        session_id: None,
+        source,
+        since: parse_ts_millis(since)?,
+        until: parse_ts_millis(until)?,
+        fuzzy: None,
+        limit: 0,
+    };
+    let topics = build_topics(&options, clusters.map(|n| n as usize), terms, members, root)?;
+    if verbose {
+        print!("{}", topics::render(&topics));
+    } else if json_array {
+        println!("{}", serde_json::to_string(&topics)?);
+    } else {
+        for topic in &topics {
+            println!("{}", serde_json::to_string(topic)?);
+        }
+    }
+    Ok(())
+}
+
+fn build_topics(
+    options: &QueryOptions,
+    clusters: Option<usize>,
+    terms: usize,
+    members: usize,
+    root: Option<PathBuf>,
+) -> Result<Vec<topics::Topic>> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let sessions = SessionVectors::open(&crate::vector::sessions_dir(&configured_store(&paths)?))?;
+    let mut session_ids: Vec<String> = index
+        .activity_events(options)?
+        .into_iter()
+        .map(|event| event.session_id)
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .collect();
+    session_ids.sort();
+
+    let mut ids = Vec::new();
+    let mut vectors = Vec::new();
+    for session_id in session_ids {
+        if let Some(mut vector) = sessions.get(&session_id)?
+            && topics::normalize(&mut vector)
+        {
+            ids.push(session_id);
+            vectors.push(vector);
+        }
+    }
+    if vectors.len() < 2 {
+        return Err(anyhow!(
+            "{} embedded sessions match; topics needs at least 2 (run `memex embed`?)",
+            vectors.len()
+        ));
+    }
+    let k = clusters.unwrap_or_else(|| topics::default_clusters(vectors.len()));
+    let (assignments, centers) = topics::kmeans(&vectors, k, TOPIC_ITERATIONS);
+
+    let mut groups: Vec<Vec<topics::TopicSession>> = vec![Vec::new(); centers.len()];
+    let mut counts: Vec<HashMap<String, u32>> = vec![HashMap::new(); centers.len()];
+    let mut spans: Vec<(u64, u64)> = vec![(u64::MAX, 0); centers.len()];
+    for ((session_id, vector), &cluster) in ids.into_iter().zip(&vectors).zip(&assignments) {
+        let mut records = index.records_by_session_id(&session_id)?;
+        records.sort_by_key(|record| record.ts);
+        let (Some(first), Some(last)) = (records.first(), records.last()) else {
+            continue;
+        };
+        let span = &mut spans[cluster];
+        *span = (span.0.min(first.ts), span.1.max(last.ts));
+        let mut read = 0;
+        for record in records
+            .iter()
+            .filter(|r| r.role == "user" || r.role == "assistant")
+        {
+            if read >= TOPIC_TEXT_BYTES {
+                break;
+            }
+            let text = text_prefix(&record.text, TOPIC_TEXT_BYTES - read);
+            read += text.len();
+            index.count_terms(text, &mut counts[cluster])?;
+        }
+        groups[cluster].push(topics::TopicSession {
+            project: first.project.clone(),
+            source: first.source.label().to_string(),
+            start: format_ts(first.ts),
+            end: format_ts(last.ts),
+            similarity: topics::dot(vector, &centers[cluster]).min(1.0),
+            title: records
+                .iter()
+                .find(|record| record.role == "user")
+                .map(|record| summarize(&record.text, SESSION_TITLE_CHARS))
+                .unwrap_or_default(),
+            session_id,
+        });
+    }
+
+    let mut topics = Vec::new();
+    for ((mut sessions, counts), (start, end)) in groups.into_iter().zip(counts).zip(spans) {
+        if sessions.is_empty() {
+            continue;
+        }
+        sessions.sort_by(|a, b| b.similarity.total_cmp(&a.similarity));
+        let size = sessions.len();
+        sessions.truncate(members);
+        topics.push(topics::Topic {
+            topic: 0,
+            terms: index.top_terms(&counts, terms)?,
+            size,
+            start: format_ts(start),
+            end: format_ts(end),
+            sessions,
+        });
+    }
+    topics.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| b.end.cmp(&a.end)));
+    for (i, topic) in topics.iter_mut().enumerate() {
+        topic.topic = i + 1;
+    }
+    Ok(topics)
+}
+
+/// Print each facet group on its own line, `limit` buckets per group and
+/// every month.
+fn print_facets(facets: &Facets, limit: usize) {
+    println!("hits: {}", facets.total);
+    let groups = [
+        ("project", &facets.project),
+        ("role", &facets.role),
+        ("tool", &facets.tool_name),
+        ("source", &facets.source),
+        ("session", &facets.session_id),
+        ("month", &facets.month),
+    ];
+    for (name, counts) in groups {
+        if counts.is_empty() {
+            continue;
+        }
+        let take = if name == "month" { counts.len() } else { limit };
+        let counts: Vec<String> = counts
+            .iter()
+            .take(take)
+            .map(|bucket| format!("{} ({})", bucket.value, bucket.count))
+            .collect();
+        println!("{name}: {}", counts.join(", "));
+    }
+}
+
+/// A record with its paired tool_use or tool_result.
+#[derive(Serialize)]
+struct PairedRecord<'a> {
+    #[serde(flatten)]
+    record: &'a Record,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pair: Option<&'a Record>,
+}
+
+fn run_session(
+    session_id: String,
+    with_pair: bool,
+    verbose: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let mut records = index.records_by_session_id(&session_id)?;
+    records.sort_by(|a, b| {
+        a.turn_id
+            .cmp(&b.turn_id)
+            .then_with(|| a.ts.cmp(&b.ts))
+            .then_with(|| a.doc_id.cmp(&b.doc_id))
+    });
+
+    // Results keyed by call id; each is rendered under its tool_use instead of on its own.
+    let mut results: HashMap<&str, &Record> = HashMap::new();
+    if with_pair {
+        note_if_predates_tool_details(&paths, "tool call ids");
+        let calls: HashSet<&str> = records
+            .iter()
+            .filter(|r| r.role == "tool_use")
+            .filter_map(|r| r.tool_call_id.as_deref())
+            .collect();
+        for record in &records {
+            if record.role == "tool_result"
+                && let Some(id) = record.tool_call_id.as_deref()
+                && calls.contains(id)
+            {
+                results.insert(id, record);
+            }
+        }
+    }
+    let pair_of = |record: &Record| -> Option<&Record> {
+        if record.role != "tool_use" {
+            return None;
+        }
+        record
+            .tool_call_id
+            .as_deref()
+            .and_then(|id| results.get(id).copied())
+    };
+    let is_paired_result = |record: &Record| {
+        record.role == "tool_result"
+            && record
+                .tool_call_id
+                .as_deref()
+                .is_some_and(|id| results.contains_key(id))
+    };
+
+    if verbose {
+        for record in &records {
+            if is_paired_result(record) {
+                continue;
+            }
+            print_session_record(record, "");
+            if let Some(result) = pair_of(record) {
+                print_session_record(result, "    ");
+            }
+        }
+        return Ok(());
+    }
+    for record in &records {
+        if is_paired_result(record) {
+            continue;
+        }
+        let line = PairedRecord {
+            record,
+            pair: pair_of(record),
+        };
+        println!("{}", serde_json::to_string(&line)?);
+    }
+    Ok(())
+}
+
+fn print_session_record(record: &Record, indent: &str) {
+    let ts = format_ts(record.ts);
+    println!("{indent}{ts} {}", record.role);
+    if record.text.is_empty() {
+        println!("{indent}  <empty>");
+        return;
+    }
+    for line in record.text.lines() {
+        println!("{indent}  {line}");
+    }
+}
+
+fn run_show(doc_id: u64, with_pair: bool, verbose: bool, root: Option<PathBuf>) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let record = index
+        .get_by_doc_id(doc_id)?
+        .ok_or_else(|| anyhow!("doc_id not found"))?;
+    if with_pair {
+        note_if_predates_tool_details(&paths, "tool call ids");
+    }
+    let pair = if with_pair {
+        index.paired_record(&record)?
+    } else {
+        None
+    };
+    let output = PairedRecord {
+        record: &record,
+        pair: pair.as_ref(),
+    };
+    if verbose {
+        println!("{}", serde_json::to_string_pretty(&output)?);
+        return Ok(());
+    }
+    println!("{}", serde_json::to_string(&output)?);
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct FileHit {
+    ts: String,
+    doc_id: u64,
+    session_id: String,
+    project: String,
+    source: &'static str,
+    turn_id: u32,
+    tool_name: Option<String>,
+    modified: bool,
+    files: Vec<String>,
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_files(
+    pattern: String,
+    modified_only: bool,
+    project: Option<String>,
+    source: Option<SourceFilter>,
+    since: Option<String>,
+    until: Option<String>,
+    limit: usize,
+    json_array: bool,
+    verbose: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let pattern = PathPattern::new(&pattern)?;
+    let options = QueryOptions {
+        query: String::new(),
+        project,
+        role: None,
+        tool: None,
+        session_id: None,
+        source,
+        since: parse_ts_millis(since)?,
+        until: parse_ts_millis(until)?,
+        fuzzy: None,
+        limit,
+    };
+    let records = index.records_by_files(|path| pattern.matches(path), modified_only, &options)?;
+
+    let mut hits = Vec::new();
+    for record in records {
+        let modified = is_modifying_call(record.tool_name.as_deref(), record.tool_input.as_deref());
+        let files = record
+            .files
+            .into_iter()
+            .filter(|path| pattern.matches(path))
+            .collect();
+        hits.push(FileHit {
+            ts: format_ts(record.ts),
+            doc_id: record.doc_id,
+            session_id: record.session_id,
+            project: record.project,
+            source: record.source.label(),
+            turn_id: record.turn_id,
+            tool_name: record.tool_name,
+            modified,
+            files,
+        });
+    }
+
+    if verbose {
+        let mut order: Vec<&str> = Vec::new();
+        let mut by_session: HashMap<&str, Vec<&FileHit>> = HashMap::new();
+        for hit in &hits {
+            let entry = by_session.entry(hit.session_id.as_str()).or_default();
+            if entry.is_empty() {
+                order.push(hit.session_id.as_str());
+            }
+            entry.push(hit);
+        }
+        for session_id in order {
+            let turns = &by_session[session_id];
+            let first = turns[0];
+            println!("{} {} ({})", session_id, first.project, first.source);
+            for hit in turns {
+                let action = if hit.modified { "modified" } else { "read" };
+                println!(
+                    "  {} turn {} {} {} {}",
+                    hit.ts,
+                    hit.turn_id,
+                    hit.tool_name.as_deref().unwrap_or("-"),
+                    action,
+                    hit.files.join(", ")
+                );
+            }
+        }
+        return Ok(());
+    }
+    if json_array {
+        println!("{}", serde_json::to_string(&hits)?);
+        return Ok(());
+    }
+    for hit in hits {
+        println!("{}", serde_json::to_string(&hit)?);
+    }
+    Ok(())
+}
+
+/// Point out that records ingested before memex stored `what` lack it, and
+/// that `memex migrate` can't add it: only a reindex rereads transcripts.
+fn note_if_predates_tool_details(paths: &Paths, what: &str) {
+    if crate::index::predates_tool_details(&paths.index) {
+        eprintln!(
+            "note: sessions indexed by an older memex have no recorded {what}; run `memex reindex` to add them"
+        );
+    }
+}
+
+fn run_diff(
+    session_id: String,
+    path: Option<String>,
+    json: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let index = SearchIndex::open_or_create(&paths.index)?;
+    let pattern = path.as_deref().map(PathPattern::new).transpose()?;
+    let mut records = index.records_by_session_id(&session_id)?;
+    if records.is_empty() {
+        return Err(anyhow!("session not found: {session_id}"));
+    }
+    records.sort_by(|a, b| {
+        a.turn_id
+            .cmp(&b.turn_id)
+            .then_with(|| a.ts.cmp(&b.ts))
+            .then_with(|| a.doc_id.cmp(&b.doc_id))
+    });
+
+    let mut order: Vec<&str> = Vec::new();
+    let mut by_path: HashMap<&str, Vec<&FilePatch>> = HashMap::new();
+    for patch in records.iter().flat_map(|r| &r.patches) {
+        if let Some(pattern) = &pattern
+            && !pattern.matches(&patch.path)
+        {
+            continue;
+        }
+        let entry = by_path.entry(patch.path.as_str()).or_default();
+        if entry.is_empty() {
+            order.push(patch.path.as_str());
+        }
+        entry.push(patch);
+    }
+    if order.is_empty() {
+        eprintln!("no file changes recorded for session {session_id}");
+        note_if_predates_tool_details(&paths, "file changes");
+        return Ok(());
+    }
+    for path in order {
+        let Some(changes) = changeset(&by_path[path]) else {
+            continue;
+        };
+        if json {
+            println!("{}", serde_json::to_string(&changes)?);
         } else {
-            println!("{}", serde_json::to_string(&value)?);
+            if changes.baseline == Baseline::Unknown {
+                println!(
+                    "# {}: {} patch(es), contents before the session unknown",
+                    changes.path, changes.patches
+                );
+            }
+            print!("{}", changes.diff);
         }
     }
-
-    if render.json_array {
-        println!("{}", serde_json::to_string(&output)?);
-    }
     Ok(())
 }
 
-fn run_session(session_id: String, verbose: bool, root: Option<PathBuf>) -> Result<()> {
+#[derive(Serialize)]
+struct FileAtOutput<'a> {
+    path: &'a str,
+    turn: Option<u32>,
+    complete: bool,
+    deleted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text: Option<String>,
+    segments: Vec<Segment>,
+    notes: &'a [String],
+}
+
+fn run_file_at(
+    session_id: String,
+    path: String,
+    turn: Option<u32>,
+    json: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
     let paths = Paths::new(root)?;
     let index = SearchIndex::open_or_create(&paths.index)?;
+    let pattern = PathPattern::new(&path)?;
     let mut records = index.records_by_session_id(&session_id)?;
+    if records.is_empty() {
+        return Err(anyhow!("session not found: {session_id}"));
+    }
     records.sort_by(|a, b| {
         a.turn_id
             .cmp(&b.turn_id)
             .then_with(|| a.ts.cmp(&b.ts))
             .then_with(|| a.doc_id.cmp(&b.doc_id))
     });
-    if verbose {
-        for record in records {
-            let ts = format_ts(record.ts);
-            println!("{ts} {}", record.role);
-            if record.text.is_empty() {
-                println!("  <empty>");
-                continue;
+
+    if records.iter().all(|record| record.patches.is_empty()) {
+        note_if_predates_tool_details(&paths, "file changes");
+    }
+    let mut candidates: Vec<&str> = Vec::new();
+    for record in &records {
+        let named = record
+            .files
+            .iter()
+            .map(String::as_str)
+            .chain(record.patches.iter().map(|p| p.path.as_str()));
+        for file in named {
+            if pattern.matches(file) && !candidates.contains(&file) {
+                candidates.push(file);
             }
-            for line in record.text.lines() {
-                println!("  {line}");
+        }
+    }
+    let file = match candidates.as_slice() {
+        [] => return Err(anyhow!("no file matching {path} in session {session_id}")),
+        [file] => *file,
+        many => {
+            return Err(anyhow!(
+                "{path} matches several files, pick one:\n  {}",
+                many.join("\n  ")
+            ));
+        }
+    };
+
+    let replay = replay_file(&records, file, turn);
+    let state = &replay.state;
+    if !state.has_content() && replay.notes.is_empty() {
+        return Err(anyhow!(
+            "session {session_id} never read or wrote the contents of {file}"
+        ));
+    }
+    if json {
+        let output = FileAtOutput {
+            path: file,
+            turn: replay.last_turn,
+            complete: state.is_complete(),
+            deleted: state.is_deleted(),
+            text: state.text(),
+            segments: state.segments(),
+            notes: &replay.notes,
+        };
+        println!("{}", serde_json::to_string(&output)?);
+    } else if state.is_deleted() {
+        eprintln!("{file} was deleted");
+    } else if let Some(text) = state.text() {
+        print!("{text}");
+    } else {
+        for segment in state.segments() {
+            match segment {
+                Segment::Known { lines, .. } => {
+                    for line in lines {
+                        println!("{line}");
+                    }
+                }
+                Segment::Unknown {
+                    start,
+                    lines: Some(n),
+                } => println!("<<< memex: lines {start}-{} unknown >>>", start + n - 1),
+                Segment::Unknown { start, lines: None } => {
+                    println!("<<< memex: unknown from line {start} >>>")
+                }
             }
         }
-        return Ok(());
     }
-    for record in records {
-        println!("{}", serde_json::to_string(&record)?);
+    for note in &replay.notes {
+        eprintln!("{note}");
+    }
+    if !state.is_complete() {
+        std::process::exit(2);
     }
     Ok(())
 }
 
-fn run_show(doc_id: u64, verbose: bool, root: Option<PathBuf>) -> Result<()> {
+enum CommandMatcher {
+    Any,
+    Substring(String),
+    Prefix(String),
+    Regex(Regex),
+}
+
+impl CommandMatcher {
+    fn new(pattern: Option<&str>, prefix: bool, regex: bool) -> Result<Self> {
+        let Some(pattern) = pattern.filter(|p| !p.is_empty()) else {
+            return Ok(CommandMatcher::Any);
+        };
+        Ok(if regex {
+            CommandMatcher::Regex(Regex::new(pattern)?)
+        } else if prefix {
+            CommandMatcher::Prefix(pattern.to_string())
+        } else {
+            CommandMatcher::Substring(pattern.to_string())
+        })
+    }
+
+    fn matches(&self, command: &str) -> bool {
+        match self {
+            CommandMatcher::Any => true,
+            CommandMatcher::Substring(s) => command.contains(s.as_str()),
+            CommandMatcher::Prefix(s) => command.starts_with(s.as_str()),
+            CommandMatcher::Regex(re) => re.is_match(command),
+        }
+    }
+}
+
+enum CommandOutput {
+    Jsonl,
+    JsonArray,
+    Verbose,
+    Plain,
+    Print0,
+}
+
+#[derive(Serialize)]
+struct CommandHit {
+    ts: String,
+    doc_id: u64,
+    session_id: String,
+    project: String,
+    source: &'static str,
+    tool_name: Option<String>,
+    command: String,
+    exit_code: Option<i64>,
+    output: Option<String>,
+}
+
+fn run_commands(
+    matcher: CommandMatcher,
+    failed: bool,
+    options: QueryOptions,
+    output_chars: usize,
+    output: CommandOutput,
+    root: Option<PathBuf>,
+) -> Result<()> {
     let paths = Paths::new(root)?;
     let index = SearchIndex::open_or_create(&paths.index)?;
-    let record = index
-        .get_by_doc_id(doc_id)?
-        .ok_or_else(|| anyhow!("doc_id not found"))?;
-    if verbose {
-        println!("{}", serde_json::to_string_pretty(&record)?);
-        return Ok(());
+    let limit = options.limit;
+    let keep = |command: &str| matcher.matches(command);
+    if failed {
+        note_if_predates_tool_details(&paths, "exit codes");
+    }
+    let pairs = if failed {
+        let distinct = matches!(output, CommandOutput::Plain | CommandOutput::Print0);
+        failed_commands(&index, keep, options, distinct)?
+    } else {
+        paired_commands(&index, keep, options)?
+    };
+
+    let mut hits = Vec::new();
+    for (call, result) in pairs {
+        let exit_code = result.as_ref().and_then(|r| r.exit_code);
+        let output = result
+            .and_then(|r| r.tool_output)
+            .map(|text| tail_chars(&command_output(&text), output_chars));
+        hits.push(CommandHit {
+            ts: format_ts(call.ts),
+            doc_id: call.doc_id,
+            session_id: call.session_id,
+            project: call.project,
+            source: call.source.label(),
+            tool_name: call.tool_name,
+            command: call.command.unwrap_or_default(),
+            exit_code,
+            output,
+        });
+    }
+
+    match output {
+        CommandOutput::Plain | CommandOutput::Print0 => {
+            let mut seen = HashSet::new();
+            let mut out = std::io::stdout().lock();
+            for hit in hits {
+                if seen.len() >= limit {
+                    break;
+                }
+                if !seen.insert(hit.command.clone()) {
+                    continue;
+                }
+                if matches!(output, CommandOutput::Print0) {
+                    write!(out, "{}\0", hit.command)?;
+                } else {
+                    writeln!(
+                        out,
+                        "{}",
+                        hit.command.replace('\\', "\\\\").replace('\n', "\\n")
+                    )?;
+                }
+            }
+        }
+        CommandOutput::Verbose => {
+            for hit in hits.iter().take(limit) {
+                let status = match hit.exit_code {
+                    Some(code) => format!("exit {code}"),
+                    None => "exit ?".to_string(),
+                };
+                println!(
+                    "{} {} {} [{}] {}",
+                    hit.ts, hit.project, hit.session_id, status, hit.source
+                );
+                println!("  $ {}", hit.command.replace('\n', "\n    "));
+                if let Some(output) = hit.output.as_deref().filter(|o| !o.trim().is_empty()) {
+                    for line in output.trim_end().lines() {
+                        println!("  | {line}");
+                    }
+                }
+            }
+        }
+        CommandOutput::JsonArray => {
+            hits.truncate(limit);
+            println!("{}", serde_json::to_string(&hits)?);
+        }
+        CommandOutput::Jsonl => {
+            for hit in hits.iter().take(limit) {
+                println!("{}", serde_json::to_string(hit)?);
+            }
+        }
     }
-    println!("{}", serde_json::to_string(&record)?);
     Ok(())
 }
 
+/// Shell calls whose command `keep` accepts, each with its result, newest
+/// first.
+fn paired_commands<F>(
+    index: &SearchIndex,
+    keep: F,
+    options: QueryOptions,
+) -> Result<Vec<(Record, Option<Record>)>>
+where
+    F: Fn(&str) -> bool,
+{
+    // Calls and their results both carry the command, and repeats are
+    // dropped after pairing, so fetch generously.
+    let options = QueryOptions {
+        limit: (options.limit * 4).max(options.limit + 20),
+        ..options
+    };
+    let mut records = index.records_by_commands(keep, false, &options)?;
+    records.sort_by_key(|r| (r.ts, r.doc_id));
+
+    // Pair each call with its result by call id, falling back to the next result
+    // in the session that ran the same command for records indexed without ids.
+    let mut by_call_id: HashMap<(String, String), usize> = HashMap::new();
+    let mut pending: HashMap<(String, String), Vec<usize>> = HashMap::new();
+    let mut results: HashMap<usize, Record> = HashMap::new();
+    let mut calls: Vec<Record> = Vec::new();
+    for record in records {
+        let Some(command) = record.command.clone() else {
+            continue;
+        };
+        let id_key = record
+            .tool_call_id
+            .clone()
+            .map(|id| (record.session_id.clone(), id));
+        let key = (record.session_id.clone(), command);
+        if record.role == "tool_use" {
+            match id_key {
+                Some(id_key) => {
+                    by_call_id.insert(id_key, calls.len());
+                }
+                None => pending.entry(key).or_default().push(calls.len()),
+            }
+            calls.push(record);
+        } else if let Some(idx) = id_key.and_then(|k| by_call_id.remove(&k)) {
+            results.insert(idx, record);
+        } else if let Some(queue) = pending.get_mut(&key)
+            && !queue.is_empty()
+        {
+            results.insert(queue.remove(0), record);
+        }
+    }
+    Ok(calls
+        .into_iter()
+        .enumerate()
+        .rev()
+        .map(|(idx, call)| {
+            let result = results.remove(&idx);
+            (call, result)
+        })
+        .collect())
+}
+
+/// Shell calls whose command `keep` accepts and exited non-zero, each with
+/// its result, newest first. The index finds the failing results; with
+/// `distinct`, it is asked for more until `options.limit` different commands
+/// failed or there are no more failures.
+fn failed_commands<F>(
+    index: &SearchIndex,
+    keep: F,
+    options: QueryOptions,
+    distinct: bool,
+) -> Result<Vec<(Record, Option<Record>)>>
+where
+    F: Fn(&str) -> bool + Copy,
+{
+    let limit = options.limit;
+    let mut fetch = limit;
+    let results = loop {
+        let options = QueryOptions {
+            limit: fetch,
+            ..options.clone()
+        };
+        let results = index.records_by_commands(keep, true, &options)?;
+        let commands: HashSet<&str> = results
+            .iter()
+            .filter_map(|r| r.command.as_deref())
+            .collect();
+        if results.len() < fetch || !distinct || commands.len() >= limit {
+            break results;
+        }
+        fetch *= 4;
+    };
+    results
+        .into_iter()
+        .map(|result| {
+            // A result without its call stands in for it.
+            let call = index
+                .command_call(&result)?
+                .unwrap_or_else(|| result.clone());
+            Ok((call, Some(result)))
+        })
+        .collect()
+}
+
+/// The last `max` characters of `text`, marked when cut.
+fn tail_chars(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    format!("...{}", take_last_chars(text, max))
+}
+
 fn run_stats(root: Option<PathBuf>) -> Result<()> {
     let paths = Paths::new(root)?;
     let index = SearchIndex::open_or_create(&paths.index)?;
     println!("index: {}", paths.index.display());
     println!("documents: {}", index.doc_count()?);
-    print_vector_stats(&paths.vectors)?;
+    print_vector_stats(&paths)?;
     Ok(())
 }
 
-fn print_vector_stats(vectors_dir: &std::path::Path) -> Result<()> {
-    let meta_path = vectors_dir.join("meta.json");
-    let vectors_path = vectors_dir.join("vectors.f32");
-    let ids_path = vectors_dir.join("doc_ids.u64");
-    if !meta_path.exists() || !vectors_path.exists() || !ids_path.exists() {
+/// One line per model's vector store, marking the model searches use.
+fn print_vector_stats(paths: &Paths) -> Result<()> {
+    let active = UserConfig::load(paths)?.resolve_model(None)?;
+    let mut stores = Vec::new();
+    if let Ok(entries) = std::fs::read_dir(&paths.vectors) {
+        for entry in entries.flatten() {
+            if let Some(manifest) = crate::vector::Manifest::load(&entry.path())? {
+                stores.push((stored_vectors(&entry.path()), manifest));
+            }
+        }
+    }
+    stores.sort_by(|a, b| a.1.model.cmp(&b.1.model));
+    let legacy = VectorIndex::exists(&paths.vectors);
+    if stores.is_empty() && !legacy {
         println!("vectors: none");
+    }
+    for (count, manifest) in stores {
+        let marker = if manifest.model == active.id() {
+            " (active)"
+        } else {
+            ""
+        };
+        println!(
+            "vectors {}: {} (dims {}){}",
+            manifest.model, count, manifest.dims, marker
+        );
+    }
+    if legacy {
+        println!(
+            "vectors (no model yet): {} in {}",
+            stored_vectors(&paths.vectors),
+            paths.vectors.display()
+        );
+    }
+    Ok(())
+}
+
+/// Every downloadable model: embedding models, then rerankers.
+fn built_in_models() -> Vec<ModelFiles> {
+    ModelChoice::BUILT_IN
+        .iter()
+        .filter_map(ModelChoice::files)
+        .chain(RerankerChoice::ALL.map(RerankerChoice::files))
+        .collect()
+}
+
+/// A built-in model by any name `--model` or `rerank_model` accepts.
+/// Embedding models win where the two overlap (`bge`).
+fn model_files(config: &UserConfig, name: &str) -> Result<ModelFiles> {
+    if config.models.contains_key(name) {
+        return Err(anyhow!(
+            "model '{name}' is a local model from config.toml; there is nothing to download"
+        ));
+    }
+    if let Ok(model) = ModelChoice::parse(name) {
+        return model
+            .files()
+            .ok_or_else(|| anyhow!("model '{name}' has no files to download"));
+    }
+    RerankerChoice::parse(name)
+        .map(RerankerChoice::files)
+        .map_err(|_| anyhow!("unknown model '{name}'; see `memex models list`"))
+}
+
+/// Names of the configured embedding model and reranker.
+fn active_models(config: &UserConfig) -> Result<Vec<String>> {
+    let mut active = vec![config.resolve_reranker()?.id().to_string()];
+    if let Some(files) = config.resolve_model(None)?.files() {
+        active.push(files.name);
+    }
+    Ok(active)
+}
+
+fn run_models_list(json_array: bool, verbose: bool, root: Option<PathBuf>) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let config = UserConfig::load(&paths)?;
+    let cache = config.model_cache()?;
+    let active = active_models(&config)?;
+    let statuses: Vec<_> = built_in_models()
+        .iter()
+        .map(|model| cache.status(model, active.contains(&model.name)))
+        .collect();
+    if verbose {
+        println!("model dir: {}", cache.dir.display());
+        if cache.offline {
+            println!("offline: models are never downloaded");
+        }
+        for status in &statuses {
+            println!(
+                "{:<36} {:<9} {:<14} {:>10}  {}{}",
+                status.name,
+                status.kind,
+                if status.downloaded {
+                    "downloaded"
+                } else if status.size > 0 {
+                    "partial"
+                } else {
+                    "not downloaded"
+                },
+                if status.size > 0 {
+                    crate::progress::format_bytes(status.size)
+                } else {
+                    "-".to_string()
+                },
+                status.repo,
+                if status.active { " (active)" } else { "" }
+            );
+        }
+    } else if json_array {
+        println!("{}", serde_json::to_string(&statuses)?);
+    } else {
+        for status in &statuses {
+            println!("{}", serde_json::to_string(status)?);
+        }
+    }
+    Ok(())
+}
+
+fn run_models_pull(names: Vec<String>, root: Option<PathBuf>) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let config = UserConfig::load(&paths)?;
+    let cache = ModelCache {
+        offline: false,
+        ..config.model_cache()?
+    };
+    let models = if names.is_empty() {
+        let model = config.resolve_model(None)?;
+        vec![model.files().ok_or_else(|| {
+            anyhow!(
+                "configured model '{}' is a local model; name a built-in model to pull",
+                match &model {
+                    ModelChoice::Custom(custom) => custom.name.as_str(),
+                    _ => model.id(),
+                }
+            )
+        })?]
+    } else {
+        names
+            .iter()
+            .map(|name| model_files(&config, name))
+            .collect::<Result<Vec<_>>>()?
+    };
+    for model in &models {
+        if cache.snapshot(model).is_some() {
+            println!("{}: already downloaded", model.name);
+            continue;
+        }
+        let dir = cache.ensure(model, true)?;
+        println!(
+            "{}: downloaded {} to {}",
+            model.name,
+            crate::progress::format_bytes(cache.status(model, false).size),
+            dir.display()
+        );
+    }
+    Ok(())
+}
+
+fn run_models_verify(names: Vec<String>, root: Option<PathBuf>) -> Result<()> {
+    use crate::models::FileCheck;
+
+    let paths = Paths::new(root)?;
+    let config = UserConfig::load(&paths)?;
+    let cache = config.model_cache()?;
+    let models = if names.is_empty() {
+        built_in_models()
+            .into_iter()
+            .filter(|model| cache.repo_dir(model).exists())
+            .collect()
+    } else {
+        names
+            .iter()
+            .map(|name| model_files(&config, name))
+            .collect::<Result<Vec<_>>>()?
+    };
+    if models.is_empty() {
+        println!("no models downloaded");
         return Ok(());
     }
-    #[derive(serde::Deserialize)]
-    struct Meta {
-        dimensions: usize,
+    let mut failed = 0;
+    for model in &models {
+        let checks = cache.verify(model)?;
+        let problems: Vec<_> = checks
+            .iter()
+            .filter_map(|(file, check)| match check {
+                FileCheck::Missing => Some(format!("{file} missing")),
+                FileCheck::Mismatch => Some(format!("{file} checksum mismatch")),
+                FileCheck::Ok | FileCheck::Unchecked => None,
+            })
+            .collect();
+        if problems.is_empty() {
+            let checked = checks
+                .iter()
+                .filter(|(_, check)| *check == FileCheck::Ok)
+                .count();
+            println!(
+                "{}: ok ({checked} of {} files checksummed)",
+                model.name,
+                checks.len()
+            );
+        } else {
+            failed += 1;
+            println!("{}: {}", model.name, problems.join(", "));
+        }
+    }
+    if failed > 0 {
+        return Err(anyhow!(
+            "{failed} model(s) failed verification; `memex models rm` them and pull again"
+        ));
     }
-    let meta_str = std::fs::read_to_string(&meta_path)?;
-    let meta: Meta = serde_json::from_str(&meta_str)?;
-    let ids_bytes = std::fs::metadata(&ids_path)?.len();
-    let vec_bytes = std::fs::metadata(&vectors_path)?.len();
-    let ids = ids_bytes / 8;
-    let vecs = if meta.dimensions == 0 {
-        0
+    Ok(())
+}
+
+fn run_models_rm(
+    names: Vec<String>,
+    unused: bool,
+    force: bool,
+    root: Option<PathBuf>,
+) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let config = UserConfig::load(&paths)?;
+    let cache = config.model_cache()?;
+    let active = active_models(&config)?;
+    let models = if unused {
+        built_in_models()
+            .into_iter()
+            .filter(|model| !active.contains(&model.name) && cache.repo_dir(model).exists())
+            .collect()
     } else {
-        (vec_bytes / 4) / meta.dimensions as u64
+        names
+            .iter()
+            .map(|name| model_files(&config, name))
+            .collect::<Result<Vec<_>>>()?
     };
-    println!(
-        "vectors: {} (dims {}, ids {}, vectors.f32 {}, doc_ids.u64 {})",
-        vecs, meta.dimensions, ids, vec_bytes, ids_bytes
-    );
+    if !force && let Some(model) = models.iter().find(|model| active.contains(&model.name)) {
+        return Err(anyhow!(
+            "{} is the configured {} model; pass --force to remove it anyway",
+            model.name,
+            model.kind
+        ));
+    }
+    if models.is_empty() {
+        println!("no unused models downloaded");
+    }
+    for model in &models {
+        let size = cache.status(model, false).size;
+        if cache.remove(model)? {
+            println!(
+                "{}: removed {}",
+                model.name,
+                crate::progress::format_bytes(size)
+            );
+        } else {
+            println!("{}: not downloaded", model.name);
+        }
+    }
+    Ok(())
+}
+
+fn run_models_path(name: Option<String>, root: Option<PathBuf>) -> Result<()> {
+    let paths = Paths::new(root)?;
+    let config = UserConfig::load(&paths)?;
+    let cache = config.model_cache()?;
+    let Some(name) = name else {
+        println!("{}", cache.dir.display());
+        return Ok(());
+    };
+    let model = model_files(&config, &name)?;
+    let dir = cache
+        .snapshot(&model)
+        .ok_or_else(|| anyhow!("model '{}' is not downloaded", model.name))?;
+    println!("{}", dir.display());
     Ok(())
 }
 
@@ -2002,7 +4856,7 @@ fn apply_post_processing(
             results.sort_by(|a, b| b.0.partial_cmp(&a.0).unwrap_or(std::cmp::Ordering::Equal));
         }
         SortBy::Ts => {
-            results.sort_by(|a, b| b.1.ts.cmp(&a.1.ts));
+            results.sort_by_key(|r| std::cmp::Reverse(r.1.ts));
         }
     }
 
@@ -2035,51 +4889,32 @@ fn format_ts(ts: u64) -> String {
     dt.to_rfc3339_opts(SecondsFormat::Secs, true)
 }
 
-fn build_matchers(query: &str) -> Result<Vec<regex::Regex>> {
-    let mut terms = Vec::new();
-    let mut seen = std::collections::HashSet::new();
-    for part in query.split_whitespace() {
-        let cleaned = part.trim_matches(|c: char| !c.is_alphanumeric());
-        if cleaned.len() < 2 {
-            continue;
-        }
-        let key = cleaned.to_lowercase();
-        if seen.insert(key.clone()) {
-            terms.push(key);
-        }
-    }
-    let mut out = Vec::new();
-    for term in terms {
-        let re = RegexBuilder::new(&regex::escape(&term))
-            .case_insensitive(true)
-            .build()?;
-        out.push(re);
-    }
-    Ok(out)
+fn match_spans(text: &str, ranges: &[std::ops::Range<usize>], max: usize) -> Vec<MatchSpan> {
+    ranges
+        .iter()
+        .take(max)
+        .map(|range| MatchSpan {
+            start: range.start,
+            end: range.end,
+            text: text[range.clone()].to_string(),
+            before: take_last_chars(&text[..range.start], 40),
+            after: take_first_chars(&text[range.end..], 40),
+        })
+        .collect()
 }
 
-fn collect_matches(text: &str, matchers: &[regex::Regex], max: usize) -> Vec<MatchSpan> {
-    if text.is_empty() || matchers.is_empty() || max == 0 {
-        return Vec::new();
+/// `summarize` the snippet window, or the start of `text` without one, marking
+/// where the window cuts the text with `...`.
+fn snippet_text(text: &str, window: Option<std::ops::Range<usize>>, max: usize) -> String {
+    let Some(window) = window else {
+        return summarize(text, max);
+    };
+    let mut out = summarize(&text[window.clone()], max);
+    if window.start > 0 {
+        out.insert_str(0, "...");
     }
-    let mut out = Vec::new();
-    for re in matchers {
-        for m in re.find_iter(text) {
-            if out.len() >= max {
-                return out;
-            }
-            let start = m.start();
-            let end = m.end();
-            let before = take_last_chars(&text[..start], 40);
-            let after = take_first_chars(&text[end..], 40);
-            out.push(MatchSpan {
-                start,
-                end,
-                text: m.as_str().to_string(),
-                before,
-                after,
-            });
-        }
+    if window.end < text.len() && !out.ends_with("...") {
+        out.push_str("...");
     }
     out
 }
@@ -2100,17 +4935,13 @@ fn is_embedding_role(role: &str) -> bool {
     role == "user" || role == "assistant"
 }
 
-fn truncate_for_embedding(mut text: String) -> String {
-    const EMBED_MAX_CHARS: usize = 8192;
-    if text.len() <= EMBED_MAX_CHARS {
-        return text;
-    }
-    let mut end = EMBED_MAX_CHARS.min(text.len());
-    while end > 0 && !text.is_char_boundary(end) {
+/// The first `max` bytes of `text`, cut at a char boundary.
+fn text_prefix(text: &str, max: usize) -> &str {
+    let mut end = max.min(text.len());
+    while !text.is_char_boundary(end) {
         end -= 1;
     }
-    text.truncate(end);
-    text
+    &text[..end]
 }
 
 fn resolve_flag(default: bool, enable: bool, disable: bool, name: &str) -> Result<bool> {
@@ -2330,3 +5161,139 @@ fn parse_version_parts(value: &str) -> Option<(u64, u64, u64)> {
     }
     Some((parts[0], parts[1], parts[2]))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SourceKind;
+    use tempfile::TempDir;
+
+    fn record(doc_id: u64, session_id: &str, text: &str) -> Record {
+        Record {
+            source: SourceKind::Claude,
+            doc_id,
+            ts: 1_735_725_600_000 + doc_id,
+            project: "demo".to_string(),
+            session_id: session_id.to_string(),
+            turn_id: 0,
+            role: "assistant".to_string(),
+            text: text.to_string(),
+            tool_name: None,
+            tool_input: None,
+            tool_output: None,
+            files: Vec::new(),
+            command: None,
+            exit_code: None,
+            tool_call_id: None,
+            patches: Vec::new(),
+            source_path: format!("/tmp/{session_id}.jsonl"),
+        }
+    }
+
+    fn options(limit: usize) -> QueryOptions {
+        QueryOptions {
+            query: String::new(),
+            project: None,
+            role: None,
+            tool: None,
+            session_id: None,
+            source: None,
+            since: None,
+            until: None,
+            fuzzy: None,
+            limit,
+        }
+    }
+
+    /// Three messages under a root whose config selects an 8-dim model, so
+    /// tests can write its vector store directly. Doc 1 shares its words
+    /// with doc 2 and nothing with doc 3.
+    fn similar_fixture(tmp: &TempDir) -> (Paths, SearchIndex) {
+        let paths = Paths::new(Some(tmp.path().to_path_buf())).unwrap();
+        paths.ensure_dirs().unwrap();
+        std::fs::write(
+            paths.root.join("config.toml"),
+            "model = \"tiny\"\n\n[models.tiny]\nkind = \"model2vec\"\npath = \"tiny\"\ndims = 8\n",
+        )
+        .unwrap();
+        let index = SearchIndex::open_or_create(&paths.index).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(1, "s1", "the tokio runtime panics on shutdown"),
+            record(2, "s2", "the tokio runtime panics again on shutdown"),
+            record(3, "s3", "graceful exit hangs forever"),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+        (paths, index)
+    }
+
+    fn similar_ids(paths: &Paths, index: &SearchIndex) -> Result<Vec<u64>> {
+        let results = similar_records(paths, index, &Seed::Doc(1), false, true, &options(10), 10)?;
+        Ok(results.into_iter().map(|(_, r)| r.doc_id).collect())
+    }
+
+    #[test]
+    fn test_similar_ranks_by_vectors() {
+        let tmp = TempDir::new().unwrap();
+        let (paths, index) = similar_fixture(&tmp);
+        let model = UserConfig::load(&paths)
+            .unwrap()
+            .resolve_model(None)
+            .unwrap();
+        let store = configured_store(&paths).unwrap();
+        let mut vectors =
+            VectorIndex::open_or_create(&store, model.id(), 8, model.template_version()).unwrap();
+        let axis = |i: usize, tilt: f32| {
+            let mut v = vec![0.0; 8];
+            v[i] = 1.0;
+            v[(i + 1) % 8] = tilt;
+            v
+        };
+        vectors.add(1, 0..0, &axis(0, 0.0)).unwrap();
+        vectors.add(2, 0..0, &axis(4, 0.0)).unwrap();
+        vectors.add(3, 0..0, &axis(0, 0.1)).unwrap();
+        vectors.save().unwrap();
+
+        // Doc 3 shares no words with the seed but sits next to it.
+        assert_eq!(similar_ids(&paths, &index).unwrap(), vec![3, 2]);
+    }
+
+    #[test]
+    fn test_similar_falls_back_to_text_without_vectors() {
+        let tmp = TempDir::new().unwrap();
+        let (paths, index) = similar_fixture(&tmp);
+        assert_eq!(similar_ids(&paths, &index).unwrap(), vec![2]);
+
+        // A store that exists but can't be read is reported.
+        let store = configured_store(&paths).unwrap();
+        std::fs::create_dir_all(&store).unwrap();
+        std::fs::write(store.join("usearch.index"), b"not an index").unwrap();
+        assert!(similar_ids(&paths, &index).is_err());
+    }
+
+    #[test]
+    fn test_models_rm_keeps_configured_model_without_force() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_path_buf();
+        std::fs::write(
+            root.join("config.toml"),
+            "model = \"potion\"\nmodel_dir = \"models\"\noffline = true\n",
+        )
+        .unwrap();
+        let config = UserConfig::load(&Paths::new(Some(root.clone())).unwrap()).unwrap();
+        let potion = model_files(&config, "potion").unwrap();
+        let repo = config.model_cache().unwrap().repo_dir(&potion);
+        std::fs::create_dir_all(&repo).unwrap();
+
+        let err = run_models_rm(vec!["potion".into()], false, false, Some(root.clone()))
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("--force"), "{err}");
+        assert!(repo.exists());
+
+        run_models_rm(vec!["potion".into()], false, true, Some(root)).unwrap();
+        assert!(!repo.exists());
+    }
+}
@@ -0,0 +1,162 @@
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde_json::Value;
+
+/// Tools that run a shell command.
+const SHELL_TOOLS: &[&str] = &[
+    "Bash",
+    "shell",
+    "shell_command",
+    "exec_command",
+    "container.exec",
+    "local_shell",
+];
+
+static EXIT_CODE_RE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^(?:Exit code|Process exited with code|exit code)[: ]\s*(-?\d+)\s*$")
+        .expect("exit code regex")
+});
+
+/// Lines of metadata a header can span before Codex's `Output:` line.
+const HEADER_LINES: usize = 6;
+
+/// Extract the shell command a tool call ran from its JSON input.
+pub fn tool_command(tool_name: &str, input: &str) -> Option<String> {
+    if !SHELL_TOOLS.contains(&tool_name) {
+        return None;
+    }
+    let value: Value = serde_json::from_str(input).ok()?;
+    let command = value.get("command").or_else(|| value.get("cmd"))?;
+    command_from_value(command)
+}
+
+/// Render a command given either as a string or as an argv array.
+///
+/// `["bash", "-lc", "cargo test"]` becomes `cargo test`; other argv arrays are
+/// joined with spaces, quoting arguments that contain whitespace.
+pub fn command_from_value(command: &Value) -> Option<String> {
+    let command = match command {
+        Value::String(s) => s.trim().to_string(),
+        Value::Array(items) => {
+            let argv: Vec<&str> = items.iter().filter_map(|v| v.as_str()).collect();
+            command_from_argv(&argv)
+        }
+        _ => return None,
+    };
+    if command.is_empty() {
+        None
+    } else {
+        Some(command)
+    }
+}
+
+fn command_from_argv(argv: &[&str]) -> String {
+    if argv.len() == 3 && matches!(argv[1], "-c" | "-lc") {
+        let shell = argv[0].rsplit('/').next().unwrap_or(argv[0]);
+        if matches!(shell, "bash" | "sh" | "zsh") {
+            return argv[2].trim().to_string();
+        }
+    }
+    argv.iter()
+        .map(|arg| {
+            if arg.is_empty() || arg.chars().any(char::is_whitespace) {
+                format!("'{}'", arg.replace('\'', r"'\''"))
+            } else {
+                arg.to_string()
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Exit status of a shell tool result.
+///
+/// Reads Codex's `{"output", "metadata": {"exit_code"}}` wrapper or an
+/// `Exit code N` line. Claude only flags failures, so an error without a code
+/// maps to 1 and a success to 0.
+pub fn exit_code(output: &str, is_error: Option<bool>) -> Option<i64> {
+    if let Ok(value) = serde_json::from_str::<Value>(output)
+        && let Some(code) = value
+            .get("metadata")
+            .and_then(|m| m.get("exit_code"))
+            .and_then(|c| c.as_i64())
+    {
+        return Some(code);
+    }
+    if let Some(code) = result_header(output)
+        .find_map(|line| EXIT_CODE_RE.captures(line))
+        .and_then(|c| c.get(1))
+        .and_then(|m| m.as_str().parse().ok())
+    {
+        return Some(code);
+    }
+    is_error.map(|err| if err { 1 } else { 0 })
+}
+
+/// The metadata lines a shell result starts with, where its exit code is:
+/// those before Codex's `Output:` line, or else just the first line, as in
+/// Claude's `Exit code N`. The command's own output is never searched.
+fn result_header(output: &str) -> impl Iterator<Item = &str> {
+    let lines: Vec<&str> = output.lines().take(HEADER_LINES + 1).collect();
+    let end = lines
+        .iter()
+        .position(|line| line.trim_end() == "Output:")
+        .unwrap_or(1);
+    lines.into_iter().take(end)
+}
+
+/// The command output inside a tool result, unwrapping Codex's JSON envelope.
+pub fn command_output(output: &str) -> String {
+    if let Ok(value) = serde_json::from_str::<Value>(output)
+        && let Some(text) = value.get("output").and_then(|v| v.as_str())
+    {
+        return text.to_string();
+    }
+    output.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tool_command() {
+        let claude = r#"{"command":"cargo test --workspace","description":"Run tests"}"#;
+        assert_eq!(
+            tool_command("Bash", claude).as_deref(),
+            Some("cargo test --workspace")
+        );
+        let codex = r#"{"command":["bash","-lc","rg -n foo src"],"workdir":"/repo"}"#;
+        assert_eq!(
+            tool_command("shell", codex).as_deref(),
+            Some("rg -n foo src")
+        );
+        let argv = r#"{"command":["git","commit","-m","fix it"]}"#;
+        assert_eq!(
+            tool_command("shell", argv).as_deref(),
+            Some("git commit -m 'fix it'")
+        );
+        assert_eq!(tool_command("Read", r#"{"command":"ls"}"#), None);
+    }
+
+    #[test]
+    fn test_exit_code() {
+        let codex = r#"{"output":"ok\n","metadata":{"exit_code":2,"duration_seconds":0.1}}"#;
+        assert_eq!(exit_code(codex, None), Some(2));
+        assert_eq!(command_output(codex), "ok\n");
+        assert_eq!(exit_code("Exit code 101\npanicked", Some(true)), Some(101));
+        assert_eq!(exit_code("Exit code: 0\nWall time: 1s", None), Some(0));
+        assert_eq!(exit_code("permission denied", Some(true)), Some(1));
+        assert_eq!(exit_code("done", Some(false)), Some(0));
+        assert_eq!(exit_code("done", None), None);
+
+        let exec =
+            "Chunk ID: 5d1f\nWall time: 0.2 seconds\nProcess exited with code 2\nOutput:\nerror\n";
+        assert_eq!(exit_code(exec, None), Some(2));
+        // "exit code 1" printed by the command itself is output, not status.
+        let nested = "Exit code: 0\nWall time: 1s\nOutput:\nexit code 1\n";
+        assert_eq!(exit_code(nested, None), Some(0));
+        assert_eq!(exit_code("running\nexit code 1\n", None), None);
+        assert_eq!(exit_code("running\nexit code 1\n", Some(false)), Some(0));
+    }
+}
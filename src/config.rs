@@ -1,15 +1,22 @@
-use crate::embed::ModelChoice;
+use crate::embed::{CustomModel, ModelChoice, RerankerChoice};
+use crate::fusion::FusionStrategy;
+use crate::models::ModelCache;
 use anyhow::{Result, anyhow};
 use directories::BaseDirs;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::PathBuf;
+use std::sync::Arc;
 
 #[derive(Debug, Clone)]
 pub struct Paths {
     pub root: PathBuf,
     pub index: PathBuf,
+    /// One vector store per embedding model, in `<model id>/`.
     pub vectors: PathBuf,
     pub state: PathBuf,
+    /// Embeddings by text hash, in `<model id>/`. `reindex` keeps them.
+    pub embed_cache: PathBuf,
 }
 
 impl Paths {
@@ -26,6 +33,7 @@ impl Paths {
             index: root.join("index"),
             vectors: root.join("vectors"),
             state: root.join("state"),
+            embed_cache: root.join("cache").join("embeddings"),
             root,
         })
     }
@@ -36,6 +44,18 @@ impl Paths {
         std::fs::create_dir_all(&self.state)?;
         Ok(())
     }
+
+    /// Delete the index, vector stores and ingest state. Everything else
+    /// under the root stays: config.toml, the embedding cache, and any model
+    /// files a relative path in the config points at.
+    pub fn clear_for_reindex(&self) -> Result<()> {
+        for dir in [&self.index, &self.vectors, &self.state] {
+            if dir.exists() {
+                std::fs::remove_dir_all(dir)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 pub fn default_claude_source() -> PathBuf {
@@ -49,8 +69,33 @@ pub fn default_claude_source() -> PathBuf {
 pub struct UserConfig {
     pub embeddings: Option<bool>,
     pub auto_index_on_search: Option<bool>,
-    /// Embedding model: minilm, bge, nomic, gemma (default), potion
+    /// Embedding model: minilm, bge, nomic, gemma (default), potion, or a
+    /// name from `models`
     pub model: Option<String>,
+    /// Local embedding models, `[models.<name>]`, selectable by name like the
+    /// built-ins.
+    #[serde(default)]
+    pub models: BTreeMap<String, CustomModel>,
+    /// Directory for downloaded model weights. Default: `MEMEX_MODEL_DIR`,
+    /// else `memex/models` in the user cache directory.
+    pub model_dir: Option<PathBuf>,
+    /// Never download models; fail when one isn't in the model directory.
+    /// Default: false.
+    pub offline: Option<bool>,
+    /// Hybrid search fusion: rrf (default), weighted-rrf, min-max, z-score, bm25
+    pub fusion: Option<String>,
+    /// BM25 weight for weighted fusion strategies. Default: 1.0.
+    pub fusion_bm25_weight: Option<f32>,
+    /// Vector weight for weighted fusion strategies. Default: 1.0.
+    pub fusion_vector_weight: Option<f32>,
+    /// Rank offset for reciprocal rank fusion. Default: 60.
+    pub fusion_rrf_k: Option<f32>,
+    /// Rerank hybrid search results with a cross-encoder. Default: false.
+    pub rerank: Option<bool>,
+    /// Reranking model: bge (default), bge-m3, jina, jina-v2
+    pub rerank_model: Option<String>,
+    /// How many fused hybrid candidates the reranker scores. Default: 50.
+    pub rerank_candidates: Option<usize>,
     /// Scan cache TTL in seconds. If a scan was done within this time,
     /// skip re-scanning on search. Default: 3600 seconds (1 hour).
     pub scan_cache_ttl: Option<u64>,
@@ -89,7 +134,19 @@ impl UserConfig {
             return Ok(Self::default());
         }
         let contents = std::fs::read_to_string(path)?;
-        let config: UserConfig = toml::from_str(&contents)?;
+        let mut config: UserConfig = toml::from_str(&contents)?;
+        for (name, model) in &mut config.models {
+            model.name = name.clone();
+            if model.path.is_relative() {
+                model.path = paths.root.join(&model.path);
+            }
+            model.identify();
+        }
+        if let Some(dir) = config.model_dir.as_mut()
+            && dir.is_relative()
+        {
+            *dir = paths.root.join(&*dir);
+        }
         Ok(config)
     }
 
@@ -103,17 +160,89 @@ impl UserConfig {
 
     pub fn resolve_model(&self, cli_model: Option<String>) -> Result<ModelChoice> {
         if let Some(model) = cli_model {
-            return ModelChoice::parse(&model);
+            return self.model_by_name(&model);
         }
         if let Some(model) = self.model.as_deref() {
-            return ModelChoice::parse(model);
+            return self.model_by_name(model);
         }
         if let Ok(model) = std::env::var("MEMEX_MODEL") {
-            return ModelChoice::parse(&model);
+            return self.model_by_name(&model);
         }
         Ok(ModelChoice::default())
     }
 
+    fn model_by_name(&self, name: &str) -> Result<ModelChoice> {
+        if let Some(model) = self.models.get(name) {
+            model.validate()?;
+            return Ok(ModelChoice::Custom(Arc::new(model.clone())));
+        }
+        ModelChoice::parse(name).map_err(|err| {
+            if self.models.is_empty() {
+                err
+            } else {
+                let names: Vec<&str> = self.models.keys().map(String::as_str).collect();
+                anyhow!("{err}, {}", names.join(", "))
+            }
+        })
+    }
+
+    /// Model directory priority: config file > `MEMEX_MODEL_DIR` > user cache
+    /// directory. It lives outside the memex root so `reindex` keeps it.
+    pub fn model_cache(&self) -> Result<ModelCache> {
+        let dir = match &self.model_dir {
+            Some(dir) => dir.clone(),
+            None => match std::env::var_os("MEMEX_MODEL_DIR") {
+                Some(dir) => PathBuf::from(dir),
+                None => BaseDirs::new()
+                    .ok_or_else(|| anyhow!("missing home dir"))?
+                    .cache_dir()
+                    .join("memex")
+                    .join("models"),
+            },
+        };
+        Ok(ModelCache {
+            dir,
+            offline: self.offline.unwrap_or(false),
+        })
+    }
+
+    pub fn fusion_strategy(&self) -> Result<FusionStrategy> {
+        match self.fusion.as_deref() {
+            Some(fusion) => FusionStrategy::parse(fusion),
+            None => Ok(FusionStrategy::default()),
+        }
+    }
+
+    pub fn fusion_bm25_weight(&self) -> f32 {
+        self.fusion_bm25_weight.unwrap_or(1.0)
+    }
+
+    pub fn fusion_vector_weight(&self) -> f32 {
+        self.fusion_vector_weight.unwrap_or(1.0)
+    }
+
+    pub fn fusion_rrf_k(&self) -> f32 {
+        self.fusion_rrf_k.unwrap_or(60.0)
+    }
+
+    pub fn rerank_default(&self) -> bool {
+        self.rerank.unwrap_or(false)
+    }
+
+    pub fn resolve_reranker(&self) -> Result<RerankerChoice> {
+        if let Some(model) = self.rerank_model.as_deref() {
+            return RerankerChoice::parse(model);
+        }
+        if let Ok(model) = std::env::var("MEMEX_RERANK_MODEL") {
+            return RerankerChoice::parse(&model);
+        }
+        Ok(RerankerChoice::default())
+    }
+
+    pub fn rerank_candidates(&self) -> usize {
+        self.rerank_candidates.unwrap_or(50)
+    }
+
     pub fn scan_cache_ttl(&self) -> u64 {
         self.scan_cache_ttl.unwrap_or(3600)
     }
@@ -134,3 +263,72 @@ impl UserConfig {
         self.index_service_poll_interval.unwrap_or(30)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_reindex_keeps_relative_model_files() {
+        let tmp = TempDir::new().unwrap();
+        let paths = Paths::new(Some(tmp.path().to_path_buf())).unwrap();
+        paths.ensure_dirs().unwrap();
+        std::fs::write(
+            paths.root.join("config.toml"),
+            "model = \"tiny\"\nmodel_dir = \"weights\"\n\n\
+             [models.tiny]\nkind = \"model2vec\"\npath = \"my-models/tiny\"\ndims = 8\n",
+        )
+        .unwrap();
+        let config = UserConfig::load(&paths).unwrap();
+        let model_path = config.models["tiny"].path.clone();
+        let model_dir = config.model_dir.clone().unwrap();
+        std::fs::create_dir_all(&model_path).unwrap();
+        std::fs::write(model_path.join("model.safetensors"), b"weights").unwrap();
+        std::fs::create_dir_all(&model_dir).unwrap();
+        std::fs::create_dir_all(paths.embed_cache.join("tiny")).unwrap();
+        std::fs::write(paths.index.join("meta.json"), b"{}").unwrap();
+
+        paths.clear_for_reindex().unwrap();
+
+        assert!(model_path.join("model.safetensors").exists());
+        assert!(model_dir.exists());
+        assert!(paths.embed_cache.join("tiny").exists());
+        assert!(paths.root.join("config.toml").exists());
+        assert!(!paths.index.exists());
+        assert!(!paths.vectors.exists());
+        assert!(!paths.state.exists());
+    }
+
+    #[test]
+    fn test_custom_model_id_follows_its_files() {
+        let tmp = TempDir::new().unwrap();
+        let paths = Paths::new(Some(tmp.path().to_path_buf())).unwrap();
+        paths.ensure_dirs().unwrap();
+        std::fs::write(
+            paths.root.join("config.toml"),
+            "[models.tiny]\nkind = \"model2vec\"\npath = \"tiny\"\ndims = 8\n",
+        )
+        .unwrap();
+        let id = || {
+            let config = UserConfig::load(&paths).unwrap();
+            config
+                .resolve_model(Some("tiny".into()))
+                .unwrap()
+                .id()
+                .to_string()
+        };
+        let missing = id();
+        assert!(missing.starts_with("tiny-"));
+
+        let weights = paths.root.join("tiny").join("model.safetensors");
+        std::fs::create_dir_all(weights.parent().unwrap()).unwrap();
+        std::fs::write(&weights, b"weights").unwrap();
+        let first = id();
+        assert_ne!(first, missing);
+        assert_eq!(id(), first);
+
+        std::fs::write(&weights, b"new weights").unwrap();
+        assert_ne!(id(), first);
+    }
+}
@@ -1,9 +1,28 @@
+use crate::models::{ModelCache, ModelFiles, TOKENIZER_FILES};
 use anyhow::{Result, anyhow};
-use fastembed::{EmbeddingModel, InitOptions, TextEmbedding};
+use fastembed::{
+    EmbeddingModel, InitOptions, InitOptionsUserDefined, Pooling, RerankInitOptions, RerankerModel,
+    TextEmbedding, TextRerank, TokenizerFiles, UserDefinedEmbeddingModel,
+};
 use model2vec_rs::model::StaticModel;
+use serde::Deserialize;
+use std::ops::Range;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+/// Tokens embedded per text unless a custom model sets `max_length`.
+const DEFAULT_MAX_LENGTH: usize = 512;
+
+const POTION_REPO: &str = "minishlab/potion-base-8M";
+
+/// Nomic's retrieval task prefixes: (query, document).
+const NOMIC_PROMPTS: (&str, &str) = ("search_query: ", "search_document: ");
+/// EmbeddingGemma's retrieval prompts: (query, document). Documents have no
+/// title.
+const GEMMA_PROMPTS: (&str, &str) = ("task: search result | query: ", "title: none | text: ");
 
 /// Supported embedding models
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Default)]
 pub enum ModelChoice {
     /// AllMiniLML6V2 - 22M params, 384 dims, very fast
     MiniLM,
@@ -16,16 +35,102 @@ pub enum ModelChoice {
     Gemma,
     /// PotionBase8M - 8M params, model2vec backend, tiny and fast
     Potion,
+    /// A local model declared under `[models.<name>]` in config.toml
+    Custom(Arc<CustomModel>),
 }
 
 impl ModelChoice {
-    fn fastembed_config(self) -> Option<(EmbeddingModel, usize)> {
+    pub const BUILT_IN: [ModelChoice; 5] = [
+        ModelChoice::MiniLM,
+        ModelChoice::BGESmall,
+        ModelChoice::Nomic,
+        ModelChoice::Gemma,
+        ModelChoice::Potion,
+    ];
+
+    fn fastembed_config(&self) -> Option<(EmbeddingModel, usize)> {
         match self {
             ModelChoice::MiniLM => Some((EmbeddingModel::AllMiniLML6V2, 384)),
             ModelChoice::BGESmall => Some((EmbeddingModel::BGESmallENV15, 384)),
             ModelChoice::Nomic => Some((EmbeddingModel::NomicEmbedTextV15, 768)),
             ModelChoice::Gemma => Some((EmbeddingModel::EmbeddingGemma300M, 768)),
-            ModelChoice::Potion => None,
+            ModelChoice::Potion | ModelChoice::Custom(_) => None,
+        }
+    }
+
+    /// Name of the model's vector store directory.
+    pub fn id(&self) -> &str {
+        match self {
+            ModelChoice::MiniLM => "minilm",
+            ModelChoice::BGESmall => "bge-small",
+            ModelChoice::Nomic => "nomic",
+            ModelChoice::Gemma => "gemma",
+            ModelChoice::Potion => "potion",
+            ModelChoice::Custom(model) => &model.id,
+        }
+    }
+
+    /// Files to download for a built-in model. None for custom models.
+    pub fn files(&self) -> Option<ModelFiles> {
+        let (repo, files) = match self {
+            ModelChoice::Custom(_) => return None,
+            ModelChoice::Potion => (
+                POTION_REPO.to_string(),
+                ["tokenizer.json", "model.safetensors", "config.json"]
+                    .map(String::from)
+                    .to_vec(),
+            ),
+            _ => {
+                let (model, _) = self.fastembed_config()?;
+                let info = TextEmbedding::get_model_info(&model).ok()?;
+                (
+                    info.model_code.clone(),
+                    onnx_files(&info.model_file, &info.additional_files),
+                )
+            }
+        };
+        Some(ModelFiles {
+            name: self.id().to_string(),
+            kind: "embedding",
+            repo,
+            files,
+        })
+    }
+
+    /// Text put before (queries, documents) when embedding them.
+    pub fn prompts(&self) -> (&str, &str) {
+        match self {
+            ModelChoice::Nomic => NOMIC_PROMPTS,
+            ModelChoice::Gemma => GEMMA_PROMPTS,
+            ModelChoice::Custom(model) => (
+                model.query_prefix.as_deref().unwrap_or_default(),
+                model.document_prefix.as_deref().unwrap_or_default(),
+            ),
+            _ => ("", ""),
+        }
+    }
+
+    /// Version of the document prompt, recorded with stored vectors so that
+    /// changing it re-embeds them. Bump a built-in's number whenever its
+    /// document prompt changes; custom models use a checksum of theirs.
+    pub fn template_version(&self) -> u32 {
+        match self {
+            ModelChoice::Nomic | ModelChoice::Gemma => 1,
+            ModelChoice::Custom(model) => model
+                .document_prefix
+                .as_deref()
+                .filter(|prefix| !prefix.is_empty())
+                .map_or(0, fnv1a),
+            _ => 0,
+        }
+    }
+
+    /// Embedding dimensions the model produces.
+    pub fn dims(&self) -> usize {
+        match self {
+            ModelChoice::Potion => 256,
+            ModelChoice::Custom(model) => model.dims,
+            _ => self.fastembed_config().map_or(0, |(_, dims)| dims),
         }
     }
 
@@ -46,27 +151,324 @@ impl ModelChoice {
     }
 }
 
+/// How a custom model is loaded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomKind {
+    /// `model.onnx` (or `onnx/model.onnx`) with tokenizer.json, config.json,
+    /// special_tokens_map.json and tokenizer_config.json, run by fastembed
+    Onnx,
+    /// A model2vec directory: model.safetensors, tokenizer.json, config.json
+    Model2Vec,
+}
+
+/// Token pooling for custom ONNX models.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomPooling {
+    Mean,
+    Cls,
+}
+
+/// An embedding model loaded from a local directory, for machines that can't
+/// reach the Hugging Face Hub or for domain-tuned models.
+#[derive(Debug, Clone, Deserialize)]
+pub struct CustomModel {
+    /// The `[models.<name>]` key.
+    #[serde(skip)]
+    pub name: String,
+    /// The name plus a hash of the model files' sizes and modification
+    /// times; names the vector store and embedding cache, so replacing the
+    /// files starts new ones. Set by `identify`.
+    #[serde(skip)]
+    pub id: String,
+    pub kind: CustomKind,
+    /// Directory holding the model files. Relative paths are resolved from
+    /// the memex root.
+    pub path: PathBuf,
+    /// Embedding dimensions, checked when the model loads.
+    pub dims: usize,
+    /// ONNX only: mean (default) or cls.
+    pub pooling: Option<CustomPooling>,
+    /// Scale embeddings to unit length. Default: true. fastembed always
+    /// normalizes ONNX output, so this can only be turned off for model2vec.
+    pub normalize: Option<bool>,
+    /// Tokens embedded per text. Default: 512.
+    pub max_length: Option<usize>,
+    /// Put before search queries, e.g. "search_query: ".
+    pub query_prefix: Option<String>,
+    /// Put before stored text, e.g. "search_document: ". Changing it
+    /// re-embeds the model's vectors.
+    pub document_prefix: Option<String>,
+}
+
+impl CustomModel {
+    /// Reject definitions that could never load or would collide with a
+    /// built-in model's vector store.
+    pub fn validate(&self) -> Result<()> {
+        let name = &self.name;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(anyhow!(
+                "model name '{name}' may only use letters, digits, '-' and '_'"
+            ));
+        }
+        if ModelChoice::parse(name).is_ok() {
+            return Err(anyhow!("model '{name}' shadows a built-in model"));
+        }
+        if self.dims == 0 {
+            return Err(anyhow!("model '{name}': dims must be at least 1"));
+        }
+        if self.max_length == Some(0) {
+            return Err(anyhow!("model '{name}': max_length must be at least 1"));
+        }
+        match self.kind {
+            CustomKind::Onnx if self.normalize == Some(false) => Err(anyhow!(
+                "model '{name}': ONNX embeddings are always normalized"
+            )),
+            CustomKind::Model2Vec if self.pooling.is_some() => Err(anyhow!(
+                "model '{name}': pooling only applies to ONNX models"
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Set `id` from the model files now in `path`. Missing files hash as
+    /// absent; loading the model reports them.
+    pub fn identify(&mut self) {
+        let mut stamp = String::new();
+        for file in ["model.onnx", "onnx/model.onnx", "model.safetensors"]
+            .into_iter()
+            .chain(TOKENIZER_FILES)
+        {
+            let Ok(meta) = std::fs::metadata(self.path.join(file)) else {
+                continue;
+            };
+            let modified = meta
+                .modified()
+                .ok()
+                .and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+                .map_or(0, |since| since.as_nanos());
+            stamp.push_str(&format!("{file} {} {modified}\n", meta.len()));
+        }
+        self.id = format!("{}-{:08x}", self.name, fnv1a(&stamp));
+    }
+
+    fn read(&self, file: &str) -> Result<Vec<u8>> {
+        let path = self.path.join(file);
+        std::fs::read(&path)
+            .map_err(|err| anyhow!("model '{}': reading {}: {err}", self.name, path.display()))
+    }
+}
+
+/// Supported cross-encoder reranking models
+#[derive(Debug, Clone, Copy, Default)]
+pub enum RerankerChoice {
+    /// bge-reranker-base - 278M params, English and Chinese
+    #[default]
+    BGEBase,
+    /// bge-reranker-v2-m3 - 568M params, multilingual, slowest
+    BGEV2M3,
+    /// jina-reranker-v1-turbo-en - 38M params, English, fastest
+    JinaTurbo,
+    /// jina-reranker-v2-base-multilingual - 278M params, multilingual
+    JinaV2,
+}
+
+impl RerankerChoice {
+    pub const ALL: [RerankerChoice; 4] = [
+        RerankerChoice::BGEBase,
+        RerankerChoice::BGEV2M3,
+        RerankerChoice::JinaTurbo,
+        RerankerChoice::JinaV2,
+    ];
+
+    /// Name the model is listed under by `memex models`.
+    pub fn id(self) -> &'static str {
+        match self {
+            RerankerChoice::BGEBase => "bge-reranker-base",
+            RerankerChoice::BGEV2M3 => "bge-reranker-v2-m3",
+            RerankerChoice::JinaTurbo => "jina-reranker-v1-turbo-en",
+            RerankerChoice::JinaV2 => "jina-reranker-v2-base-multilingual",
+        }
+    }
+
+    pub fn files(self) -> ModelFiles {
+        let info = TextRerank::get_model_info(&self.fastembed_model());
+        ModelFiles {
+            name: self.id().to_string(),
+            kind: "reranker",
+            repo: info.model_code,
+            files: onnx_files(&info.model_file, &info.additional_files),
+        }
+    }
+
+    fn fastembed_model(self) -> RerankerModel {
+        match self {
+            RerankerChoice::BGEBase => RerankerModel::BGERerankerBase,
+            RerankerChoice::BGEV2M3 => RerankerModel::BGERerankerV2M3,
+            RerankerChoice::JinaTurbo => RerankerModel::JINARerankerV1TurboEn,
+            RerankerChoice::JinaV2 => RerankerModel::JINARerankerV2BaseMultiligual,
+        }
+    }
+
+    /// Parse from string (env var or config)
+    pub fn parse(s: &str) -> Result<Self> {
+        match s.to_lowercase().as_str() {
+            "bge" | "bge-base" | "bge-reranker-base" | "default" => Ok(RerankerChoice::BGEBase),
+            "bge-m3" | "bge-v2-m3" | "bge-reranker-v2-m3" => Ok(RerankerChoice::BGEV2M3),
+            "jina" | "jina-turbo" | "jina-reranker-v1-turbo-en" => Ok(RerankerChoice::JinaTurbo),
+            "jina-v2" | "jina-reranker-v2-base-multilingual" => Ok(RerankerChoice::JinaV2),
+            _ => Err(anyhow!(
+                "unknown reranker '{s}', options: bge, bge-m3, jina, jina-v2"
+            )),
+        }
+    }
+}
+
+/// 32-bit FNV-1a, a stable checksum for custom document prompts.
+fn fnv1a(text: &str) -> u32 {
+    text.bytes().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+/// An ONNX model's weights plus the tokenizer files fastembed loads with it.
+fn onnx_files(model_file: &str, additional_files: &[String]) -> Vec<String> {
+    let mut files = vec![model_file.to_string()];
+    files.extend(additional_files.iter().cloned());
+    files.extend(TOKENIZER_FILES.map(String::from));
+    files
+}
+
+/// A cross-encoder that scores (query, document) pairs.
+pub struct RerankerHandle {
+    model: TextRerank,
+}
+
+impl RerankerHandle {
+    pub fn with_model(choice: RerankerChoice, cache: &ModelCache) -> Result<Self> {
+        cache.ensure(&choice.files(), false)?;
+        use_all_cores();
+        let opts = RerankInitOptions::new(choice.fastembed_model())
+            .with_cache_dir(cache.dir.clone())
+            .with_show_download_progress(false);
+        Ok(Self {
+            model: TextRerank::try_new(opts)?,
+        })
+    }
+
+    /// Relevance of each document to `query`, in document order. Scores are
+    /// raw logits: higher is more relevant.
+    pub fn score(&mut self, query: &str, documents: &[&str]) -> Result<Vec<f32>> {
+        if documents.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut scores = vec![0.0; documents.len()];
+        for result in self.model.rerank(query, documents, false, None)? {
+            if let Some(score) = scores.get_mut(result.index) {
+                *score = result.score;
+            }
+        }
+        Ok(scores)
+    }
+}
+
+/// Set thread count for ONNX Runtime to use all cores.
+fn use_all_cores() {
+    let num_cpus = std::thread::available_parallelism()
+        .map(|p| p.get())
+        .unwrap_or(8);
+    unsafe {
+        std::env::set_var("OMP_NUM_THREADS", num_cpus.to_string());
+        std::env::set_var("ORT_NUM_THREADS", num_cpus.to_string());
+    }
+}
+
+/// Bytes of text per embedded chunk, about what a 512-token model reads.
+pub const CHUNK_BYTES: usize = 2048;
+/// Bytes each chunk repeats from the end of the one before, so a passage
+/// cut at a chunk boundary is still whole in one of them.
+const CHUNK_OVERLAP: usize = 256;
+
+/// Overlapping byte spans covering `text`, each at most `CHUNK_BYTES` long
+/// and ending at a line break or space where one is near. A text that fits
+/// one chunk is a single span.
+pub fn chunk_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = 0;
+    loop {
+        let mut end = floor_char_boundary(text, start + CHUNK_BYTES);
+        if end >= text.len() {
+            spans.push(start..text.len());
+            return spans;
+        }
+        let earliest = start + CHUNK_BYTES * 3 / 4;
+        let piece = &text[start..end];
+        if let Some(at) = piece
+            .rfind('\n')
+            .filter(|at| start + at >= earliest)
+            .or_else(|| piece.rfind(' ').filter(|at| start + at >= earliest))
+        {
+            end = start + at + 1;
+        }
+        spans.push(start..end);
+        let next = floor_char_boundary(text, end - CHUNK_OVERLAP);
+        // Begin after a space rather than mid-word, if one is early enough in
+        // the overlap to keep most of it; otherwise keep the raw byte overlap.
+        let window = floor_char_boundary(text, next + CHUNK_OVERLAP / 2);
+        start = text[next..window]
+            .find([' ', '\n'])
+            .map_or(next, |at| next + at + 1);
+    }
+}
+
+fn floor_char_boundary(text: &str, at: usize) -> usize {
+    let mut at = at.min(text.len());
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+fn with_prefix(prefix: &str, texts: &[&str]) -> Vec<String> {
+    texts.iter().map(|text| format!("{prefix}{text}")).collect()
+}
+
 enum EmbedBackend {
     Fastembed(TextEmbedding),
-    Model2Vec(StaticModel),
+    /// The model and its max tokens per text.
+    Model2Vec(StaticModel, usize),
 }
 
 pub struct EmbedderHandle {
     backend: EmbedBackend,
     pub dims: usize,
+    /// (query, document) prefixes from [`ModelChoice::prompts`].
+    prompts: (String, String),
 }
 
 impl EmbedderHandle {
-    pub fn with_model(choice: ModelChoice) -> Result<Self> {
+    pub fn with_model(choice: &ModelChoice, cache: &ModelCache) -> Result<Self> {
+        let mut handle = Self::load(choice, cache)?;
+        let (query, document) = choice.prompts();
+        handle.prompts = (query.to_string(), document.to_string());
+        Ok(handle)
+    }
+
+    fn load(choice: &ModelChoice, cache: &ModelCache) -> Result<Self> {
+        if let ModelChoice::Custom(model) = choice {
+            return Self::with_custom(model);
+        }
+        let files = choice
+            .files()
+            .ok_or_else(|| anyhow!("unknown model files for '{}'", choice.id()))?;
+        let snapshot = cache.ensure(&files, false)?;
         if let Some((model_type, dims)) = choice.fastembed_config() {
-            // Set thread count for ONNX Runtime to use all cores
-            let num_cpus = std::thread::available_parallelism()
-                .map(|p| p.get())
-                .unwrap_or(8);
-            unsafe {
-                std::env::set_var("OMP_NUM_THREADS", num_cpus.to_string());
-                std::env::set_var("ORT_NUM_THREADS", num_cpus.to_string());
-            }
+            use_all_cores();
 
             #[cfg(target_os = "macos")]
             let opts = {
@@ -86,46 +488,201 @@ impl EmbedderHandle {
                     .with_subgraphs(true)
                     .with_compute_units(compute_units);
                 InitOptions::new(model_type)
+                    .with_cache_dir(cache.dir.clone())
                     .with_show_download_progress(false)
                     .with_execution_providers(vec![provider.build()])
             };
 
             #[cfg(not(target_os = "macos"))]
-            let opts = InitOptions::new(model_type).with_show_download_progress(false);
+            let opts = InitOptions::new(model_type)
+                .with_cache_dir(cache.dir.clone())
+                .with_show_download_progress(false);
 
             let model = TextEmbedding::try_new(opts)?;
             Ok(Self {
                 backend: EmbedBackend::Fastembed(model),
                 dims,
+                prompts: Default::default(),
             })
         } else {
-            let model = StaticModel::from_pretrained("minishlab/potion-base-8M", None, None, None)?;
+            let model = StaticModel::from_pretrained(&snapshot, None, None, None)?;
             let dims = model
                 .encode(&[String::from("dimension_check")])
                 .first()
                 .map(|vec| vec.len())
                 .ok_or_else(|| anyhow!("no embedding returned"))?;
             Ok(Self {
-                backend: EmbedBackend::Model2Vec(model),
+                backend: EmbedBackend::Model2Vec(model, DEFAULT_MAX_LENGTH),
                 dims,
+                prompts: Default::default(),
             })
         }
     }
 
-    pub fn embed_texts(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+    fn with_custom(custom: &CustomModel) -> Result<Self> {
+        custom.validate()?;
+        if !custom.path.is_dir() {
+            return Err(anyhow!(
+                "model '{}': directory {} not found",
+                custom.name,
+                custom.path.display()
+            ));
+        }
+        let max_length = custom.max_length.unwrap_or(DEFAULT_MAX_LENGTH);
+        let backend = match custom.kind {
+            CustomKind::Onnx => {
+                use_all_cores();
+                let onnx = if custom.path.join("model.onnx").exists() {
+                    custom.read("model.onnx")?
+                } else {
+                    custom.read("onnx/model.onnx")?
+                };
+                let tokenizer_files = TokenizerFiles {
+                    tokenizer_file: custom.read("tokenizer.json")?,
+                    config_file: custom.read("config.json")?,
+                    special_tokens_map_file: custom.read("special_tokens_map.json")?,
+                    tokenizer_config_file: custom.read("tokenizer_config.json")?,
+                };
+                // fastembed panics when these are missing.
+                let tokenizer_config: serde_json::Value =
+                    serde_json::from_slice(&tokenizer_files.tokenizer_config_file)?;
+                if !tokenizer_config["model_max_length"].is_number()
+                    || !tokenizer_config["pad_token"].is_string()
+                {
+                    return Err(anyhow!(
+                        "model '{}': tokenizer_config.json needs model_max_length and pad_token",
+                        custom.name
+                    ));
+                }
+                let pooling = match custom.pooling.unwrap_or(CustomPooling::Mean) {
+                    CustomPooling::Mean => Pooling::Mean,
+                    CustomPooling::Cls => Pooling::Cls,
+                };
+                let model =
+                    UserDefinedEmbeddingModel::new(onnx, tokenizer_files).with_pooling(pooling);
+                let opts = InitOptionsUserDefined::new().with_max_length(max_length);
+                EmbedBackend::Fastembed(TextEmbedding::try_new_from_user_defined(model, opts)?)
+            }
+            CustomKind::Model2Vec => {
+                let model = StaticModel::from_pretrained(
+                    &custom.path,
+                    None,
+                    Some(custom.normalize.unwrap_or(true)),
+                    None,
+                )?;
+                EmbedBackend::Model2Vec(model, max_length)
+            }
+        };
+        let mut handle = Self {
+            backend,
+            dims: custom.dims,
+            prompts: Default::default(),
+        };
+        let dims = handle
+            .embed_texts(&["dimension_check"])?
+            .first()
+            .map(|vec| vec.len())
+            .ok_or_else(|| anyhow!("no embedding returned"))?;
+        if dims != custom.dims {
+            return Err(anyhow!(
+                "model '{}' produces {dims}-dim embeddings but config.toml declares dims = {}",
+                custom.name,
+                custom.dims
+            ));
+        }
+        Ok(handle)
+    }
+
+    /// Embed search queries, each behind the model's query prompt.
+    pub fn embed_queries(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let texts = with_prefix(&self.prompts.0, texts);
+        self.embed_texts(&texts.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    /// Embed stored text, each behind the model's document prompt.
+    pub fn embed_documents(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
+        let texts = with_prefix(&self.prompts.1, texts);
+        self.embed_texts(&texts.iter().map(String::as_str).collect::<Vec<_>>())
+    }
+
+    fn embed_texts(&mut self, texts: &[&str]) -> Result<Vec<Vec<f32>>> {
         if texts.is_empty() {
             return Ok(Vec::new());
         }
         match &mut self.backend {
             EmbedBackend::Fastembed(model) => Ok(model.embed(texts, None)?),
-            EmbedBackend::Model2Vec(model) => {
+            EmbedBackend::Model2Vec(model, max_length) => {
                 let input: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
-                Ok(model.encode_with_args(&input, Some(512), 64))
+                Ok(model.encode_with_args(&input, Some(*max_length), 64))
             }
         }
     }
 }
 
+/// A tiny model2vec model written to `dir`, for tests that embed without
+/// downloading anything. Each word of `vocab` gets its own 8-dim vector.
+#[cfg(test)]
+pub(crate) fn tiny_test_model(dir: &std::path::Path, vocab: &[&str]) -> ModelChoice {
+    const DIMS: usize = 8;
+    let words: Vec<&str> = ["[UNK]", "[PAD]"].iter().chain(vocab).copied().collect();
+    let ids: serde_json::Map<String, serde_json::Value> = words
+        .iter()
+        .enumerate()
+        .map(|(id, word)| (word.to_string(), id.into()))
+        .collect();
+    let tokenizer = serde_json::json!({
+        "version": "1.0",
+        "truncation": null,
+        "padding": null,
+        "added_tokens": [],
+        "normalizer": {"type": "Lowercase"},
+        "pre_tokenizer": {"type": "Whitespace"},
+        "post_processor": null,
+        "decoder": null,
+        "model": {"type": "WordLevel", "vocab": ids, "unk_token": "[UNK]"},
+    });
+    let mut data = Vec::new();
+    for row in 0..words.len() {
+        for col in 0..DIMS {
+            let x = ((row * 7 + col * 3) % 11) as f32 - 5.0;
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+    }
+    let mut header = serde_json::json!({
+        "embeddings": {
+            "dtype": "F32",
+            "shape": [words.len(), DIMS],
+            "data_offsets": [0, data.len()],
+        }
+    })
+    .to_string();
+    while !header.len().is_multiple_of(8) {
+        header.push(' ');
+    }
+    let mut safetensors = (header.len() as u64).to_le_bytes().to_vec();
+    safetensors.extend_from_slice(header.as_bytes());
+    safetensors.extend_from_slice(&data);
+
+    std::fs::create_dir_all(dir).unwrap();
+    std::fs::write(dir.join("tokenizer.json"), tokenizer.to_string()).unwrap();
+    std::fs::write(dir.join("model.safetensors"), safetensors).unwrap();
+    std::fs::write(dir.join("config.json"), r#"{"normalize": true}"#).unwrap();
+    let mut model = CustomModel {
+        name: "tiny".to_string(),
+        id: String::new(),
+        kind: CustomKind::Model2Vec,
+        path: dir.to_path_buf(),
+        dims: DIMS,
+        pooling: None,
+        normalize: None,
+        max_length: None,
+        query_prefix: None,
+        document_prefix: None,
+    };
+    model.identify();
+    ModelChoice::Custom(Arc::new(model))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -138,6 +695,12 @@ mod tests {
             .expect("lock fastembed")
     }
 
+    fn test_cache() -> ModelCache {
+        crate::config::UserConfig::default()
+            .model_cache()
+            .expect("model cache")
+    }
+
     fn test_embedder_from_env() -> EmbedderHandle {
         let choice = std::env::var("MEMEX_MODEL")
             .ok()
@@ -145,7 +708,7 @@ mod tests {
             .transpose()
             .expect("parse MEMEX_MODEL")
             .unwrap_or_default();
-        EmbedderHandle::with_model(choice).expect("failed to init embedder")
+        EmbedderHandle::with_model(&choice, &test_cache()).expect("failed to init embedder")
     }
 
     #[test]
@@ -241,14 +804,111 @@ mod tests {
         assert!(matches!(choice, ModelChoice::Potion));
     }
 
+    #[test]
+    fn test_custom_model_validation() {
+        let mut model: CustomModel =
+            toml::from_str("kind = \"model2vec\"\npath = \"models/code\"\ndims = 256\n")
+                .expect("parse custom model");
+        model.name = "code-m2v".to_string();
+        assert!(model.validate().is_ok());
+        model.pooling = Some(CustomPooling::Cls);
+        assert!(model.validate().is_err());
+        model.pooling = None;
+        model.name = "gemma".to_string();
+        assert!(model.validate().is_err());
+        model.name = "../code".to_string();
+        assert!(model.validate().is_err());
+        model.name = "code".to_string();
+        model.kind = CustomKind::Onnx;
+        model.normalize = Some(false);
+        assert!(model.validate().is_err());
+    }
+
+    #[test]
+    fn test_prompts_and_template_version() {
+        assert_eq!(ModelChoice::Nomic.prompts().0, "search_query: ");
+        assert_eq!(ModelChoice::Potion.prompts(), ("", ""));
+        assert_eq!(ModelChoice::Potion.template_version(), 0);
+        assert_ne!(ModelChoice::Gemma.template_version(), 0);
+
+        let mut model: CustomModel = toml::from_str(
+            "kind = \"model2vec\"\npath = \"m\"\ndims = 8\ndocument_prefix = \"\"\n",
+        )
+        .expect("parse custom model");
+        let empty = ModelChoice::Custom(Arc::new(model.clone()));
+        assert_eq!(empty.template_version(), 0);
+        model.document_prefix = Some("passage: ".to_string());
+        let prefixed = ModelChoice::Custom(Arc::new(model));
+        assert_eq!(prefixed.prompts(), ("", "passage: "));
+        assert_ne!(prefixed.template_version(), 0);
+    }
+
+    #[test]
+    fn test_chunk_spans_overlap_and_cover() {
+        assert_eq!(chunk_spans("short text"), vec![0..10]);
+
+        let text = "word é ".repeat(1000);
+        let spans = chunk_spans(&text);
+        assert!(spans.len() > 3);
+        assert_eq!(spans[0].start, 0);
+        assert_eq!(spans.last().unwrap().end, text.len());
+        for pair in spans.windows(2) {
+            assert!(pair[1].start < pair[0].end);
+            assert!(pair[1].start > pair[0].start);
+        }
+        for span in &spans {
+            assert!(span.len() <= CHUNK_BYTES);
+            assert!(span.start == 0 || text[..span.start].ends_with(' '));
+        }
+    }
+
+    #[test]
+    fn test_chunk_spans_without_spaces() {
+        for text in [
+            "x".repeat(CHUNK_BYTES * 4),
+            "é".repeat(CHUNK_BYTES * 2),
+            format!(
+                "{} {}",
+                "y".repeat(CHUNK_BYTES - 10),
+                "z".repeat(CHUNK_BYTES * 2)
+            ),
+        ] {
+            let spans = chunk_spans(&text);
+            assert!(spans.len() > 2);
+            assert_eq!(spans[0].start, 0);
+            assert_eq!(spans.last().unwrap().end, text.len());
+            for pair in spans.windows(2) {
+                assert!(pair[0].end - pair[1].start >= CHUNK_OVERLAP / 2);
+            }
+            for span in &spans {
+                assert!(span.len() <= CHUNK_BYTES);
+                assert!(text.is_char_boundary(span.start) && text.is_char_boundary(span.end));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_reranker() {
+        assert!(matches!(
+            RerankerChoice::parse("BGE").expect("parse bge"),
+            RerankerChoice::BGEBase
+        ));
+        assert!(matches!(
+            RerankerChoice::parse("jina-turbo").expect("parse jina-turbo"),
+            RerankerChoice::JinaTurbo
+        ));
+        assert!(RerankerChoice::parse("gemma").is_err());
+    }
+
     #[test]
     fn test_potion_embedding() {
         let _guard = fastembed_test_lock();
-        let mut embedder =
-            EmbedderHandle::with_model(ModelChoice::Potion).expect("init potion embedder");
+        let mut embedder = EmbedderHandle::with_model(&ModelChoice::Potion, &test_cache())
+            .expect("init potion embedder");
         let texts = vec!["potion model smoke test", "another short sentence"];
         let embeddings = embedder.embed_texts(&texts).expect("embed with potion");
         assert_eq!(embeddings.len(), 2);
         assert_eq!(embeddings[0].len(), embedder.dims);
+        assert_eq!(embedder.dims, ModelChoice::Potion.dims());
     }
 }
@@ -0,0 +1,216 @@
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+
+/// Tools whose calls change the files they name.
+const MODIFYING_TOOLS: &[&str] = &["Edit", "Write", "MultiEdit", "NotebookEdit", "apply_patch"];
+
+/// Extract the file paths a tool call operates on from its JSON input.
+pub fn tool_files(tool_name: &str, input: &str) -> Vec<String> {
+    let mut out = Vec::new();
+    let value: Option<Value> = serde_json::from_str(input).ok();
+    match value.as_ref() {
+        Some(Value::Object(obj)) => {
+            for key in ["file_path", "notebook_path"] {
+                if let Some(path) = obj.get(key).and_then(|v| v.as_str()) {
+                    push_unique(&mut out, path);
+                }
+            }
+            if let Some(patch) = obj.get("input").and_then(|v| v.as_str()) {
+                for path in patch_files(patch) {
+                    push_unique(&mut out, &path);
+                }
+            }
+            if let Some(command) = obj.get("command") {
+                for part in command_strings(command) {
+                    for path in patch_files(part) {
+                        push_unique(&mut out, &path);
+                    }
+                }
+            }
+        }
+        _ => {
+            if tool_name == "apply_patch" {
+                for path in patch_files(input) {
+                    push_unique(&mut out, &path);
+                }
+            }
+        }
+    }
+    out
+}
+
+/// File paths named by the headers of a Codex `apply_patch` body.
+pub fn patch_files(patch: &str) -> Vec<String> {
+    if !patch.contains("*** Begin Patch") {
+        return Vec::new();
+    }
+    let mut out = Vec::new();
+    for line in patch.lines() {
+        let line = line.trim_end();
+        for prefix in [
+            "*** Add File: ",
+            "*** Update File: ",
+            "*** Delete File: ",
+            "*** Move to: ",
+        ] {
+            if let Some(path) = line.strip_prefix(prefix) {
+                push_unique(&mut out, path.trim());
+            }
+        }
+    }
+    out
+}
+
+/// Whether a tool call writes to the files it names (as opposed to reading them).
+pub fn is_modifying_call(tool_name: Option<&str>, tool_input: Option<&str>) -> bool {
+    if let Some(name) = tool_name
+        && MODIFYING_TOOLS.contains(&name)
+    {
+        return true;
+    }
+    tool_input.is_some_and(|input| input.contains("*** Begin Patch"))
+}
+
+/// The string parts of a `command` given as a string or an argv array.
+pub fn command_strings(command: &Value) -> Vec<&str> {
+    match command {
+        Value::String(s) => vec![s.as_str()],
+        Value::Array(items) => items.iter().filter_map(|v| v.as_str()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn push_unique(out: &mut Vec<String>, path: &str) {
+    if path.is_empty() || out.iter().any(|p| p == path) {
+        return;
+    }
+    out.push(path.to_string());
+}
+
+/// A path or glob matched against indexed file paths.
+///
+/// Relative patterns match any path ending in them, so `src/*.rs` matches
+/// `/home/me/proj/src/main.rs`. Patterns starting with `/` are anchored.
+#[derive(Debug, Clone)]
+pub struct PathPattern {
+    re: Regex,
+}
+
+impl PathPattern {
+    pub fn new(pattern: &str) -> Result<Self> {
+        let pattern = pattern.trim();
+        let anchor = if pattern.starts_with('/') {
+            "^"
+        } else {
+            "(?:^|/)"
+        };
+        let re = Regex::new(&format!("{anchor}{}$", glob_to_regex(pattern)))?;
+        Ok(Self { re })
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        self.re.is_match(path)
+    }
+}
+
+fn glob_to_regex(glob: &str) -> String {
+    let mut out = String::with_capacity(glob.len() * 2);
+    let mut chars = glob.chars().peekable();
+    let mut in_braces = false;
+    while let Some(ch) = chars.next() {
+        match ch {
+            '*' => {
+                if chars.peek() == Some(&'*') {
+                    chars.next();
+                    if chars.peek() == Some(&'/') {
+                        chars.next();
+                        out.push_str("(?:.*/)?");
+                    } else {
+                        out.push_str(".*");
+                    }
+                } else {
+                    out.push_str("[^/]*");
+                }
+            }
+            '?' => out.push_str("[^/]"),
+            '[' => {
+                let mut class = String::from("[");
+                let mut closed = false;
+                for next in chars.by_ref() {
+                    if next == ']' {
+                        closed = true;
+                        break;
+                    }
+                    if next == '\\' {
+                        class.push_str("\\\\");
+                    } else {
+                        class.push(next);
+                    }
+                }
+                if closed {
+                    out.push_str(&class.replacen("[!", "[^", 1));
+                    out.push(']');
+                } else {
+                    out.push_str(&regex::escape(&class));
+                }
+            }
+            '{' => {
+                in_braces = true;
+                out.push_str("(?:");
+            }
+            '}' if in_braces => {
+                in_braces = false;
+                out.push(')');
+            }
+            ',' if in_braces => out.push('|'),
+            _ => out.push_str(&regex::escape(&ch.to_string())),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claude_tool_files() {
+        let input = r#"{"file_path":"/repo/src/main.rs","old_string":"a","new_string":"b"}"#;
+        assert_eq!(tool_files("Edit", input), vec!["/repo/src/main.rs"]);
+        let input = r#"{"notebook_path":"/repo/nb.ipynb","new_source":""}"#;
+        assert_eq!(tool_files("NotebookEdit", input), vec!["/repo/nb.ipynb"]);
+        assert!(tool_files("Bash", r#"{"command":"ls"}"#).is_empty());
+    }
+
+    #[test]
+    fn test_codex_patch_files() {
+        let patch = "*** Begin Patch\n*** Update File: src/a.rs\n@@\n-x\n+y\n*** Add File: src/b.rs\n+new\n*** End Patch";
+        assert_eq!(
+            tool_files("apply_patch", patch),
+            vec!["src/a.rs", "src/b.rs"]
+        );
+
+        let shell = serde_json::json!({ "command": ["apply_patch", patch] }).to_string();
+        assert_eq!(tool_files("shell", &shell), vec!["src/a.rs", "src/b.rs"]);
+        assert!(is_modifying_call(Some("shell"), Some(&shell)));
+        assert!(!is_modifying_call(Some("Read"), Some("{}")));
+    }
+
+    #[test]
+    fn test_path_pattern() {
+        let exact = PathPattern::new("src/main.rs").unwrap();
+        assert!(exact.matches("/home/me/proj/src/main.rs"));
+        assert!(exact.matches("src/main.rs"));
+        assert!(!exact.matches("/home/me/proj/xsrc/main.rs"));
+
+        let glob = PathPattern::new("src/*.rs").unwrap();
+        assert!(glob.matches("/repo/src/index.rs"));
+        assert!(!glob.matches("/repo/src/nested/index.rs"));
+
+        let deep = PathPattern::new("/repo/**/*.{toml,json}").unwrap();
+        assert!(deep.matches("/repo/Cargo.toml"));
+        assert!(deep.matches("/repo/a/b/config.json"));
+        assert!(!deep.matches("/other/Cargo.toml"));
+    }
+}
@@ -0,0 +1,290 @@
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// How hybrid search combines the BM25 and vector rankings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FusionStrategy {
+    /// Reciprocal rank fusion: sum of 1 / (k + rank).
+    #[default]
+    Rrf,
+    /// Reciprocal rank fusion with per-component weights.
+    WeightedRrf,
+    /// Weighted sum of scores scaled to 0-1 within each ranking.
+    MinMax,
+    /// Weighted sum of standard scores within each ranking, shifted so the
+    /// lowest hit scores 0.
+    ZScore,
+    /// BM25 alone. Used for quoted queries.
+    Bm25,
+}
+
+impl FusionStrategy {
+    pub fn parse(s: &str) -> anyhow::Result<Self> {
+        <Self as ValueEnum>::from_str(s, true).map_err(|_| {
+            anyhow::anyhow!(
+                "unknown fusion '{s}', options: rrf, weighted-rrf, min-max, z-score, bm25"
+            )
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Fusion {
+    pub strategy: FusionStrategy,
+    pub bm25_weight: f32,
+    pub vector_weight: f32,
+    /// Rank offset for reciprocal rank fusion.
+    pub rrf_k: f32,
+}
+
+impl Default for Fusion {
+    fn default() -> Self {
+        Self {
+            strategy: FusionStrategy::Rrf,
+            bm25_weight: 1.0,
+            vector_weight: 1.0,
+            rrf_k: 60.0,
+        }
+    }
+}
+
+/// A hit's place in one ranking. `rank` is 1-based; `score` is higher for
+/// better hits.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Ranked {
+    #[serde(skip)]
+    pub doc_id: u64,
+    pub rank: usize,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fused {
+    pub doc_id: u64,
+    pub score: f32,
+    pub bm25: Option<Ranked>,
+    pub vector: Option<Ranked>,
+}
+
+impl Fusion {
+    /// Combine two rankings into one score per hit, in no particular order.
+    pub fn fuse(&self, bm25: &[Ranked], vector: &[Ranked]) -> Vec<Fused> {
+        let mut hits: HashMap<u64, Fused> = HashMap::new();
+        for (list, is_bm25) in [(bm25, true), (vector, false)] {
+            for ranked in list {
+                let hit = hits.entry(ranked.doc_id).or_insert(Fused {
+                    doc_id: ranked.doc_id,
+                    score: 0.0,
+                    bm25: None,
+                    vector: None,
+                });
+                if is_bm25 {
+                    hit.bm25 = Some(*ranked);
+                } else {
+                    hit.vector = Some(*ranked);
+                }
+            }
+        }
+        if self.strategy == FusionStrategy::Bm25 {
+            hits.retain(|_, hit| hit.bm25.is_some());
+        }
+
+        let (bm25_weight, vector_weight) = match self.strategy {
+            FusionStrategy::Rrf => (1.0, 1.0),
+            FusionStrategy::Bm25 => (1.0, 0.0),
+            _ => (self.bm25_weight, self.vector_weight),
+        };
+        let bm25_norm = Normalizer::new(self.strategy, bm25);
+        let vector_norm = Normalizer::new(self.strategy, vector);
+        for hit in hits.values_mut() {
+            hit.score = match self.strategy {
+                FusionStrategy::Rrf | FusionStrategy::WeightedRrf => {
+                    let rrf = |ranked: Option<Ranked>| {
+                        ranked.map_or(0.0, |r| 1.0 / (self.rrf_k + r.rank as f32))
+                    };
+                    bm25_weight * rrf(hit.bm25) + vector_weight * rrf(hit.vector)
+                }
+                FusionStrategy::MinMax | FusionStrategy::ZScore => {
+                    bm25_weight * bm25_norm.apply(hit.bm25)
+                        + vector_weight * vector_norm.apply(hit.vector)
+                }
+                FusionStrategy::Bm25 => hit.bm25.map_or(0.0, |r| r.score),
+            };
+        }
+
+        let mut fused: Vec<Fused> = hits.into_values().collect();
+        if self.strategy == FusionStrategy::ZScore
+            && let Some(min) = fused.iter().map(|hit| hit.score).reduce(f32::min)
+        {
+            for hit in &mut fused {
+                hit.score -= min;
+            }
+        }
+        fused
+    }
+}
+
+/// Maps one ranking's scores onto a common scale. A hit missing from the
+/// ranking gets that ranking's lowest value.
+struct Normalizer {
+    offset: f32,
+    scale: f32,
+    missing: f32,
+}
+
+impl Normalizer {
+    fn new(strategy: FusionStrategy, list: &[Ranked]) -> Self {
+        let identity = Self {
+            offset: 0.0,
+            scale: 1.0,
+            missing: 0.0,
+        };
+        if list.is_empty() {
+            return identity;
+        }
+        let scores = list.iter().map(|r| r.score);
+        match strategy {
+            FusionStrategy::MinMax => {
+                let min = scores.clone().fold(f32::INFINITY, f32::min);
+                let max = scores.fold(f32::NEG_INFINITY, f32::max);
+                let range = max - min;
+                Self {
+                    offset: if range > 0.0 { min } else { min - 1.0 },
+                    scale: if range > 0.0 { 1.0 / range } else { 1.0 },
+                    missing: 0.0,
+                }
+            }
+            FusionStrategy::ZScore => {
+                let n = list.len() as f32;
+                let mean = scores.clone().sum::<f32>() / n;
+                let std = (scores.map(|s| (s - mean).powi(2)).sum::<f32>() / n).sqrt();
+                let scale = if std > 0.0 { 1.0 / std } else { 0.0 };
+                let min = list.iter().map(|r| r.score).fold(f32::INFINITY, f32::min);
+                Self {
+                    offset: mean,
+                    scale,
+                    missing: (min - mean) * scale,
+                }
+            }
+            _ => identity,
+        }
+    }
+
+    fn apply(&self, ranked: Option<Ranked>) -> f32 {
+        ranked.map_or(self.missing, |r| (r.score - self.offset) * self.scale)
+    }
+}
+
+/// Whether `query` is a single quoted phrase, which hybrid search answers
+/// with BM25 alone.
+pub fn is_quoted(query: &str) -> bool {
+    let query = query.trim();
+    query.len() >= 2
+        && query.starts_with('"')
+        && query.ends_with('"')
+        && !query[1..query.len() - 1].contains('"')
+}
+
+/// Where a hit's score came from, for `search --explain`.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct Explain {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<FusionStrategy>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bm25: Option<Ranked>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vector: Option<VectorRank>,
+    /// Score before recency: the fused score in hybrid mode, otherwise the
+    /// component's own.
+    pub base: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rerank: Option<f32>,
+    /// Factor `apply_recency` multiplied the score by (applied to `rerank`
+    /// when present, else `base`).
+    pub recency: f32,
+    pub score: f32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct VectorRank {
+    pub rank: usize,
+    pub distance: f32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranking(scores: &[(u64, f32)]) -> Vec<Ranked> {
+        scores
+            .iter()
+            .enumerate()
+            .map(|(i, (doc_id, score))| Ranked {
+                doc_id: *doc_id,
+                rank: i + 1,
+                score: *score,
+            })
+            .collect()
+    }
+
+    fn ordered(fusion: Fusion, bm25: &[Ranked], vector: &[Ranked]) -> Vec<(u64, f32)> {
+        let mut fused: Vec<(u64, f32)> = fusion
+            .fuse(bm25, vector)
+            .into_iter()
+            .map(|hit| (hit.doc_id, hit.score))
+            .collect();
+        fused.sort_by(|a, b| b.1.total_cmp(&a.1).then(a.0.cmp(&b.0)));
+        fused
+    }
+
+    #[test]
+    fn test_strategies() {
+        let bm25 = ranking(&[(1, 12.0), (2, 6.0), (3, 2.0)]);
+        let vector = ranking(&[(3, 0.9), (4, 0.8), (1, 0.5)]);
+
+        let rrf = ordered(Fusion::default(), &bm25, &vector);
+        assert_eq!(rrf[0].0, 1);
+        assert!((rrf[0].1 - (1.0 / 61.0 + 1.0 / 63.0)).abs() < 1e-6);
+
+        let vector_heavy = Fusion {
+            strategy: FusionStrategy::WeightedRrf,
+            bm25_weight: 0.2,
+            ..Fusion::default()
+        };
+        assert_eq!(ordered(vector_heavy, &bm25, &vector)[0].0, 3);
+
+        let min_max = Fusion {
+            strategy: FusionStrategy::MinMax,
+            ..Fusion::default()
+        };
+        let scores = ordered(min_max, &bm25, &vector);
+        assert_eq!(scores[0], (1, 1.0));
+        let only_vector = scores.iter().find(|(id, _)| *id == 4).unwrap().1;
+        assert!((only_vector - 0.75).abs() < 1e-6);
+
+        let z = Fusion {
+            strategy: FusionStrategy::ZScore,
+            ..Fusion::default()
+        };
+        let scores = ordered(z, &bm25, &vector);
+        assert_eq!(scores.last().unwrap().1, 0.0);
+
+        let bm25_only = Fusion {
+            strategy: FusionStrategy::Bm25,
+            ..Fusion::default()
+        };
+        assert_eq!(
+            ordered(bm25_only, &bm25, &vector),
+            vec![(1, 12.0), (2, 6.0), (3, 2.0)]
+        );
+    }
+
+    #[test]
+    fn test_is_quoted() {
+        assert!(is_quoted("\"connection reset by peer\""));
+        assert!(!is_quoted("\"a\" and \"b\""));
+        assert!(!is_quoted("connection reset"));
+    }
+}
@@ -1,14 +1,25 @@
 use crate::types::Record;
 use anyhow::{Result, anyhow};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ops::Bound;
-use tantivy::collector::TopDocs;
-use tantivy::query::{AllQuery, BooleanQuery, Occur, Query, RangeQuery, TermQuery};
-use tantivy::schema::Value;
+use tantivy::aggregation::agg_req::Aggregations;
+use tantivy::aggregation::{AggregationCollector, AggregationLimits};
+use tantivy::collector::{Count, DocSetCollector, TopDocs};
+use tantivy::columnar::StrColumn;
+use tantivy::query::{
+    AllQuery, BooleanQuery, BoostQuery, ConstScoreQuery, FuzzyTermQuery, MoreLikeThisQuery, Occur,
+    Query, RangeQuery, RegexQuery, TermQuery, TermSetQuery,
+};
 use tantivy::schema::{
-    FAST, Field, INDEXED, IndexRecordOption, STORED, STRING, Schema, SchemaBuilder, TEXT,
+    FAST, Field, FieldType, INDEXED, IndexRecordOption, STORED, STRING, Schema, SchemaBuilder,
     TextFieldIndexing, TextOptions,
 };
-use tantivy::{Index, IndexReader, IndexWriter, Order, TantivyDocument, Term};
+use tantivy::schema::{OwnedValue, Value};
+use tantivy::snippet::SnippetGenerator;
+use tantivy::tokenizer::TokenStream;
+use tantivy::{DocId, Index, IndexReader, IndexWriter, Order, Searcher, TantivyDocument, Term};
 
 #[derive(Clone)]
 pub struct IndexFields {
@@ -23,6 +34,13 @@ pub struct IndexFields {
     pub tool_name: Field,
     pub tool_input: Field,
     pub tool_output: Field,
+    pub files: Option<Field>,
+    pub command: Option<Field>,
+    pub exit_code: Option<Field>,
+    pub tool_call_id: Option<Field>,
+    pub patches: Option<Field>,
+    /// Whether a tool call writes to the files it names.
+    pub modified: Option<Field>,
     pub source_path: Field,
 }
 
@@ -42,22 +60,79 @@ pub struct QueryOptions {
     pub source: Option<crate::types::SourceFilter>,
     pub since: Option<u64>,
     pub until: Option<u64>,
+    /// Also match free-text words within this edit distance (at most 2) or as
+    /// prefixes, ranked below exact matches.
+    pub fuzzy: Option<u8>,
     pub limit: usize,
 }
 
+/// Terms of a seed text `more_like_this` queries with, most distinctive first.
+const MLT_MAX_TERMS: usize = 40;
+
+/// Words too common in conversation to make two records alike.
+const MLT_STOP_WORDS: &[&str] = &[
+    "and", "are", "but", "can", "did", "does", "for", "from", "had", "has", "have", "how", "its",
+    "let", "not", "now", "our", "out", "that", "the", "then", "there", "these", "this", "use",
+    "was", "what", "when", "where", "which", "while", "who", "why", "will", "with", "you", "your",
+];
+
+/// Score factor for fuzzy matches, low enough that exact matches rank first.
+const FUZZY_BOOST: f32 = 0.01;
+
+/// Fields `facets` counts hits by. They must be fast fields.
+const FACET_FIELDS: [&str; 5] = ["project", "role", "tool_name", "source", "session_id"];
+
+const DAY_MS: u64 = 86_400_000;
+
+/// Hit counts for a query, grouped by field.
+#[derive(Debug, Clone, Serialize)]
+pub struct Facets {
+    pub total: usize,
+    pub project: Vec<FacetCount>,
+    pub role: Vec<FacetCount>,
+    pub tool_name: Vec<FacetCount>,
+    pub source: Vec<FacetCount>,
+    pub session_id: Vec<FacetCount>,
+    pub month: Vec<FacetCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FacetCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// Version of the schema `build_schema` produces. Bump it when a change needs
+/// existing indexes rebuilt through `migrate`.
+pub const SCHEMA_VERSION: u32 = 4;
+
+/// File in the index directory recording the schema version it was built
+/// with. Indexes from before versioning have none and count as version 1.
+const SCHEMA_VERSION_FILE: &str = "memex_schema_version";
+
+/// File in the index directory recording the oldest schema version any of
+/// its records was ingested at. `migrate` keeps it: a migrated record only
+/// holds what the memex that read its transcript stored.
+const INGEST_VERSION_FILE: &str = "memex_ingest_version";
+
+/// First schema version whose ingest stores patches, tool call ids and exit
+/// codes. `migrate` can't derive them from older records.
+pub const TOOL_DETAILS_VERSION: u32 = 2;
+
 impl SearchIndex {
     pub fn open_or_create(dir: &std::path::Path) -> Result<Self> {
         let meta_path = dir.join("meta.json");
-        if meta_path.exists() {
-            let index = Index::open_in_dir(dir)?;
-            let fields = load_fields(index.schema())?;
-            Ok(Self { index, fields })
+        let index = if meta_path.exists() {
+            Index::open_in_dir(dir)?
         } else {
-            let schema = build_schema()?;
-            let index = Index::create_in_dir(dir, schema.clone())?;
-            let fields = load_fields(schema)?;
-            Ok(Self { index, fields })
-        }
+            let index = Index::create_in_dir(dir, build_schema()?)?;
+            std::fs::write(dir.join(SCHEMA_VERSION_FILE), SCHEMA_VERSION.to_string())?;
+            std::fs::write(dir.join(INGEST_VERSION_FILE), SCHEMA_VERSION.to_string())?;
+            index
+        };
+        crate::tokenizer::register(&index);
+        let fields = load_fields(index.schema())?;
+        Ok(Self { index, fields })
     }
 
     pub fn writer(&self) -> Result<IndexWriter> {
@@ -94,14 +169,45 @@ impl SearchIndex {
         if let Some(tool_output) = &record.tool_output {
             doc.add_text(self.fields.tool_output, tool_output);
         }
+        if let Some(field) = self.fields.files {
+            for path in &record.files {
+                doc.add_text(field, path);
+            }
+        }
+        if let (Some(field), Some(command)) = (self.fields.command, &record.command) {
+            doc.add_text(field, command);
+        }
+        if let (Some(field), Some(code)) = (self.fields.exit_code, record.exit_code) {
+            doc.add_i64(field, code);
+        }
+        if let (Some(field), Some(id)) = (self.fields.tool_call_id, &record.tool_call_id) {
+            doc.add_text(field, id);
+        }
+        if let Some(field) = self.fields.patches
+            && !record.patches.is_empty()
+        {
+            doc.add_text(field, serde_json::to_string(&record.patches)?);
+        }
+        if let Some(field) = self.fields.modified {
+            doc.add_bool(
+                field,
+                crate::files::is_modifying_call(
+                    record.tool_name.as_deref(),
+                    record.tool_input.as_deref(),
+                ),
+            );
+        }
         doc.add_text(self.fields.source_path, &record.source_path);
         writer.add_document(doc)?;
         Ok(())
     }
 
     pub fn get_by_doc_id(&self, doc_id: u64) -> Result<Option<Record>> {
-        let reader = self.reader()?;
-        let searcher = reader.searcher();
+        self.get_by_doc_id_in(&self.reader()?.searcher(), doc_id)
+    }
+
+    /// The record with `doc_id` as `searcher` sees the index.
+    pub fn get_by_doc_id_in(&self, searcher: &Searcher, doc_id: u64) -> Result<Option<Record>> {
         let term = Term::from_field_u64(self.fields.doc_id, doc_id);
         let query = TermQuery::new(term, IndexRecordOption::Basic);
         let top = searcher.search(&query, &TopDocs::with_limit(1))?;
@@ -125,6 +231,324 @@ impl SearchIndex {
         Ok(results)
     }
 
+    /// A snippet generator highlighting the `text` terms of `options.query`, with
+    /// fragments of at most `max_chars` bytes. `None` for an empty query.
+    pub fn snippet_generator(
+        &self,
+        options: &QueryOptions,
+        max_chars: usize,
+    ) -> Result<Option<SnippetGenerator>> {
+        let Some(query) = text_query(&self.fields, options, &self.index) else {
+            return Ok(None);
+        };
+        let searcher = self.reader()?.searcher();
+        let mut generator = SnippetGenerator::create(&searcher, &*query, self.fields.text)?;
+        generator.set_max_num_chars(max_chars);
+        Ok(Some(generator))
+    }
+
+    /// Records whose text matches `regex` and the filters in `options`, scored
+    /// by match count. Literals the pattern requires narrow the candidates
+    /// through the term dictionary; otherwise every stored record is scanned.
+    pub fn regex_search(
+        &self,
+        regex: &regex::Regex,
+        options: &QueryOptions,
+    ) -> Result<Vec<(f32, Record)>> {
+        let reader = self.reader()?;
+        let searcher = reader.searcher();
+        let mut clauses = filter_clauses(&self.fields, options);
+        if let Some(prefilter) = regex_prefilter(&self.index, self.fields.text, regex.as_str()) {
+            clauses.push((Occur::Must, prefilter));
+        }
+        let candidates: Vec<tantivy::DocAddress> = if clauses.is_empty() {
+            searcher.search(&AllQuery, &DocSetCollector)?
+        } else {
+            searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)?
+        }
+        .into_iter()
+        .collect();
+
+        let mut results = candidates
+            .par_iter()
+            .map(|addr| -> Result<Option<(f32, Record)>> {
+                let doc = searcher.doc::<TantivyDocument>(*addr)?;
+                let record = record_from_doc(&self.fields, &doc);
+                let count = regex.find_iter(&record.text).count();
+                Ok((count > 0).then_some((count as f32, record)))
+            })
+            .filter_map(|result| result.transpose())
+            .collect::<Result<Vec<_>>>()?;
+        results.sort_by(|(a_score, a), (b_score, b)| {
+            b_score.total_cmp(a_score).then_with(|| b.ts.cmp(&a.ts))
+        });
+        results.truncate(options.limit);
+        Ok(results)
+    }
+
+    /// Doc ids of the records passing `options`' project, role, tool, source,
+    /// session and time filters, ignoring its query text. `None` when no
+    /// filter is set.
+    pub fn filtered_doc_ids(&self, options: &QueryOptions) -> Result<Option<HashSet<u64>>> {
+        let clauses = filter_clauses(&self.fields, options);
+        if clauses.is_empty() {
+            return Ok(None);
+        }
+        let searcher = self.reader()?.searcher();
+        let mut by_segment: BTreeMap<u32, Vec<DocId>> = BTreeMap::new();
+        for addr in searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)? {
+            by_segment
+                .entry(addr.segment_ord)
+                .or_default()
+                .push(addr.doc_id);
+        }
+        let mut ids = HashSet::new();
+        for (segment_ord, docs) in by_segment {
+            let doc_id = searcher
+                .segment_reader(segment_ord)
+                .fast_fields()
+                .u64("doc_id")?;
+            ids.extend(docs.into_iter().filter_map(|doc| doc_id.first(doc)));
+        }
+        Ok(Some(ids))
+    }
+
+    /// Doc ids of the records read from any of `source_paths`.
+    pub fn source_doc_ids(&self, source_paths: &[String]) -> Result<HashSet<u64>> {
+        if source_paths.is_empty() {
+            return Ok(HashSet::new());
+        }
+        let clauses = source_paths
+            .iter()
+            .map(|path| -> (Occur, Box<dyn Query>) {
+                let term = Term::from_field_text(self.fields.source_path, path);
+                (
+                    Occur::Should,
+                    Box::new(TermQuery::new(term, IndexRecordOption::Basic)),
+                )
+            })
+            .collect();
+        let searcher = self.reader()?.searcher();
+        let mut ids = HashSet::new();
+        for addr in searcher.search(&BooleanQuery::new(clauses), &DocSetCollector)? {
+            let doc_id = searcher
+                .segment_reader(addr.segment_ord)
+                .fast_fields()
+                .u64("doc_id")?;
+            ids.extend(doc_id.first(addr.doc_id));
+        }
+        Ok(ids)
+    }
+
+    /// Records whose text shares distinctive terms with `text`, by tantivy's
+    /// MoreLikeThis, restricted by the filters in `options`. `options.query`
+    /// is ignored.
+    pub fn more_like_this(&self, text: &str, options: &QueryOptions) -> Result<Vec<(f32, Record)>> {
+        let reader = self.reader()?;
+        let searcher = reader.searcher();
+        let similar = MoreLikeThisQuery::builder()
+            .with_min_doc_frequency(1)
+            .with_min_term_frequency(1)
+            .with_min_word_length(3)
+            .with_max_query_terms(MLT_MAX_TERMS)
+            .with_stop_words(MLT_STOP_WORDS.iter().map(|w| w.to_string()).collect())
+            .with_document_fields(vec![(
+                self.fields.text,
+                vec![OwnedValue::Str(text.to_string())],
+            )]);
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = vec![(Occur::Must, Box::new(similar))];
+        clauses.extend(filter_clauses(&self.fields, options));
+        let query = BooleanQuery::new(clauses);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(options.limit))?;
+        let mut results = Vec::with_capacity(top_docs.len());
+        for (score, addr) in top_docs {
+            let doc = searcher.doc::<TantivyDocument>(addr)?;
+            results.push((score, record_from_doc(&self.fields, &doc)));
+        }
+        Ok(results)
+    }
+
+    /// Hit counts for `options` grouped by project, role, tool, source,
+    /// session and month. Each group keeps its `size` largest buckets; months
+    /// are all kept, oldest first.
+    pub fn facets(&self, options: &QueryOptions, size: usize) -> Result<Facets> {
+        self.require_fast_fields()?;
+        let mut aggs = serde_json::Map::new();
+        for name in FACET_FIELDS {
+            aggs.insert(
+                name.to_string(),
+                serde_json::json!({ "terms": { "field": name, "size": size } }),
+            );
+        }
+        aggs.insert(
+            "days".to_string(),
+            serde_json::json!({
+                "histogram": { "field": "ts", "interval": DAY_MS, "min_doc_count": 1 }
+            }),
+        );
+        let aggs: Aggregations = serde_json::from_value(serde_json::Value::Object(aggs))?;
+        let collector = AggregationCollector::from_aggs(aggs, AggregationLimits::default());
+
+        let query = build_query(&self.fields, options, &self.index)?;
+        let searcher = self.reader()?.searcher();
+        let (total, results) = searcher.search(&query, &(Count, collector))?;
+        let results = serde_json::to_value(results)?;
+        let buckets = |name: &str| -> Vec<(serde_json::Value, usize)> {
+            results[name]["buckets"]
+                .as_array()
+                .map(|buckets| {
+                    buckets
+                        .iter()
+                        .map(|bucket| {
+                            let count = bucket["doc_count"].as_u64().unwrap_or(0) as usize;
+                            (bucket["key"].clone(), count)
+                        })
+                        .collect()
+                })
+                .unwrap_or_default()
+        };
+        let terms = |name: &str| -> Vec<FacetCount> {
+            buckets(name)
+                .into_iter()
+                .map(|(key, count)| FacetCount {
+                    value: key.as_str().unwrap_or_default().to_string(),
+                    count,
+                })
+                .collect()
+        };
+
+        let mut months: Vec<FacetCount> = Vec::new();
+        for (key, count) in buckets("days") {
+            let Some(day) = key
+                .as_f64()
+                .and_then(|ms| chrono::DateTime::<chrono::Utc>::from_timestamp_millis(ms as i64))
+            else {
+                continue;
+            };
+            let month = day.format("%Y-%m").to_string();
+            match months.last_mut() {
+                Some(last) if last.value == month => last.count += count,
+                _ => months.push(FacetCount {
+                    value: month,
+                    count,
+                }),
+            }
+        }
+
+        Ok(Facets {
+            total,
+            project: terms("project"),
+            role: terms("role"),
+            tool_name: terms("tool_name"),
+            source: terms("source"),
+            session_id: terms("session_id"),
+            month: months,
+        })
+    }
+
+    /// Add the terms the index stores for `text` to `counts`.
+    pub fn count_terms(&self, text: &str, counts: &mut HashMap<String, u32>) -> Result<()> {
+        let mut analyzer = self.index.tokenizer_for_field(self.fields.text)?;
+        let mut stream = analyzer.token_stream(text);
+        while stream.advance() {
+            let term = &stream.token().text;
+            if term.len() < 3
+                || term.bytes().all(|b| b.is_ascii_digit() || b == b'.')
+                || MLT_STOP_WORDS.contains(&term.as_str())
+            {
+                continue;
+            }
+            *counts.entry(term.clone()).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    /// The `n` terms of `counts` that best tell its text apart from the rest
+    /// of the index: BM25's saturated term frequency times inverse document
+    /// frequency. Terms seen once are skipped.
+    pub fn top_terms(&self, counts: &HashMap<String, u32>, n: usize) -> Result<Vec<String>> {
+        const K1: f32 = 1.2;
+        let searcher = self.reader()?.searcher();
+        let docs = searcher.num_docs() as f32;
+        let mut weighted = Vec::new();
+        for (term, &tf) in counts {
+            if tf < 2 {
+                continue;
+            }
+            let df = searcher.doc_freq(&Term::from_field_text(self.fields.text, term))? as f32;
+            let idf = (1.0 + (docs - df + 0.5) / (df + 0.5)).ln();
+            let tf = tf as f32;
+            weighted.push((tf * (K1 + 1.0) / (tf + K1) * idf, term));
+        }
+        weighted.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        Ok(weighted
+            .into_iter()
+            .take(n)
+            .map(|(_, term)| term.clone())
+            .collect())
+    }
+
+    /// Timestamp, project, session, role and source of every record matching
+    /// `options`, read from fast fields without loading stored documents.
+    pub fn activity_events(
+        &self,
+        options: &QueryOptions,
+    ) -> Result<Vec<crate::activity::ActivityEvent>> {
+        self.require_fast_fields()?;
+        let query = build_query(&self.fields, options, &self.index)?;
+        let searcher = self.reader()?.searcher();
+        let mut by_segment: BTreeMap<u32, Vec<DocId>> = BTreeMap::new();
+        for addr in searcher.search(&query, &DocSetCollector)? {
+            by_segment
+                .entry(addr.segment_ord)
+                .or_default()
+                .push(addr.doc_id);
+        }
+
+        let mut events = Vec::new();
+        for (segment_ord, docs) in by_segment {
+            let fast = searcher.segment_reader(segment_ord).fast_fields();
+            let ts = fast.u64("ts")?;
+            let column = |name: &str| -> Result<StrValues> {
+                let column = fast
+                    .str(name)?
+                    .ok_or_else(|| anyhow!("missing fast field {name}"))?;
+                Ok(StrValues::new(column))
+            };
+            let mut project = column("project")?;
+            let mut session_id = column("session_id")?;
+            let mut role = column("role")?;
+            let mut source = column("source")?;
+            for doc in docs {
+                events.push(crate::activity::ActivityEvent {
+                    ts: ts.first(doc).unwrap_or(0),
+                    project: project.get(doc)?,
+                    session_id: session_id.get(doc)?,
+                    role: role.get(doc)?,
+                    source: source.get(doc)?,
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    /// Fail unless the fields `facets` and `activity_events` aggregate over
+    /// are fast fields, which indexes from before schema version 3 lack.
+    fn require_fast_fields(&self) -> Result<()> {
+        let schema = self.index.schema();
+        let fast = FACET_FIELDS.iter().all(|name| {
+            schema
+                .get_field(name)
+                .is_ok_and(|field| schema.get_field_entry(field).is_fast())
+        });
+        if !fast {
+            return Err(anyhow!(
+                "index predates fast-field aggregations; run `memex migrate` to upgrade it"
+            ));
+        }
+        Ok(())
+    }
+
     pub fn records_by_session_id(&self, session_id: &str) -> Result<Vec<Record>> {
         let reader = self.reader()?;
         let searcher = reader.searcher();
@@ -140,6 +564,41 @@ impl SearchIndex {
         Ok(records)
     }
 
+    /// The `tool_result` for a `tool_use` record, or the `tool_use` for a result.
+    pub fn paired_record(&self, record: &Record) -> Result<Option<Record>> {
+        let (Some(field), Some(call_id)) = (self.fields.tool_call_id, &record.tool_call_id) else {
+            return Ok(None);
+        };
+        let reader = self.reader()?;
+        let searcher = reader.searcher();
+        let clauses: Vec<(Occur, Box<dyn Query>)> = vec![
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(field, call_id),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(self.fields.session_id, &record.session_id),
+                    IndexRecordOption::Basic,
+                )),
+            ),
+        ];
+        let query = BooleanQuery::new(clauses);
+        let top_docs = searcher.search(&query, &TopDocs::with_limit(8))?;
+        for (_score, addr) in top_docs {
+            let doc = searcher.doc::<TantivyDocument>(addr)?;
+            let candidate = record_from_doc(&self.fields, &doc);
+            if candidate.doc_id != record.doc_id && candidate.role != record.role {
+                return Ok(Some(candidate));
+            }
+        }
+        Ok(None)
+    }
+
     pub fn recent_records(&self, limit: usize) -> Result<Vec<Record>> {
         let reader = self.reader()?;
         let searcher = reader.searcher();
@@ -155,6 +614,176 @@ impl SearchIndex {
         Ok(records)
     }
 
+    /// Distinct indexed terms of a raw (`STRING`) field accepted by `keep`.
+    fn field_terms<F>(&self, field: Field, keep: F) -> Result<Vec<String>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let reader = self.reader()?;
+        let searcher = reader.searcher();
+        let mut out = BTreeSet::new();
+        for segment_reader in searcher.segment_readers() {
+            let inverted = segment_reader.inverted_index(field)?;
+            let mut stream = inverted.terms().stream()?;
+            while stream.advance() {
+                let Ok(term) = std::str::from_utf8(stream.key()) else {
+                    continue;
+                };
+                if keep(term) && !out.contains(term) {
+                    out.insert(term.to_string());
+                }
+            }
+        }
+        Ok(out.into_iter().collect())
+    }
+
+    /// Records naming an indexed file path accepted by `keep`, newest first.
+    /// With `modified_only`, only tool calls that write to their files.
+    pub fn records_by_files<F>(
+        &self,
+        keep: F,
+        modified_only: bool,
+        options: &QueryOptions,
+    ) -> Result<Vec<Record>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let Some(files_field) = self.fields.files else {
+            return Err(anyhow!(
+                "index predates the files field; run `memex migrate` to upgrade it"
+            ));
+        };
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        if modified_only {
+            let Some(modified) = self.fields.modified else {
+                return Err(anyhow!(
+                    "index predates the modified field; run `memex migrate` to upgrade it"
+                ));
+            };
+            clauses.push((
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_bool(modified, true),
+                    IndexRecordOption::Basic,
+                )),
+            ));
+        }
+        self.records_matching_terms(files_field, keep, clauses, options)
+    }
+
+    /// Shell tool calls and results whose command is accepted by `keep`, newest first.
+    /// With `failed_only`, only the results of commands that exited non-zero.
+    pub fn records_by_commands<F>(
+        &self,
+        keep: F,
+        failed_only: bool,
+        options: &QueryOptions,
+    ) -> Result<Vec<Record>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let Some(command_field) = self.fields.command else {
+            return Err(anyhow!(
+                "index predates the command field; run `memex migrate` to upgrade it"
+            ));
+        };
+        let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+        if failed_only {
+            let exit_code = |lower, upper| -> (Occur, Box<dyn Query>) {
+                (
+                    Occur::Should,
+                    Box::new(RangeQuery::new_i64_bounds(
+                        "exit_code".to_string(),
+                        lower,
+                        upper,
+                    )),
+                )
+            };
+            let nonzero = BooleanQuery::new(vec![
+                exit_code(Bound::Unbounded, Bound::Excluded(0)),
+                exit_code(Bound::Excluded(0), Bound::Unbounded),
+            ]);
+            clauses.push((Occur::Must, Box::new(nonzero)));
+        }
+        self.records_matching_terms(command_field, keep, clauses, options)
+    }
+
+    /// The call that ran a shell `result`: the one with its call id, else the
+    /// session's latest call of the same command at or before it.
+    pub fn command_call(&self, result: &Record) -> Result<Option<Record>> {
+        if let Some(call) = self.paired_record(result)? {
+            return Ok(Some(call));
+        }
+        let (Some(command_field), Some(command)) = (self.fields.command, &result.command) else {
+            return Ok(None);
+        };
+        let term = |field, value: &str| -> (Occur, Box<dyn Query>) {
+            (
+                Occur::Must,
+                Box::new(TermQuery::new(
+                    Term::from_field_text(field, value),
+                    IndexRecordOption::Basic,
+                )),
+            )
+        };
+        let query = BooleanQuery::new(vec![
+            term(self.fields.session_id, &result.session_id),
+            term(command_field, command),
+            term(self.fields.role, "tool_use"),
+            (
+                Occur::Must,
+                Box::new(RangeQuery::new_u64_bounds(
+                    "ts".to_string(),
+                    Bound::Unbounded,
+                    Bound::Included(result.ts),
+                )),
+            ),
+        ]);
+        let searcher = self.reader()?.searcher();
+        let collector = TopDocs::with_limit(1).order_by_fast_field::<u64>("ts", Order::Desc);
+        let top_docs: Vec<(u64, tantivy::DocAddress)> = searcher.search(&query, &collector)?;
+        let Some((_, addr)) = top_docs.first() else {
+            return Ok(None);
+        };
+        let doc = searcher.doc::<TantivyDocument>(*addr)?;
+        Ok(Some(record_from_doc(&self.fields, &doc)))
+    }
+
+    /// Records whose `field` holds a term accepted by `keep` and that match
+    /// every one of `clauses` and `options`' filters, newest first.
+    fn records_matching_terms<F>(
+        &self,
+        field: Field,
+        keep: F,
+        mut clauses: Vec<(Occur, Box<dyn Query>)>,
+        options: &QueryOptions,
+    ) -> Result<Vec<Record>>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let values = self.field_terms(field, keep)?;
+        if values.is_empty() {
+            return Ok(Vec::new());
+        }
+        let reader = self.reader()?;
+        let searcher = reader.searcher();
+        let terms = values
+            .iter()
+            .map(|value| Term::from_field_text(field, value));
+        clauses.push((Occur::Must, Box::new(TermSetQuery::new(terms))));
+        clauses.extend(filter_clauses(&self.fields, options));
+        let query = BooleanQuery::new(clauses);
+        let collector =
+            TopDocs::with_limit(options.limit.max(1)).order_by_fast_field::<u64>("ts", Order::Desc);
+        let top_docs: Vec<(u64, tantivy::DocAddress)> = searcher.search(&query, &collector)?;
+        let mut records = Vec::with_capacity(top_docs.len());
+        for (_ts, addr) in top_docs {
+            let doc = searcher.doc::<TantivyDocument>(addr)?;
+            records.push(record_from_doc(&self.fields, &doc));
+        }
+        Ok(records)
+    }
+
     pub fn doc_count(&self) -> Result<usize> {
         let reader = self.reader()?;
         Ok(reader.searcher().num_docs() as usize)
@@ -178,28 +807,63 @@ impl SearchIndex {
     }
 }
 
+/// Values of a string fast field, with each term looked up once.
+struct StrValues {
+    column: StrColumn,
+    terms: HashMap<u64, String>,
+}
+
+impl StrValues {
+    fn new(column: StrColumn) -> Self {
+        Self {
+            column,
+            terms: HashMap::new(),
+        }
+    }
+
+    /// The first value of `doc`, or an empty string.
+    fn get(&mut self, doc: DocId) -> Result<String> {
+        let Some(ord) = self.column.ords().first(doc) else {
+            return Ok(String::new());
+        };
+        if let Some(term) = self.terms.get(&ord) {
+            return Ok(term.clone());
+        }
+        let mut term = String::new();
+        self.column.ord_to_str(ord, &mut term)?;
+        self.terms.insert(ord, term.clone());
+        Ok(term)
+    }
+}
+
 fn build_schema() -> Result<Schema> {
     let mut builder = SchemaBuilder::default();
 
     builder.add_u64_field("doc_id", INDEXED | STORED | FAST);
     builder.add_u64_field("ts", INDEXED | STORED | FAST);
-    builder.add_text_field("project", STRING | STORED);
-    builder.add_text_field("session_id", STRING | STORED);
+    builder.add_text_field("project", STRING | STORED | FAST);
+    builder.add_text_field("session_id", STRING | STORED | FAST);
     builder.add_u64_field("turn_id", INDEXED | STORED | FAST);
-    builder.add_text_field("role", STRING | STORED);
-    builder.add_text_field("source", STRING | STORED);
+    builder.add_text_field("role", STRING | STORED | FAST);
+    builder.add_text_field("source", STRING | STORED | FAST);
 
     let text_indexing = TextFieldIndexing::default()
-        .set_tokenizer("default")
+        .set_tokenizer(crate::tokenizer::CODE_TOKENIZER)
         .set_index_option(IndexRecordOption::WithFreqsAndPositions);
     let text_options = TextOptions::default()
         .set_indexing_options(text_indexing)
         .set_stored();
-    builder.add_text_field("text", text_options);
+    builder.add_text_field("text", text_options.clone());
 
-    builder.add_text_field("tool_name", STRING | STORED);
-    builder.add_text_field("tool_input", TEXT | STORED);
-    builder.add_text_field("tool_output", TEXT | STORED);
+    builder.add_text_field("tool_name", STRING | STORED | FAST);
+    builder.add_text_field("tool_input", text_options.clone());
+    builder.add_text_field("tool_output", text_options);
+    builder.add_text_field("files", STRING | STORED);
+    builder.add_text_field("command", STRING | STORED);
+    builder.add_i64_field("exit_code", INDEXED | STORED | FAST);
+    builder.add_text_field("tool_call_id", STRING | STORED);
+    builder.add_text_field("patches", STORED);
+    builder.add_bool_field("modified", INDEXED);
     builder.add_text_field("source_path", STRING | STORED);
 
     Ok(builder.build())
@@ -223,25 +887,675 @@ fn load_fields(schema: Schema) -> Result<IndexFields> {
         tool_name: get("tool_name")?,
         tool_input: get("tool_input")?,
         tool_output: get("tool_output")?,
+        files: schema.get_field("files").ok(),
+        command: schema.get_field("command").ok(),
+        exit_code: schema.get_field("exit_code").ok(),
+        tool_call_id: schema.get_field("tool_call_id").ok(),
+        patches: schema.get_field("patches").ok(),
+        modified: schema.get_field("modified").ok(),
         source_path: get("source_path")?,
     })
 }
 
+/// Schema version of the index in `dir`.
+pub fn schema_version(dir: &std::path::Path) -> u32 {
+    std::fs::read_to_string(dir.join(SCHEMA_VERSION_FILE))
+        .ok()
+        .and_then(|raw| raw.trim().parse().ok())
+        .unwrap_or(1)
+}
+
+/// Oldest schema version the records of the index in `dir` were ingested
+/// at. Indexes from before this was recorded were ingested at their schema
+/// version.
+pub fn ingest_version(dir: &std::path::Path) -> u32 {
+    std::fs::read_to_string(dir.join(INGEST_VERSION_FILE))
+        .ok()
+        .and_then(|raw| raw.trim().parse().ok())
+        .unwrap_or_else(|| schema_version(dir))
+}
+
+/// Whether the index in `dir` holds records ingested before memex stored
+/// patches, tool call ids and exit codes. Only `memex reindex` adds them.
+pub fn predates_tool_details(dir: &std::path::Path) -> bool {
+    dir.join("meta.json").exists() && ingest_version(dir) < TOOL_DETAILS_VERSION
+}
+
+/// Whether `dir` holds an index built with an older schema.
+pub fn needs_migration(dir: &std::path::Path) -> bool {
+    dir.join("meta.json").exists() && schema_version(dir) < SCHEMA_VERSION
+}
+
+/// Rebuild the index in `dir` with the current schema from its stored
+/// records, re-deriving `files` and `command` from tool inputs. Doc ids are
+/// kept, so vectors and ingest state stay valid. Fields the old ingest never
+/// stored stay empty; see [`predates_tool_details`]. Returns the record count.
+pub fn migrate(dir: &std::path::Path) -> Result<usize> {
+    let ingested = ingest_version(dir);
+    let old = SearchIndex::open_or_create(dir)?;
+    // Keep `memex index` from writing to the old index while it is copied.
+    let lock = tantivy::Directory::acquire_lock(
+        old.index.directory(),
+        &tantivy::directory::INDEX_WRITER_LOCK,
+    )
+    .map_err(|_| anyhow!("index is locked by another memex process; try again later"))?;
+
+    let name = dir
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid index path {}", dir.display()))?;
+    let staging = dir.with_file_name(format!("{name}.migrating"));
+    let backup = dir.with_file_name(format!("{name}.old"));
+    for path in [&staging, &backup] {
+        if path.exists() {
+            std::fs::remove_dir_all(path)?;
+        }
+    }
+    std::fs::create_dir_all(&staging)?;
+
+    let mut count = 0;
+    {
+        let new = SearchIndex::open_or_create(&staging)?;
+        std::fs::write(staging.join(INGEST_VERSION_FILE), ingested.to_string())?;
+        let mut writer = new.writer()?;
+        old.for_each_record(|mut record| {
+            // Older ingest stored Claude results JSON-encoded; newer stores
+            // the text itself, which `output:` queries and exit codes expect.
+            if ingested < TOOL_DETAILS_VERSION
+                && record.source == crate::types::SourceKind::Claude
+                && record.role == "tool_result"
+                && let Some(serde_json::Value::String(text)) = record
+                    .tool_output
+                    .as_deref()
+                    .and_then(|output| serde_json::from_str(output).ok())
+            {
+                record.tool_output = Some(text);
+            }
+            if let (Some(name), Some(input)) = (&record.tool_name, &record.tool_input) {
+                if record.files.is_empty() {
+                    record.files = crate::files::tool_files(name, input);
+                }
+                if record.command.is_none() {
+                    record.command = crate::commands::tool_command(name, input);
+                }
+            }
+            new.add_record(&mut writer, &record)?;
+            count += 1;
+            Ok(())
+        })?;
+        writer.commit()?;
+        writer.wait_merging_threads()?;
+    }
+
+    drop(lock);
+    drop(old);
+    std::fs::rename(dir, &backup)?;
+    std::fs::rename(&staging, dir)?;
+    std::fs::remove_dir_all(&backup)?;
+    Ok(count)
+}
+
+impl QueryOptions {
+    /// Move positive top-level `project:`, `role:`, `tool:`, `session:`,
+    /// `source:`, `since:`, `until:` and `date:` terms from the query into the
+    /// matching filters, so paths that do not parse the query (semantic search,
+    /// post-filtering) honor them too. Flags already set take precedence.
+    pub fn extract_filters(&mut self) {
+        let tokens = split_query(&self.query);
+        let mut kept = Vec::with_capacity(tokens.len());
+        for (i, token) in tokens.iter().enumerate() {
+            let next_is_or = tokens.get(i + 1).is_some_and(|t| is_or(&t.text));
+            let prev_is_or = i > 0 && is_or(&tokens[i - 1].text);
+            if token.depth == 0
+                && !next_is_or
+                && !prev_is_or
+                && let Some(term) = FieldTerm::parse(&token.text)
+                && term.occur != Some('-')
+                && self.take_filter(term.field, term.value)
+            {
+                continue;
+            }
+            kept.push(token.text.as_str());
+        }
+        self.query = kept.join(" ");
+    }
+
+    fn take_filter(&mut self, field: &str, value: &str) -> bool {
+        let value = value.trim_matches('"');
+        let slot = match field {
+            "project" => &mut self.project,
+            "role" => &mut self.role,
+            "tool" | "tool_name" => &mut self.tool,
+            "session" | "session_id" => &mut self.session_id,
+            "source" => {
+                if self.source.is_some() {
+                    return false;
+                }
+                let Ok(source) =
+                    <crate::types::SourceFilter as clap::ValueEnum>::from_str(value, true)
+                else {
+                    return false;
+                };
+                self.source = Some(source);
+                return true;
+            }
+            "since" | "until" | "date" => {
+                let Some((start, end)) = parse_date_range(value) else {
+                    return false;
+                };
+                if field != "until" {
+                    self.since = Some(self.since.map_or(start, |s| s.max(start)));
+                }
+                if field != "since" {
+                    self.until = Some(self.until.map_or(end, |u| u.min(end)));
+                }
+                return true;
+            }
+            _ => return false,
+        };
+        if slot.is_some() {
+            return false;
+        }
+        *slot = Some(value.to_string());
+        true
+    }
+}
+
+/// Field names accepted in queries besides the schema's own.
+const FIELD_ALIASES: &[(&str, &str)] = &[
+    ("tool", "tool_name"),
+    ("input", "tool_input"),
+    ("output", "tool_output"),
+    ("session", "session_id"),
+    ("file", "files"),
+    ("cmd", "command"),
+];
+
+/// Schema fields that may be named in a query.
+const QUERY_FIELDS: &[&str] = &[
+    "text",
+    "project",
+    "role",
+    "source",
+    "tool_name",
+    "tool_input",
+    "tool_output",
+    "session_id",
+    "files",
+    "command",
+    "tool_call_id",
+    "ts",
+];
+
+struct QueryToken {
+    text: String,
+    /// Parenthesis nesting at the start of the token.
+    depth: usize,
+}
+
+/// Split a query on whitespace, keeping quoted phrases and `[a TO b]` ranges whole.
+fn split_query(query: &str) -> Vec<QueryToken> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut start_depth = 0usize;
+    let mut quoted = false;
+    let mut ranged = false;
+    for ch in query.chars() {
+        if ch.is_whitespace() && !quoted && !ranged {
+            if !current.is_empty() {
+                tokens.push(QueryToken {
+                    text: std::mem::take(&mut current),
+                    depth: start_depth,
+                });
+            }
+            continue;
+        }
+        if current.is_empty() {
+            start_depth = depth;
+        }
+        match ch {
+            '"' => quoted = !quoted,
+            '[' | '{' if !quoted => ranged = true,
+            ']' | '}' if !quoted => ranged = false,
+            '(' if !quoted => depth += 1,
+            ')' if !quoted => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+        current.push(ch);
+    }
+    if !current.is_empty() {
+        tokens.push(QueryToken {
+            text: current,
+            depth: start_depth,
+        });
+    }
+    tokens
+}
+
+fn is_or(token: &str) -> bool {
+    token == "OR" || token == "||"
+}
+
+/// A `[+-]field:value` token, with any leading parentheses split off.
+struct FieldTerm<'a> {
+    open: &'a str,
+    occur: Option<char>,
+    field: &'a str,
+    value: &'a str,
+}
+
+impl<'a> FieldTerm<'a> {
+    fn parse(token: &'a str) -> Option<Self> {
+        let body = token.trim_start_matches('(');
+        let open = &token[..token.len() - body.len()];
+        let (occur, body) = match body.chars().next() {
+            Some(c @ ('+' | '-')) => (Some(c), &body[1..]),
+            _ => (None, body),
+        };
+        let (field, value) = body.split_once(':')?;
+        if field.is_empty()
+            || value.is_empty()
+            || !field.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        {
+            return None;
+        }
+        Some(Self {
+            open,
+            occur,
+            field,
+            value,
+        })
+    }
+}
+
+/// Rewrite the user query into tantivy syntax: resolve field aliases, turn
+/// date terms into `ts` ranges, make top-level field terms required and quote
+/// `word:word` text that names no field.
+/// Also reports whether any clause is positive (not negated).
+///
+/// With `sanitize`, free-text tokens lose query syntax characters, which turns
+/// input the grammar rejects (`foo(bar)`, a stray quote) into plain words.
+fn rewrite_query(query: &str, sanitize: bool) -> (String, bool) {
+    let tokens = split_query(query);
+    let mut out = Vec::new();
+    let mut positive = false;
+    for (i, token) in tokens.iter().enumerate() {
+        let text = token.text.clone();
+        // Field terms filter: outside of groups and OR chains they are required.
+        let required = token.depth == 0
+            && !tokens.get(i + 1).is_some_and(|t| is_or(&t.text))
+            && !(i > 0 && is_or(&tokens[i - 1].text));
+        let Some(term) = FieldTerm::parse(&text) else {
+            if !text.starts_with('-') && !is_or(&text) && text != "AND" && text != "NOT" {
+                positive = true;
+            }
+            if sanitize {
+                let (occur, body) = match text.chars().next() {
+                    Some('+' | '-') => (&text[..1], &text[1..]),
+                    _ => ("", text.as_str()),
+                };
+                let words: String = body
+                    .chars()
+                    .map(|c| {
+                        if "+^`:{}\"[]()!\\*".contains(c) {
+                            ' '
+                        } else {
+                            c
+                        }
+                    })
+                    .collect();
+                for word in words.split_whitespace() {
+                    out.push(format!("{occur}{word}"));
+                }
+                continue;
+            }
+            out.push(text);
+            continue;
+        };
+        if term.occur != Some('-') {
+            positive = true;
+        }
+        let occur = match term.occur {
+            Some(c) => c.to_string(),
+            None if required => "+".to_string(),
+            None => String::new(),
+        };
+        let prefix = format!("{}{occur}", term.open);
+        let field = FIELD_ALIASES
+            .iter()
+            .find(|(alias, _)| *alias == term.field)
+            .map(|(_, name)| *name)
+            .unwrap_or(term.field);
+        if matches!(field, "since" | "until" | "date") {
+            let close_at = term.value.find(')').unwrap_or(term.value.len());
+            let (value, close) = term.value.split_at(close_at);
+            if let Some((start, end)) = parse_date_range(value.trim_matches('"')) {
+                let range = match field {
+                    "since" => format!("ts:[{start} TO *]"),
+                    "until" => format!("ts:[* TO {end}]"),
+                    _ => format!("ts:[{start} TO {end}]"),
+                };
+                out.push(format!("{prefix}{range}{close}"));
+                continue;
+            }
+        }
+        if QUERY_FIELDS.contains(&field) {
+            out.push(format!("{prefix}{field}:{}", term.value));
+        } else {
+            // `Error:`, `http://host` and the like are text, not field names.
+            let close_at = text.trim_end_matches(')').len();
+            let (body, close) = text.split_at(close_at);
+            let body = &body[term.open.len() + term.occur.map_or(0, |_| 1)..];
+            out.push(format!("{prefix}\"{}\"{close}", body.replace('"', "")));
+        }
+    }
+    (out.join(" "), positive)
+}
+
+/// Inclusive `[start, end]` unix-millisecond bounds for a date term: `2024`,
+/// `2024-03`, `2024-03-15`, an RFC3339 timestamp, unix seconds/ms, or a
+/// relative age such as `90m`, `12h`, `7d` or `2w` (from then until now).
+fn parse_date_range(value: &str) -> Option<(u64, u64)> {
+    use chrono::{Datelike, NaiveDate, TimeZone, Utc};
+    let value = value.trim();
+    let day_start = |date: NaiveDate| {
+        Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?)
+            .timestamp_millis()
+            .try_into()
+            .ok()
+    };
+    let span = |start: NaiveDate, next: NaiveDate| -> Option<(u64, u64)> {
+        let start: u64 = day_start(start)?;
+        let next: u64 = day_start(next)?;
+        Some((start, next.saturating_sub(1)))
+    };
+    if let Some(unit) = value.chars().last().filter(|c| "mhdw".contains(*c))
+        && let Ok(n) = value[..value.len() - 1].parse::<u64>()
+    {
+        let minutes = match unit {
+            'm' => n,
+            'h' => n * 60,
+            'd' => n * 60 * 24,
+            _ => n * 60 * 24 * 7,
+        };
+        let now = Utc::now().timestamp_millis() as u64;
+        return Some((now.saturating_sub(minutes * 60_000), now));
+    }
+    if value.chars().all(|c| c.is_ascii_digit()) && value.len() > 4 {
+        let num: u64 = value.parse().ok()?;
+        let ms = if num >= 1_000_000_000_000 {
+            num
+        } else {
+            num * 1000
+        };
+        return Some((ms, ms));
+    }
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(value) {
+        let ms = dt.timestamp_millis() as u64;
+        return Some((ms, ms));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return span(date, date.succ_opt()?);
+    }
+    let parts: Vec<&str> = value.split('-').collect();
+    let year: i32 = parts.first()?.parse().ok()?;
+    match parts.as_slice() {
+        [_] if value.len() == 4 => span(
+            NaiveDate::from_ymd_opt(year, 1, 1)?,
+            NaiveDate::from_ymd_opt(year + 1, 1, 1)?,
+        ),
+        [_, month] => {
+            let start = NaiveDate::from_ymd_opt(year, month.parse().ok()?, 1)?;
+            let next = if start.month() == 12 {
+                NaiveDate::from_ymd_opt(year + 1, 1, 1)?
+            } else {
+                NaiveDate::from_ymd_opt(year, start.month() + 1, 1)?
+            };
+            span(start, next)
+        }
+        _ => None,
+    }
+}
+
+/// Whether `text` names a field memex understands, as opposed to text that
+/// merely contains a colon.
+fn is_field_term(text: &str) -> bool {
+    FieldTerm::parse(text).is_some_and(|t| {
+        QUERY_FIELDS.contains(&t.field)
+            || FIELD_ALIASES.iter().any(|(alias, _)| *alias == t.field)
+            || matches!(t.field, "since" | "until" | "date")
+    })
+}
+
+/// The free text of a query, without field terms, negations, operators or
+/// boosts; used for embeddings and match highlighting.
+pub fn plain_query_text(query: &str) -> String {
+    let mut out = Vec::new();
+    for token in split_query(query) {
+        let text = token.text.trim_matches(|c| c == '(' || c == ')');
+        if text.is_empty()
+            || text.starts_with('-')
+            || matches!(text, "AND" | "OR" | "NOT" | "||" | "&&")
+            || is_field_term(text)
+        {
+            continue;
+        }
+        let text = text.trim_start_matches('+');
+        let text = match text.rsplit_once('^') {
+            Some((body, boost)) if boost.parse::<f32>().is_ok() => body,
+            _ => text,
+        };
+        out.push(text.trim_matches('"').to_string());
+    }
+    out.join(" ")
+}
+
 fn build_query(
     fields: &IndexFields,
     options: &QueryOptions,
     index: &Index,
 ) -> Result<Box<dyn Query>> {
     let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+    let text_query = text_query(fields, options, index).unwrap_or_else(|| Box::new(AllQuery));
+    clauses.push((Occur::Must, text_query));
+    clauses.extend(filter_clauses(fields, options));
+    Ok(Box::new(BooleanQuery::new(clauses)))
+}
 
-    if options.query.trim().is_empty() {
-        clauses.push((Occur::Must, Box::new(AllQuery)));
+/// The query for `options.query` alone, without the flag filters. `None` when
+/// the query is empty.
+fn text_query(
+    fields: &IndexFields,
+    options: &QueryOptions,
+    index: &Index,
+) -> Option<Box<dyn Query>> {
+    let (query, positive) = rewrite_query(&options.query, false);
+    if query.trim().is_empty() {
+        return None;
+    }
+    // A query of only exclusions needs something to exclude from.
+    let query = if positive {
+        query
     } else {
-        let parser = tantivy::query::QueryParser::for_index(index, vec![fields.text]);
-        let text_query = parser.parse_query(&options.query)?;
-        clauses.push((Occur::Must, text_query));
+        format!("* {query}")
+    };
+    let parser = tantivy::query::QueryParser::new(
+        index.schema(),
+        vec![fields.text],
+        crate::tokenizer::query_tokenizers(),
+    );
+    // Raw text such as `foo(bar)` or an unbalanced quote should still search.
+    let text_query = match parser.parse_query(&query) {
+        Ok(query) => query,
+        Err(_) => {
+            let (plain, _) = rewrite_query(&options.query, true);
+            let plain = if positive {
+                plain
+            } else {
+                format!("* {plain}")
+            };
+            parser
+                .parse_query(&plain)
+                .unwrap_or_else(|_| parser.parse_query_lenient(&plain).0)
+        }
+    };
+    Some(match options.fuzzy {
+        Some(distance) => with_fuzzy(fields, index, &parser, &options.query, text_query, distance),
+        None => text_query,
+    })
+}
+
+/// `exact`, or a typo-tolerant prefix match of any free-text word in `query`.
+/// The fuzzy branch keeps the query's field terms and exclusions as
+/// constraints and scores far below exact matches.
+fn with_fuzzy(
+    fields: &IndexFields,
+    index: &Index,
+    parser: &tantivy::query::QueryParser,
+    query: &str,
+    exact: Box<dyn Query>,
+    distance: u8,
+) -> Box<dyn Query> {
+    let words: Vec<(Occur, Box<dyn Query>)> =
+        query_terms(index, fields.text, &plain_query_text(query))
+            .into_iter()
+            .map(|word| {
+                let distance = fuzzy_distance(&word, distance);
+                let term = Term::from_field_text(fields.text, &word);
+                let query: Box<dyn Query> =
+                    Box::new(FuzzyTermQuery::new_prefix(term, distance, true));
+                (Occur::Should, query)
+            })
+            .collect();
+    if words.is_empty() {
+        return exact;
     }
 
+    let mut fuzzy: Vec<(Occur, Box<dyn Query>)> = vec![(
+        Occur::Must,
+        Box::new(BoostQuery::new(
+            Box::new(BooleanQuery::new(words)),
+            FUZZY_BOOST,
+        )),
+    )];
+    let (constraints, positive) = rewrite_query(&constraint_text(query), false);
+    if !constraints.trim().is_empty() {
+        let constraints = if positive {
+            constraints
+        } else {
+            format!("* {constraints}")
+        };
+        if let Ok(query) = parser.parse_query(&constraints) {
+            fuzzy.push((Occur::Must, Box::new(ConstScoreQuery::new(query, 0.0))));
+        }
+    }
+    Box::new(BooleanQuery::new(vec![
+        (Occur::Should, exact),
+        (Occur::Should, Box::new(BooleanQuery::new(fuzzy))),
+    ]))
+}
+
+/// Short words tolerate fewer edits, or every short word would match.
+fn fuzzy_distance(word: &str, max: u8) -> u8 {
+    match word.chars().count() {
+        0..=3 => 0,
+        4..=6 => max.min(1),
+        _ => max,
+    }
+}
+
+/// The parts of `query` other than its top-level free-text words: field
+/// terms, exclusions and groups.
+fn constraint_text(query: &str) -> String {
+    split_query(query)
+        .into_iter()
+        .filter(|token| {
+            let text = token.text.as_str();
+            token.depth > 0
+                || text.contains(['(', ')'])
+                || text.starts_with('-')
+                || is_field_term(text)
+        })
+        .map(|token| token.text)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// A query for documents that can match `pattern`: each literal every match
+/// must start (or end) with contributes its longest alphanumeric run, looked
+/// up as a substring of the `field` terms. Only the code tokenizer keeps such
+/// runs inside a single term, so other fields are not pre-filtered.
+fn regex_prefilter(index: &Index, field: Field, pattern: &str) -> Option<Box<dyn Query>> {
+    use regex_syntax::hir::literal::{ExtractKind, Extractor};
+
+    let schema = index.schema();
+    let FieldType::Str(options) = schema.get_field_entry(field).field_type() else {
+        return None;
+    };
+    if options.get_indexing_options()?.tokenizer() != crate::tokenizer::CODE_TOKENIZER {
+        return None;
+    }
+    let hir = regex_syntax::Parser::new().parse(pattern).ok()?;
+    let runs = [ExtractKind::Prefix, ExtractKind::Suffix]
+        .into_iter()
+        .find_map(|kind| {
+            let mut extractor = Extractor::new();
+            extractor.kind(kind);
+            let literals = extractor.extract(&hir);
+            let mut runs: Vec<String> = Vec::new();
+            for literal in literals.literals()? {
+                let text = String::from_utf8_lossy(literal.as_bytes()).to_lowercase();
+                let run = text
+                    .split(|c: char| !c.is_alphanumeric())
+                    .max_by_key(|run| run.chars().count())
+                    .filter(|run| run.chars().count() >= 3)?;
+                if !runs.iter().any(|r| r == run) {
+                    runs.push(run.to_string());
+                }
+            }
+            (!runs.is_empty()).then_some(runs)
+        })?;
+    let clauses = runs
+        .into_iter()
+        .map(|run| -> Option<(Occur, Box<dyn Query>)> {
+            let query = RegexQuery::from_pattern(&format!(".*{run}.*"), field).ok()?;
+            Some((Occur::Should, Box::new(query)))
+        })
+        .collect::<Option<Vec<_>>>()?;
+    Some(Box::new(BooleanQuery::new(clauses)))
+}
+
+/// Lowercased terms `text` produces for `field` at query time.
+fn query_terms(index: &Index, field: Field, text: &str) -> Vec<String> {
+    let schema = index.schema();
+    let tokenizer = match schema.get_field_entry(field).field_type() {
+        FieldType::Str(options) => options
+            .get_indexing_options()
+            .map(|indexing| indexing.tokenizer().to_string()),
+        _ => None,
+    };
+    let Some(mut analyzer) =
+        tokenizer.and_then(|name| crate::tokenizer::query_tokenizers().get(&name))
+    else {
+        return Vec::new();
+    };
+    let mut stream = analyzer.token_stream(text);
+    let mut out: Vec<String> = Vec::new();
+    while stream.advance() {
+        let term = &stream.token().text;
+        if !out.contains(term) {
+            out.push(term.clone());
+        }
+    }
+    out
+}
+
+/// Clauses for the non-text filters in `options` (project, role, tool, source,
+/// session and time range).
+fn filter_clauses(fields: &IndexFields, options: &QueryOptions) -> Vec<(Occur, Box<dyn Query>)> {
+    let mut clauses: Vec<(Occur, Box<dyn Query>)> = Vec::new();
+
     if let Some(project) = &options.project {
         let term = Term::from_field_text(fields.project, project);
         clauses.push((
@@ -295,7 +1609,7 @@ fn build_query(
         clauses.push((Occur::Must, Box::new(range)));
     }
 
-    Ok(Box::new(BooleanQuery::new(clauses)))
+    clauses
 }
 
 fn record_from_doc(fields: &IndexFields, doc: &TantivyDocument) -> Record {
@@ -322,6 +1636,455 @@ fn record_from_doc(fields: &IndexFields, doc: &TantivyDocument) -> Record {
         tool_name: get_str(fields.tool_name),
         tool_input: get_str(fields.tool_input),
         tool_output: get_str(fields.tool_output),
+        files: fields
+            .files
+            .map(|field| {
+                doc.get_all(field)
+                    .filter_map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default(),
+        command: fields.command.and_then(get_str),
+        exit_code: fields
+            .exit_code
+            .and_then(|field| doc.get_first(field))
+            .and_then(|v| v.as_i64()),
+        tool_call_id: fields.tool_call_id.and_then(get_str),
+        patches: fields
+            .patches
+            .and_then(get_str)
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default(),
         source_path,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::SourceKind;
+
+    fn record(doc_id: u64, role: &str, tool: Option<&str>, text: &str) -> Record {
+        Record {
+            source: SourceKind::Claude,
+            doc_id,
+            ts: 1_735_725_600_000 + doc_id,
+            project: "demo".to_string(),
+            session_id: "s1".to_string(),
+            turn_id: doc_id as u32,
+            role: role.to_string(),
+            text: text.to_string(),
+            tool_name: tool.map(|t| t.to_string()),
+            tool_input: (role == "tool_use").then(|| text.to_string()),
+            tool_output: (role == "tool_result").then(|| text.to_string()),
+            files: Vec::new(),
+            command: None,
+            exit_code: None,
+            tool_call_id: None,
+            patches: Vec::new(),
+            source_path: "/tmp/s1.jsonl".to_string(),
+        }
+    }
+
+    fn options(query: &str, limit: usize) -> QueryOptions {
+        QueryOptions {
+            query: query.to_string(),
+            project: None,
+            role: None,
+            tool: None,
+            session_id: None,
+            source: None,
+            since: None,
+            until: None,
+            fuzzy: None,
+            limit,
+        }
+    }
+
+    fn search_ids(index: &SearchIndex, query: &str) -> Vec<u64> {
+        let mut ids: Vec<u64> = index
+            .search(&options(query, 10))
+            .unwrap()
+            .into_iter()
+            .map(|(_, r)| r.doc_id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn test_field_qualified_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(1, "user", None, "why does cargo test panic"),
+            record(2, "tool_use", Some("Bash"), "cargo test --workspace"),
+            record(3, "tool_result", Some("Bash"), "thread main panicked"),
+            record(4, "tool_use", Some("Read"), "src/main.rs"),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        assert_eq!(search_ids(&index, "cargo"), vec![1, 2]);
+        assert_eq!(search_ids(&index, "tool:Bash"), vec![2, 3]);
+        assert_eq!(search_ids(&index, "input:\"cargo test\""), vec![2]);
+        assert_eq!(search_ids(&index, "output:panicked"), vec![3]);
+        assert_eq!(search_ids(&index, "cargo -role:user"), vec![2]);
+        assert_eq!(search_ids(&index, "-tool:Bash -role:user"), vec![4]);
+        assert_eq!(
+            search_ids(&index, "role:tool_use date:2025-01-01"),
+            vec![2, 4]
+        );
+        assert!(search_ids(&index, "role:tool_use until:2024").is_empty());
+        assert_eq!(search_ids(&index, "panic(thread"), vec![1, 3]);
+        assert_eq!(search_ids(&index, "Error: cargo^2"), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_files_modified_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        // Two edits, then more reads than the limit.
+        for doc_id in 1..=12 {
+            let tool = if doc_id <= 2 { "Edit" } else { "Read" };
+            let mut record = record(doc_id, "tool_use", Some(tool), "{}");
+            record.files = vec!["/proj/src/main.rs".to_string()];
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let keep = |path: &str| path.ends_with("main.rs");
+        let ids = |records: Vec<Record>| records.iter().map(|r| r.doc_id).collect::<Vec<_>>();
+        let all = index
+            .records_by_files(keep, false, &options("", 3))
+            .unwrap();
+        assert_eq!(ids(all), vec![12, 11, 10]);
+        let modified = index.records_by_files(keep, true, &options("", 3)).unwrap();
+        assert_eq!(ids(modified), vec![2, 1]);
+    }
+
+    #[test]
+    fn test_paired_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        let with_call_id = |mut record: Record, session_id: &str, call_id: &str| {
+            record.session_id = session_id.to_string();
+            record.tool_call_id = Some(call_id.to_string());
+            record
+        };
+        let call = with_call_id(record(1, "tool_use", Some("Bash"), "ls"), "s1", "toolu_1");
+        let result = with_call_id(
+            record(2, "tool_result", Some("Bash"), "src"),
+            "s1",
+            "toolu_1",
+        );
+        let unanswered = with_call_id(record(3, "tool_use", Some("Bash"), "pwd"), "s1", "toolu_2");
+        // The same call id in another session is another call.
+        let elsewhere = with_call_id(record(4, "tool_result", Some("Bash"), "/"), "s2", "toolu_2");
+        for record in [&call, &result, &unanswered, &elsewhere] {
+            index.add_record(&mut writer, record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let paired = |record: &Record| index.paired_record(record).unwrap().map(|r| r.doc_id);
+        assert_eq!(paired(&call), Some(2));
+        assert_eq!(paired(&result), Some(1));
+        assert_eq!(paired(&unanswered), None);
+        assert_eq!(paired(&record(5, "tool_use", Some("Bash"), "ls")), None);
+    }
+
+    #[test]
+    fn test_failed_commands() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        // A failing run, then more passing runs than the limit.
+        for doc_id in 1..=12 {
+            let role = if doc_id % 2 == 1 {
+                "tool_use"
+            } else {
+                "tool_result"
+            };
+            let mut record = record(doc_id, role, Some("Bash"), "cargo test");
+            record.command = Some("cargo test".to_string());
+            if role == "tool_result" {
+                record.exit_code = Some(if doc_id == 2 { 101 } else { 0 });
+            }
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let keep = |command: &str| command.starts_with("cargo");
+        let failed = index
+            .records_by_commands(keep, true, &options("", 1))
+            .unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].doc_id, 2);
+        // Without call ids, the call is the latest run of the command before it.
+        let call = index.command_call(&failed[0]).unwrap().unwrap();
+        assert_eq!(call.doc_id, 1);
+    }
+
+    #[test]
+    fn test_code_identifiers_and_migrate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index");
+        std::fs::create_dir_all(&path).unwrap();
+        let index = SearchIndex::open_or_create(&path).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(
+                1,
+                "assistant",
+                None,
+                "fixed parseClaudeFile in src/ingest.rs",
+            ),
+            record(2, "assistant", None, "call parse_claude_file twice"),
+            record(3, "assistant", None, "the parser reads claude files"),
+            // Claude results were stored JSON-encoded before tool details.
+            record(4, "tool_result", Some("Bash"), r#""Exit code 1\nboom""#),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+        drop(writer);
+
+        assert_eq!(search_ids(&index, "parse_claude_file"), vec![1, 2]);
+        assert_eq!(search_ids(&index, "parseClaudeFile"), vec![1, 2]);
+        assert_eq!(search_ids(&index, "claude"), vec![1, 2, 3]);
+        assert_eq!(search_ids(&index, "ingest.rs"), vec![1]);
+        drop(index);
+
+        assert!(!predates_tool_details(&path));
+        std::fs::remove_file(path.join(SCHEMA_VERSION_FILE)).unwrap();
+        std::fs::remove_file(path.join(INGEST_VERSION_FILE)).unwrap();
+        assert!(needs_migration(&path));
+        assert_eq!(migrate(&path).unwrap(), 4);
+        assert!(!needs_migration(&path));
+        assert!(predates_tool_details(&path));
+        let index = SearchIndex::open_or_create(&path).unwrap();
+        assert_eq!(search_ids(&index, "parseClaudeFile"), vec![1, 2]);
+        let result = index.get_by_doc_id(4).unwrap().unwrap();
+        assert_eq!(result.tool_output.as_deref(), Some("Exit code 1\nboom"));
+    }
+
+    #[test]
+    fn test_fuzzy_queries() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(1, "assistant", None, "add a reqwest client"),
+            record(2, "assistant", None, "size the tokio-postgres pool"),
+            record(3, "assistant", None, "the request handler"),
+            record(4, "tool_use", Some("Bash"), "reqwest retry"),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let fuzzy = |query: &str, distance: u8| -> Vec<u64> {
+            let options = QueryOptions {
+                fuzzy: Some(distance),
+                ..options(query, 10)
+            };
+            let results = index.search(&options).unwrap();
+            results.into_iter().map(|(_, r)| r.doc_id).collect()
+        };
+
+        assert!(search_ids(&index, "reqwst").is_empty());
+        let mut ids = fuzzy("reqwst", 1);
+        ids.sort();
+        assert_eq!(ids, vec![1, 4]);
+        assert_eq!(fuzzy("tokio-postgress", 1), vec![2]);
+        assert_eq!(fuzzy("postg", 0), vec![2]);
+        assert_eq!(fuzzy("reqwst -tool:Bash", 1), vec![1]);
+        // Exact matches rank above fuzzy ones.
+        assert_eq!(fuzzy("request", 1)[0], 3);
+    }
+
+    #[test]
+    fn test_regex_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(
+                1,
+                "tool_result",
+                Some("Bash"),
+                "error[E0308]: mismatched types",
+            ),
+            record(2, "assistant", None, "fixed error[E0499] and error[E0502]"),
+            record(3, "assistant", None, "error: something else"),
+            record(
+                4,
+                "user",
+                None,
+                "session 123e4567-e89b-12d3-a456-426614174000",
+            ),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let options = |tool: Option<&str>| QueryOptions {
+            tool: tool.map(|t| t.to_string()),
+            ..options("", 10)
+        };
+        let ids = |pattern: &str, tool: Option<&str>| -> Vec<(u64, f32)> {
+            let regex = regex::Regex::new(pattern).unwrap();
+            index
+                .regex_search(&regex, &options(tool))
+                .unwrap()
+                .into_iter()
+                .map(|(score, r)| (r.doc_id, score))
+                .collect()
+        };
+
+        let rustc = r"error\[E0\d{3}\]";
+        assert!(regex_prefilter(&index.index, index.fields.text, rustc).is_some());
+        assert_eq!(ids(rustc, None), vec![(2, 2.0), (1, 1.0)]);
+        assert_eq!(ids(rustc, Some("Bash")), vec![(1, 1.0)]);
+
+        let uuid = r"[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}";
+        assert!(regex_prefilter(&index.index, index.fields.text, uuid).is_none());
+        assert_eq!(ids(uuid, None), vec![(4, 1.0)]);
+    }
+
+    #[test]
+    fn test_top_terms_prefer_rare_terms() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for (doc_id, text) in [
+            (1, "flaky upload test in cargo"),
+            (2, "cargo is green"),
+            (3, "cargo cache"),
+        ] {
+            index
+                .add_record(&mut writer, &record(doc_id, "user", None, text))
+                .unwrap();
+        }
+        writer.commit().unwrap();
+
+        let mut counts = HashMap::new();
+        for text in [
+            "the flaky upload test failed in cargo",
+            "cargo retried the flaky upload",
+        ] {
+            index.count_terms(text, &mut counts).unwrap();
+        }
+        assert!(!counts.contains_key("the"));
+        assert_eq!(counts["cargo"], 2);
+        let terms = index.top_terms(&counts, 3).unwrap();
+        assert_eq!(terms, vec!["flaky", "upload", "cargo"]);
+    }
+
+    #[test]
+    fn test_more_like_this() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        for record in [
+            record(1, "user", None, "tokio runtime panicked inside block_on"),
+            record(
+                2,
+                "assistant",
+                None,
+                "the tokio runtime panicked in block_on again",
+            ),
+            record(3, "assistant", None, "css grid layout for the sidebar"),
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let mut options = options("", 10);
+        let ids = |options: &QueryOptions| -> Vec<u64> {
+            index
+                .more_like_this("why did the tokio runtime panic in block_on", options)
+                .unwrap()
+                .into_iter()
+                .map(|(_, r)| r.doc_id)
+                .collect()
+        };
+        let mut all = ids(&options);
+        all.sort();
+        assert_eq!(all, vec![1, 2]);
+        options.role = Some("assistant".to_string());
+        assert_eq!(ids(&options), vec![2]);
+    }
+
+    #[test]
+    fn test_facets() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        let mut later = record(4, "tool_result", Some("Bash"), "timeout again");
+        later.project = "web".to_string();
+        later.session_id = "s2".to_string();
+        later.ts = 1_738_400_000_000;
+        for record in [
+            record(1, "assistant", None, "request timeout"),
+            record(2, "tool_result", Some("Bash"), "timeout after 30s"),
+            record(3, "user", None, "unrelated"),
+            later,
+        ] {
+            index.add_record(&mut writer, &record).unwrap();
+        }
+        writer.commit().unwrap();
+
+        let options = options("timeout", 10);
+        let counts = |buckets: &[FacetCount]| -> Vec<(String, usize)> {
+            buckets.iter().map(|b| (b.value.clone(), b.count)).collect()
+        };
+        let owned = |pairs: &[(&str, usize)]| -> Vec<(String, usize)> {
+            pairs.iter().map(|(v, c)| (v.to_string(), *c)).collect()
+        };
+        let facets = index.facets(&options, 10).unwrap();
+        assert_eq!(facets.total, 3);
+        assert_eq!(counts(&facets.project), owned(&[("demo", 2), ("web", 1)]));
+        assert_eq!(
+            counts(&facets.role),
+            owned(&[("tool_result", 2), ("assistant", 1)])
+        );
+        assert_eq!(counts(&facets.tool_name), owned(&[("Bash", 2)]));
+        assert_eq!(counts(&facets.source), owned(&[("claude", 3)]));
+        assert_eq!(
+            counts(&facets.month),
+            owned(&[("2025-01", 2), ("2025-02", 1)])
+        );
+
+        let facets = index.facets(&options, 1).unwrap();
+        assert_eq!(counts(&facets.session_id), owned(&[("s1", 2)]));
+    }
+
+    #[test]
+    fn test_extract_filters() {
+        let mut options = QueryOptions {
+            project: Some("api".to_string()),
+            ..options(
+                "tool:Bash (role:user OR role:assistant) timeout project:web -source:codex since:2024-03",
+                10,
+            )
+        };
+        options.extract_filters();
+        assert_eq!(options.tool.as_deref(), Some("Bash"));
+        assert_eq!(options.project.as_deref(), Some("api"));
+        assert_eq!(options.since, Some(1_709_251_200_000));
+        assert_eq!(
+            options.query,
+            "(role:user OR role:assistant) timeout project:web -source:codex"
+        );
+        assert_eq!(plain_query_text(&options.query), "timeout");
+        assert_eq!(
+            parse_date_range("2024-02-29"),
+            Some((1_709_164_800_000, 1_709_251_199_999))
+        );
+    }
+}
@@ -1,6 +1,11 @@
+use crate::cache::EmbeddingCache;
+use crate::commands::{exit_code, tool_command};
 use crate::config::Paths;
 use crate::embed::{EmbedderHandle, ModelChoice};
+use crate::files::tool_files;
 use crate::index::SearchIndex;
+use crate::models::ModelCache;
+use crate::patch::{claude_patches, tool_patches};
 use crate::progress::Progress;
 use crate::state::{FileState, IngestState, ScanCache};
 use crate::types::{Record, SourceKind};
@@ -10,18 +15,20 @@ use crossbeam_channel::{Receiver, Sender, unbounded};
 use memchr::memchr;
 use memmap2::Mmap;
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use simd_json::BorrowedValue;
 use simd_json::prelude::*;
 use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::sync::atomic::{AtomicU64, Ordering};
 use walkdir::WalkDir;
 
 const EMBED_BATCH_SIZE: usize = 64;
-const EMBED_MAX_CHARS: usize = 8192;
 const INDEX_PROGRESS_BATCH: u64 = 1;
+/// Above this many new message vectors, session vectors look up sessions in
+/// one pass over the index instead of one query per doc.
+const SESSION_LOOKUP_QUERIES: usize = 2000;
 
 #[derive(Debug, Clone)]
 pub struct IngestOptions {
@@ -31,7 +38,10 @@ pub struct IngestOptions {
     pub include_opencode: bool,
     pub embeddings: bool,
     pub backfill_embeddings: bool,
+    /// Drop cached embeddings this run doesn't use (a full reindex).
+    pub prune_embed_cache: bool,
     pub model: ModelChoice,
+    pub models: ModelCache,
 }
 
 #[derive(Debug)]
@@ -63,9 +73,12 @@ struct FileUpdate {
 struct WriterContext {
     embeddings: bool,
     do_backfill_embeddings: bool,
+    prune_embed_cache: bool,
     vector_dir: PathBuf,
+    cache_dir: PathBuf,
     progress: Arc<Progress>,
     model: ModelChoice,
+    models: ModelCache,
 }
 
 /// Check if scan cache is fresh; if so, skip indexing entirely.
@@ -94,7 +107,6 @@ pub fn ingest_all(
 ) -> Result<IngestReport> {
     let state_path = paths.state.join("ingest.json");
     let mut state = IngestState::load(&state_path)?;
-    let next_doc_id = Arc::new(AtomicU64::new(state.next_doc_id));
 
     let mut tasks = Vec::new();
     let mut files_scanned = 0usize;
@@ -294,9 +306,12 @@ pub fn ingest_all(
     let writer_ctx = WriterContext {
         embeddings: options.embeddings,
         do_backfill_embeddings: options.backfill_embeddings,
-        vector_dir: paths.vectors.clone(),
+        prune_embed_cache: options.prune_embed_cache,
+        vector_dir: crate::vector::model_store(&paths.vectors, &options.model),
+        cache_dir: paths.embed_cache.join(options.model.id()),
         progress: progress.clone(),
-        model: options.model,
+        model: options.model.clone(),
+        models: options.models.clone(),
     };
     let writer_handle =
         std::thread::spawn(move || writer_loop(writer_index, rx_record, delete_paths, writer_ctx));
@@ -304,23 +319,14 @@ pub fn ingest_all(
     let tasks_arc = Arc::new(tasks);
     tasks_arc.par_iter().try_for_each(|task| -> Result<()> {
         match task.source {
-            SourceKind::Claude => {
-                parse_claude_file(task, &tx_record, &tx_update, &next_doc_id, &progress)?
-            }
+            SourceKind::Claude => parse_claude_file(task, &tx_record, &tx_update, &progress)?,
             SourceKind::CodexSession => {
-                parse_codex_session(task, &tx_record, &tx_update, &next_doc_id, &progress)?
+                parse_codex_session(task, &tx_record, &tx_update, &progress)?
             }
-            SourceKind::CodexHistory => parse_codex_history(
-                task,
-                &tx_record,
-                &tx_update,
-                &next_doc_id,
-                &session_ids,
-                &progress,
-            )?,
-            SourceKind::Opencode => {
-                parse_opencode_file(task, &tx_record, &tx_update, &next_doc_id, &progress)?
+            SourceKind::CodexHistory => {
+                parse_codex_history(task, &tx_record, &tx_update, &session_ids, &progress)?
             }
+            SourceKind::Opencode => parse_opencode_file(task, &tx_record, &tx_update, &progress)?,
         }
         Ok(())
     })?;
@@ -343,7 +349,6 @@ pub fn ingest_all(
     for (path, update) in updated_files {
         state.files.insert(path, update);
     }
-    state.next_doc_id = next_doc_id.load(Ordering::SeqCst);
     state.save(&state_path)?;
 
     // Update scan cache with current scan results
@@ -369,10 +374,23 @@ fn writer_loop(
     let WriterContext {
         embeddings,
         do_backfill_embeddings,
+        prune_embed_cache,
         vector_dir,
+        cache_dir,
         progress,
         model,
+        models,
     } = ctx;
+    // Ids stay with their message across rewrites: those of the files being
+    // reread are free again, and any other id already in the index or
+    // written this run is a hash collision. `committed` keeps seeing the
+    // records the writer deletes.
+    let committed = index.reader()?.searcher();
+    let mut rewritten = index.source_doc_ids(&delete_paths)?;
+    let mut written = HashSet::new();
+    // Reread records whose text changed, or that are gone: their vectors no
+    // longer embed them.
+    let mut stale = Vec::new();
     let mut writer = index.writer()?;
     for path in delete_paths {
         index.delete_by_source_path(&mut writer, &path);
@@ -381,6 +399,7 @@ fn writer_loop(
     let mut count = 0usize;
     let mut embedded_count = 0usize;
     let mut vector_index = None;
+    let mut embed_cache = None;
     let mut embedder: Option<EmbedderHandle> = None;
     let mut embed_buffer: Vec<(u64, String, SourceKind)> = Vec::new();
     let mut index_pending: [u64; 4] = [0, 0, 0, 0];
@@ -388,17 +407,42 @@ fn writer_loop(
         unsafe {
             std::env::set_var("HF_HUB_DISABLE_PROGRESS_BARS", "1");
         }
-        let handle = EmbedderHandle::with_model(model)?;
+        let handle = EmbedderHandle::with_model(&model, &models)?;
         let dims = handle.dims;
         vector_index = Some(crate::vector::VectorIndex::open_or_create(
             &vector_dir,
+            model.id(),
+            dims,
+            model.template_version(),
+        )?);
+        embed_cache = Some(EmbeddingCache::open(
+            &cache_dir,
             dims,
+            model.template_version(),
         )?);
         embedder = Some(handle);
         progress.set_embed_ready();
     }
 
     for mut record in rx.iter() {
+        while !written.insert(record.doc_id)
+            || (!rewritten.contains(&record.doc_id)
+                && index.get_by_doc_id_in(&committed, record.doc_id)?.is_some())
+        {
+            record.doc_id = next_free_doc_id(record.doc_id);
+        }
+        // A rewritten file may hold other text under an old id, such as a
+        // line now at a reused byte offset.
+        if rewritten.remove(&record.doc_id)
+            && index
+                .get_by_doc_id_in(&committed, record.doc_id)?
+                .is_some_and(|old| old.text != record.text)
+        {
+            match vector_index.as_mut() {
+                Some(vindex) => vindex.remove(record.doc_id)?,
+                None => stale.push(record.doc_id),
+            }
+        }
         index.add_record(&mut writer, &record)?;
         let source_idx = record.source.idx();
         index_pending[source_idx] += 1;
@@ -407,9 +451,9 @@ fn writer_loop(
             index_pending[source_idx] = 0;
         }
         if embeddings && is_embedding_role(&record.role) && !record.text.is_empty() {
-            let text = truncate_for_embedding(std::mem::take(&mut record.text));
+            let text = std::mem::take(&mut record.text);
             if let Some(vindex) = vector_index.as_ref()
-                && !vindex.contains(record.doc_id)
+                && !vindex.covers(record.doc_id, &text)
             {
                 progress.add_embed_total(record.source, 1);
                 progress.add_embed_pending(record.source, 1);
@@ -422,6 +466,7 @@ fn writer_loop(
                     &mut embed_buffer,
                     emb,
                     vector_index.as_mut().unwrap(),
+                    embed_cache.as_mut().unwrap(),
                     &progress,
                 )?;
             }
@@ -443,12 +488,31 @@ fn writer_loop(
     }
 
     writer.commit()?;
+    stale.extend(rewritten);
+    if !stale.is_empty() {
+        match vector_index.as_mut() {
+            Some(vindex) => {
+                for &doc_id in &stale {
+                    vindex.remove(doc_id)?;
+                }
+            }
+            None if crate::vector::VectorIndex::exists(&vector_dir) => {
+                let mut vindex = crate::vector::VectorIndex::open(&vector_dir)?;
+                for &doc_id in &stale {
+                    vindex.remove(doc_id)?;
+                }
+                vindex.save()?;
+            }
+            None => {}
+        }
+    }
     if embeddings {
         if do_backfill_embeddings {
             embedded_count += backfill_embeddings(
                 &index,
                 embedder.as_mut().unwrap(),
                 vector_index.as_mut().unwrap(),
+                embed_cache.as_mut().unwrap(),
                 &progress,
             )?;
         }
@@ -458,11 +522,16 @@ fn writer_loop(
                 &mut embed_buffer,
                 embedder.as_mut().unwrap(),
                 vector_index.as_mut().unwrap(),
+                embed_cache.as_mut().unwrap(),
                 &progress,
             )?;
         }
         if let Some(vindex) = vector_index.as_mut() {
             vindex.save()?;
+            update_session_vectors(&index, vindex, &crate::vector::sessions_dir(&vector_dir))?;
+        }
+        if prune_embed_cache && let Some(cache) = embed_cache.take() {
+            cache.retain_used()?;
         }
         if let Some(handle) = embedder.take() {
             std::mem::forget(handle);
@@ -471,10 +540,86 @@ fn writer_loop(
     Ok((count, embedded_count))
 }
 
+/// Fold message vectors added since the last run into their sessions' mean
+/// embeddings. Sessions with a message whose vectors were replaced or removed
+/// are summed again from their current vectors. Returns the number of
+/// sessions updated.
+pub fn update_session_vectors(
+    index: &SearchIndex,
+    vectors: &mut crate::vector::VectorIndex,
+    dir: &Path,
+) -> Result<usize> {
+    let mut sessions = crate::vector::SessionVectors::open_or_create(dir, vectors.dimensions())?;
+    let unfolded = sessions.unfold(vectors.removed())?;
+    let mut doc_sessions: Vec<(u64, String)> = unfolded
+        .iter()
+        .filter(|(doc_id, _)| vectors.contains(*doc_id))
+        .cloned()
+        .collect();
+    let known: HashSet<u64> = doc_sessions.iter().map(|(doc_id, _)| *doc_id).collect();
+    let pending: HashSet<u64> = vectors
+        .doc_ids()
+        .filter(|doc_id| !sessions.is_folded(*doc_id) && !known.contains(doc_id))
+        .collect();
+    if pending.is_empty() && unfolded.is_empty() {
+        vectors.clear_removed()?;
+        return Ok(0);
+    }
+
+    if pending.len() <= SESSION_LOOKUP_QUERIES {
+        for &doc_id in &pending {
+            if let Some(record) = index.get_by_doc_id(doc_id)? {
+                doc_sessions.push((doc_id, record.session_id));
+            }
+        }
+    } else {
+        index.for_each_record(|record| {
+            if pending.contains(&record.doc_id) {
+                doc_sessions.push((record.doc_id, record.session_id));
+            }
+            Ok(())
+        })?;
+    }
+
+    // Only messages summed here are marked; the rest are tried again.
+    let mut sums: HashMap<String, (Vec<f32>, Vec<u64>)> = HashMap::new();
+    for (doc_id, session_id) in doc_sessions {
+        let Some(embedding) = vectors.get(doc_id)? else {
+            continue;
+        };
+        let norm = embedding.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let (sum, doc_ids) = sums
+            .entry(session_id)
+            .or_insert_with(|| (vec![0.0; embedding.len()], Vec::new()));
+        for (total, x) in sum.iter_mut().zip(&embedding) {
+            *total += x / norm;
+        }
+        doc_ids.push(doc_id);
+    }
+    for (session_id, (sum, doc_ids)) in &sums {
+        sessions.fold(session_id, sum, doc_ids.len() as u64)?;
+        for &doc_id in doc_ids {
+            sessions.mark_folded(doc_id, session_id);
+        }
+    }
+    sessions.save()?;
+    vectors.clear_removed()?;
+    let dropped: HashSet<&str> = unfolded
+        .iter()
+        .map(|(_, session_id)| session_id.as_str())
+        .filter(|session_id| !sums.contains_key(*session_id))
+        .collect();
+    Ok(sums.len() + dropped.len())
+}
+
 fn backfill_embeddings(
     index: &SearchIndex,
     embedder: &mut EmbedderHandle,
     vector_index: &mut crate::vector::VectorIndex,
+    cache: &mut EmbeddingCache,
     progress: &Arc<Progress>,
 ) -> Result<usize> {
     use std::cell::Cell;
@@ -483,25 +628,21 @@ fn backfill_embeddings(
     index.for_each_record(|record| {
         if record.text.is_empty()
             || !is_embedding_role(&record.role)
-            || vector_index.contains(record.doc_id)
+            || vector_index.covers(record.doc_id, &record.text)
         {
             return Ok(());
         }
         progress.add_embed_total(record.source, 1);
         progress.add_embed_pending(record.source, 1);
-        embed_buffer.push((
-            record.doc_id,
-            truncate_for_embedding(record.text),
-            record.source,
-        ));
+        embed_buffer.push((record.doc_id, record.text, record.source));
         if embed_buffer.len() >= EMBED_BATCH_SIZE {
-            let n = flush_embeddings(&mut embed_buffer, embedder, vector_index, progress)?;
+            let n = flush_embeddings(&mut embed_buffer, embedder, vector_index, cache, progress)?;
             embedded_count.set(embedded_count.get() + n);
         }
         Ok(())
     })?;
     if !embed_buffer.is_empty() {
-        let n = flush_embeddings(&mut embed_buffer, embedder, vector_index, progress)?;
+        let n = flush_embeddings(&mut embed_buffer, embedder, vector_index, cache, progress)?;
         embedded_count.set(embedded_count.get() + n);
     }
     Ok(embedded_count.get())
@@ -604,11 +745,60 @@ fn codex_history_path() -> PathBuf {
     home.join(".codex").join("history.jsonl")
 }
 
+/// Doc ids for the records made from one transcript entry. An id hashes the
+/// source file, the entry's uuid (its byte offset when it has none) and the
+/// record's place among the entry's records, so the same message gets the
+/// same id in every index built from the same file.
+struct EntryIds<'a> {
+    source_path: &'a str,
+    entry: String,
+    block: u64,
+}
+
+impl<'a> EntryIds<'a> {
+    fn new(source_path: &'a str, uuid: Option<&str>, offset: usize) -> Self {
+        let entry = match uuid.filter(|uuid| !uuid.is_empty()) {
+            Some(uuid) => uuid.to_string(),
+            None => format!("@{offset}"),
+        };
+        Self {
+            source_path,
+            entry,
+            block: 0,
+        }
+    }
+
+    fn next(&mut self) -> u64 {
+        let id = hash_doc_id(&[
+            self.source_path.as_bytes(),
+            self.entry.as_bytes(),
+            &self.block.to_le_bytes(),
+        ]);
+        self.block += 1;
+        id
+    }
+}
+
+fn hash_doc_id(parts: &[&[u8]]) -> u64 {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    u64::from_le_bytes(hasher.finalize()[..8].try_into().unwrap())
+}
+
+/// Where a record whose id is taken goes next. Deterministic, so a colliding
+/// record still lands on the same id when its files are indexed in the same
+/// order.
+fn next_free_doc_id(doc_id: u64) -> u64 {
+    hash_doc_id(&[b"collision", &doc_id.to_le_bytes()])
+}
+
 fn parse_claude_file(
     task: &FileTask,
     tx_record: &Sender<Record>,
     tx_update: &Sender<FileUpdate>,
-    next_doc_id: &AtomicU64,
     progress: &Arc<Progress>,
 ) -> Result<()> {
     let file = File::open(&task.path)?;
@@ -625,10 +815,13 @@ fn parse_claude_file(
         .to_string();
     let source_path = task.path.to_string_lossy().to_string();
     let mut tool_id_to_name: HashMap<String, String> = HashMap::new();
+    let mut tool_id_to_command: HashMap<String, String> = HashMap::new();
+    let mut patched_paths: HashSet<String> = HashSet::new();
 
     let mut buf = Vec::new();
     let mut parsed_bytes = 0u64;
     while start < mmap.len() {
+        let line_start = start;
         let slice = &mmap[start..];
         let rel = memchr(b'\n', slice).unwrap_or(slice.len());
         let line = &slice[..rel];
@@ -656,6 +849,11 @@ fn parse_claude_file(
         if entry_type != "user" && entry_type != "assistant" {
             continue;
         }
+        let mut ids = EntryIds::new(
+            &source_path,
+            obj.get("uuid").and_then(|v| v.as_str()),
+            line_start,
+        );
         let timestamp = obj
             .get("timestamp")
             .and_then(|v| v.as_str())
@@ -686,17 +884,27 @@ fn parse_claude_file(
                             .get("name")
                             .and_then(|v| v.as_str())
                             .map(|s| s.to_string());
-                        if let (Some(id), Some(name)) = (
-                            block_obj.get("id").and_then(|v| v.as_str()),
-                            tool_name.clone(),
-                        ) {
-                            tool_id_to_name.insert(id.to_string(), name);
+                        let tool_call_id = block_obj
+                            .get("id")
+                            .and_then(|v| v.as_str())
+                            .map(|s| s.to_string());
+                        if let (Some(id), Some(name)) = (&tool_call_id, tool_name.clone()) {
+                            tool_id_to_name.insert(id.clone(), name);
+                        }
+                        let tool_input = block_obj.get("input").map(|v| v.encode());
+                        let (files, command) = match (&tool_name, &tool_input) {
+                            (Some(name), Some(input)) => {
+                                (tool_files(name, input), tool_command(name, input))
+                            }
+                            _ => (Vec::new(), None),
+                        };
+                        if let (Some(id), Some(command)) = (&tool_call_id, &command) {
+                            tool_id_to_command.insert(id.clone(), command.clone());
                         }
-                        let tool_input = block_obj.get("input").map(|v| v.to_string());
                         let text = tool_input.clone().unwrap_or_default();
                         let record = Record {
                             source: SourceKind::Claude,
-                            doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                            doc_id: ids.next(),
                             ts: timestamp,
                             project: project.clone(),
                             session_id: session_id.clone(),
@@ -706,6 +914,11 @@ fn parse_claude_file(
                             tool_name,
                             tool_input,
                             tool_output: None,
+                            files,
+                            command,
+                            exit_code: None,
+                            tool_call_id,
+                            patches: Vec::new(),
                             source_path: source_path.clone(),
                         };
                         progress.add_produced(SourceKind::Claude, 1);
@@ -720,27 +933,49 @@ fn parse_claude_file(
             && let Some(content) = content
             && let Some(arr) = content.as_array()
         {
+            let mut patches = obj
+                .get("toolUseResult")
+                .filter(|v| v.as_object().is_some())
+                .and_then(|v| serde_json::from_str::<serde_json::Value>(&v.encode()).ok())
+                .map(|v| claude_patches(&v))
+                .unwrap_or_default();
+            // The pre-edit contents only matter the first time a file is touched.
+            for patch in &mut patches {
+                if !patched_paths.insert(patch.path.clone()) {
+                    patch.original = None;
+                }
+            }
             for block in arr {
                 let block_obj = match block.as_object() {
                     Some(b) => b,
                     None => continue,
                 };
                 if block_obj.get("type").and_then(|v| v.as_str()) == Some("tool_result") {
-                    let tool_output = block_obj.get("content").map(|v| v.to_string());
+                    let tool_output = block_obj.get("content").map(|v| match v.as_str() {
+                        Some(s) => s.to_string(),
+                        None => v.encode(),
+                    });
                     let mut text = extract_text_from_tool_result(block).unwrap_or_default();
                     if text.is_empty()
                         && let Some(content) = block_obj.get("content")
                     {
-                        text = content.to_string();
+                        text = content.encode();
                     }
-                    let tool_name = block_obj
-                        .get("tool_use_id")
-                        .and_then(|v| v.as_str())
-                        .and_then(|id| tool_id_to_name.get(id))
+                    let tool_use_id = block_obj.get("tool_use_id").and_then(|v| v.as_str());
+                    let tool_name = tool_use_id.and_then(|id| tool_id_to_name.get(id)).cloned();
+                    let command = tool_use_id
+                        .and_then(|id| tool_id_to_command.get(id))
                         .cloned();
+                    let exit_code = command.as_ref().and_then(|_| {
+                        let is_error = block_obj
+                            .get("is_error")
+                            .and_then(|v| v.as_bool())
+                            .unwrap_or(false);
+                        exit_code(&text, Some(is_error))
+                    });
                     let record = Record {
                         source: SourceKind::Claude,
-                        doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                        doc_id: ids.next(),
                         ts: timestamp,
                         project: project.clone(),
                         session_id: session_id.clone(),
@@ -750,6 +985,11 @@ fn parse_claude_file(
                         tool_name,
                         tool_input: None,
                         tool_output,
+                        files: Vec::new(),
+                        command,
+                        exit_code,
+                        tool_call_id: tool_use_id.map(|id| id.to_string()),
+                        patches: std::mem::take(&mut patches),
                         source_path: source_path.clone(),
                     };
                     progress.add_produced(SourceKind::Claude, 1);
@@ -763,7 +1003,7 @@ fn parse_claude_file(
         if !text.is_empty() {
             let record = Record {
                 source: SourceKind::Claude,
-                doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                doc_id: ids.next(),
                 ts: timestamp,
                 project: project.clone(),
                 session_id: session_id.clone(),
@@ -773,6 +1013,11 @@ fn parse_claude_file(
                 tool_name: None,
                 tool_input: None,
                 tool_output: None,
+                files: Vec::new(),
+                command: None,
+                exit_code: None,
+                tool_call_id: None,
+                patches: Vec::new(),
                 source_path: source_path.clone(),
             };
             progress.add_produced(SourceKind::Claude, 1);
@@ -803,7 +1048,6 @@ fn parse_codex_session(
     task: &FileTask,
     tx_record: &Sender<Record>,
     tx_update: &Sender<FileUpdate>,
-    next_doc_id: &AtomicU64,
     progress: &Arc<Progress>,
 ) -> Result<()> {
     let file = File::open(&task.path)?;
@@ -816,10 +1060,12 @@ fn parse_codex_session(
         session_id_from_filename(&task.path).unwrap_or_else(|| "unknown".to_string());
     let mut project = "codex".to_string();
     let mut call_id_to_name: HashMap<String, String> = HashMap::new();
+    let mut call_id_to_command: HashMap<String, String> = HashMap::new();
 
     let mut buf = Vec::new();
     let mut parsed_bytes = 0u64;
     while start < mmap.len() {
+        let line_start = start;
         let slice = &mmap[start..];
         let rel = memchr(b'\n', slice).unwrap_or(slice.len());
         let line = &slice[..rel];
@@ -844,6 +1090,7 @@ fn parse_codex_session(
             None => continue,
         };
         let entry_type = obj.get("type").and_then(|v| v.as_str()).unwrap_or("");
+        let mut ids = EntryIds::new(&source_path, None, line_start);
         let timestamp = obj
             .get("timestamp")
             .and_then(|v| v.as_str())
@@ -894,7 +1141,7 @@ fn parse_codex_session(
             }
             let record = Record {
                 source: SourceKind::CodexSession,
-                doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                doc_id: ids.next(),
                 ts: timestamp,
                 project: project.clone(),
                 session_id: session_id.clone(),
@@ -904,29 +1151,59 @@ fn parse_codex_session(
                 tool_name: None,
                 tool_input: None,
                 tool_output: None,
+                files: Vec::new(),
+                command: None,
+                exit_code: None,
+                tool_call_id: None,
+                patches: Vec::new(),
                 source_path: source_path.clone(),
             };
             progress.add_produced(SourceKind::CodexSession, 1);
             tx_record.send(record)?;
             turn_id += 1;
-        } else if payload_type == "function_call" {
-            let tool_name = payload
-                .get("name")
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string());
-            let tool_input = payload
-                .get("arguments")
+        } else if matches!(
+            payload_type,
+            "function_call" | "custom_tool_call" | "local_shell_call"
+        ) {
+            let tool_name = if payload_type == "local_shell_call" {
+                Some("local_shell".to_string())
+            } else {
+                payload
+                    .get("name")
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string())
+            };
+            // Freeform tools (custom_tool_call, e.g. apply_patch) carry raw `input`;
+            // local_shell_call carries an `action` object.
+            let tool_input = match payload.get("action") {
+                Some(action) if payload_type == "local_shell_call" => Some(action.encode()),
+                _ => payload
+                    .get("arguments")
+                    .or_else(|| payload.get("input"))
+                    .and_then(|v| v.as_str())
+                    .map(|s| s.to_string()),
+            };
+            let (files, command) = match (&tool_name, &tool_input) {
+                (Some(name), Some(input)) => (tool_files(name, input), tool_command(name, input)),
+                _ => (Vec::new(), None),
+            };
+            let patches = tool_input.as_deref().map(tool_patches).unwrap_or_default();
+            let tool_call_id = payload
+                .get("call_id")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
-            if let Some(call_id) = payload.get("call_id").and_then(|v| v.as_str())
-                && let Some(name) = tool_name.clone()
-            {
-                call_id_to_name.insert(call_id.to_string(), name);
+            if let Some(call_id) = &tool_call_id {
+                if let Some(name) = tool_name.clone() {
+                    call_id_to_name.insert(call_id.clone(), name);
+                }
+                if let Some(command) = command.clone() {
+                    call_id_to_command.insert(call_id.clone(), command);
+                }
             }
             let text = tool_input.clone().unwrap_or_default();
             let record = Record {
                 source: SourceKind::CodexSession,
-                doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                doc_id: ids.next(),
                 ts: timestamp,
                 project: project.clone(),
                 session_id: session_id.clone(),
@@ -936,17 +1213,25 @@ fn parse_codex_session(
                 tool_name,
                 tool_input,
                 tool_output: None,
+                files,
+                command,
+                exit_code: None,
+                tool_call_id,
+                patches,
                 source_path: source_path.clone(),
             };
             progress.add_produced(SourceKind::CodexSession, 1);
             tx_record.send(record)?;
             turn_id += 1;
-        } else if payload_type == "function_call_output" {
+        } else if payload_type == "function_call_output"
+            || payload_type == "custom_tool_call_output"
+        {
             let call_id = payload
                 .get("call_id")
                 .and_then(|v| v.as_str())
                 .unwrap_or("");
             let tool_name = call_id_to_name.get(call_id).cloned();
+            let command = call_id_to_command.get(call_id).cloned();
             let tool_output = payload
                 .get("output")
                 .and_then(|v| v.as_str())
@@ -955,9 +1240,10 @@ fn parse_codex_session(
             if text.is_empty() {
                 continue;
             }
+            let exit_code = command.as_ref().and_then(|_| exit_code(&text, None));
             let record = Record {
                 source: SourceKind::CodexSession,
-                doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+                doc_id: ids.next(),
                 ts: timestamp,
                 project: project.clone(),
                 session_id: session_id.clone(),
@@ -967,6 +1253,11 @@ fn parse_codex_session(
                 tool_name,
                 tool_input: None,
                 tool_output,
+                files: Vec::new(),
+                command,
+                exit_code,
+                tool_call_id: (!call_id.is_empty()).then(|| call_id.to_string()),
+                patches: Vec::new(),
                 source_path: source_path.clone(),
             };
             progress.add_produced(SourceKind::CodexSession, 1);
@@ -997,7 +1288,6 @@ fn parse_codex_history(
     task: &FileTask,
     tx_record: &Sender<Record>,
     tx_update: &Sender<FileUpdate>,
-    next_doc_id: &AtomicU64,
     session_ids: &HashSet<String>,
     progress: &Arc<Progress>,
 ) -> Result<()> {
@@ -1010,6 +1300,7 @@ fn parse_codex_history(
     let mut buf = Vec::new();
     let mut parsed_bytes = 0u64;
     while start < mmap.len() {
+        let line_start = start;
         let slice = &mmap[start..];
         let rel = memchr(b'\n', slice).unwrap_or(slice.len());
         let line = &slice[..rel];
@@ -1049,7 +1340,7 @@ fn parse_codex_history(
         }
         let record = Record {
             source: SourceKind::CodexHistory,
-            doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+            doc_id: EntryIds::new(&source_path, None, line_start).next(),
             ts: ts_ms,
             project: "codex".to_string(),
             session_id: session_id.to_string(),
@@ -1059,6 +1350,11 @@ fn parse_codex_history(
             tool_name: None,
             tool_input: None,
             tool_output: None,
+            files: Vec::new(),
+            command: None,
+            exit_code: None,
+            tool_call_id: None,
+            patches: Vec::new(),
             source_path: source_path.clone(),
         };
         progress.add_produced(SourceKind::CodexHistory, 1);
@@ -1088,7 +1384,6 @@ fn parse_opencode_file(
     task: &FileTask,
     tx_record: &Sender<Record>,
     tx_update: &Sender<FileUpdate>,
-    next_doc_id: &AtomicU64,
     progress: &Arc<Progress>,
 ) -> Result<()> {
     let session_dir = &task.path;
@@ -1098,6 +1393,7 @@ fn parse_opencode_file(
         .unwrap_or("unknown")
         .to_string();
     let project = "opencode".to_string();
+    let source_path = session_dir.to_string_lossy().to_string();
 
     let mut messages = Vec::new();
     for entry in std::fs::read_dir(session_dir)? {
@@ -1169,7 +1465,7 @@ fn parse_opencode_file(
         let text = text_parts.join("\n");
         let record = Record {
             source: SourceKind::Opencode,
-            doc_id: next_doc_id.fetch_add(1, Ordering::SeqCst),
+            doc_id: EntryIds::new(&source_path, Some(&msg_id), 0).next(),
             ts: timestamp,
             project: project.clone(),
             session_id: session_id.clone(),
@@ -1179,7 +1475,12 @@ fn parse_opencode_file(
             tool_name: None,
             tool_input: None,
             tool_output: None,
-            source_path: session_dir.to_string_lossy().to_string(),
+            files: Vec::new(),
+            command: None,
+            exit_code: None,
+            tool_call_id: None,
+            patches: Vec::new(),
+            source_path: source_path.clone(),
         };
         progress.add_produced(SourceKind::Opencode, 1);
         tx_record.send(record)?;
@@ -1312,16 +1613,15 @@ fn flush_embeddings(
     buffer: &mut Vec<(u64, String, SourceKind)>,
     embedder: &mut EmbedderHandle,
     vindex: &mut crate::vector::VectorIndex,
+    cache: &mut EmbeddingCache,
     progress: &Arc<Progress>,
 ) -> Result<usize> {
     if buffer.is_empty() {
         return Ok(0);
     }
 
-    // Prepare texts for batch embedding
     let items: Vec<(u64, String, SourceKind)> = buffer
         .drain(..)
-        .map(|(doc_id, text, source)| (doc_id, truncate_for_embedding(text), source))
         .filter(|(_, text, _)| !text.is_empty())
         .collect();
 
@@ -1329,18 +1629,64 @@ fn flush_embeddings(
         return Ok(0);
     }
 
+    let docs: Vec<(u64, &str)> = items
+        .iter()
+        .map(|(doc_id, text, _)| (*doc_id, text.as_str()))
+        .collect();
+    embed_chunks(embedder, vindex, cache, &docs)?;
+    for (_, _, source) in &items {
+        progress.add_embedded(*source, 1);
+    }
+    Ok(items.len())
+}
+
+/// Embed each document's text chunk by chunk, replacing any vectors it has.
+/// Chunks found in `cache` aren't embedded again. Returns how many were
+/// found there.
+pub fn embed_chunks(
+    embedder: &mut EmbedderHandle,
+    vindex: &mut crate::vector::VectorIndex,
+    cache: &mut EmbeddingCache,
+    docs: &[(u64, &str)],
+) -> Result<usize> {
+    let mut chunks = Vec::new();
+    let mut missing = Vec::new();
+    for &(doc_id, text) in docs {
+        for span in crate::embed::chunk_spans(text) {
+            let chunk = &text[span.clone()];
+            let cached = cache.get(chunk)?;
+            if cached.is_none() {
+                missing.push(chunk);
+            }
+            chunks.push((doc_id, span, chunk, cached));
+        }
+    }
+    let chunk_count = chunks.len();
     // Batch embed all texts at once (ONNX Runtime handles internal parallelism)
-    let texts: Vec<&str> = items.iter().map(|(_, text, _)| text.as_str()).collect();
-    let embeddings = embedder.embed_texts(&texts)?;
+    let embeddings = embedder.embed_documents(&missing)?;
+    let entries: Vec<(&str, &[f32])> = missing
+        .iter()
+        .zip(&embeddings)
+        .map(|(text, vec)| (*text, vec.as_slice()))
+        .collect();
+    cache.insert(&entries)?;
 
-    // Add embeddings to index
-    let mut count = 0;
-    for ((doc_id, _, source), vec) in items.iter().zip(embeddings.iter()) {
-        vindex.add(*doc_id, vec)?;
-        progress.add_embedded(*source, 1);
-        count += 1;
+    for &(doc_id, _) in docs {
+        if vindex.contains(doc_id) {
+            vindex.remove(doc_id)?;
+        }
+    }
+    let mut embedded = embeddings.iter();
+    for (doc_id, span, _, cached) in chunks {
+        let vec = match &cached {
+            Some(vec) => vec,
+            None => embedded
+                .next()
+                .ok_or_else(|| anyhow!("embedding missing"))?,
+        };
+        vindex.add(doc_id, span, vec)?;
     }
-    Ok(count)
+    Ok(chunk_count - missing.len())
 }
 
 fn compute_totals(tasks: &[FileTask]) -> [u64; 4] {
@@ -1360,18 +1706,161 @@ fn compute_file_totals(tasks: &[FileTask]) -> [u64; 4] {
     totals
 }
 
-fn truncate_for_embedding(mut text: String) -> String {
-    if text.len() <= EMBED_MAX_CHARS {
-        return text;
+fn is_embedding_role(role: &str) -> bool {
+    role == "user" || role == "assistant"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vector::VectorIndex;
+    use tempfile::TempDir;
+
+    fn claude_line(uuid: &str, role: &str, text: &str) -> String {
+        serde_json::json!({
+            "type": role,
+            "uuid": uuid,
+            "timestamp": "2025-01-01T10:00:00Z",
+            "message": {"role": role, "content": text},
+        })
+        .to_string()
+            + "\n"
     }
-    let mut end = EMBED_MAX_CHARS.min(text.len());
-    while end > 0 && !text.is_char_boundary(end) {
-        end -= 1;
+
+    #[test]
+    fn test_reingest_keeps_doc_ids_and_vectors() {
+        let tmp = TempDir::new().unwrap();
+        let paths = Paths::new(Some(tmp.path().join("root"))).unwrap();
+        paths.ensure_dirs().unwrap();
+        let index = SearchIndex::open_or_create(&paths.index).unwrap();
+        let model = crate::embed::tiny_test_model(
+            &tmp.path().join("tiny"),
+            &[
+                "fix", "the", "parser", "bug", "reads", "files", "add", "a", "test",
+            ],
+        );
+        let projects = tmp.path().join("projects");
+        let transcript = projects.join("-proj").join("s1.jsonl");
+        std::fs::create_dir_all(transcript.parent().unwrap()).unwrap();
+        let options = IngestOptions {
+            claude_source: projects,
+            include_agents: false,
+            include_codex: false,
+            include_opencode: false,
+            embeddings: true,
+            backfill_embeddings: false,
+            prune_embed_cache: false,
+            model: model.clone(),
+            models: ModelCache {
+                dir: tmp.path().join("models"),
+                offline: true,
+            },
+        };
+        let store = crate::vector::model_store(&paths.vectors, &model);
+        let doc_ids = || {
+            let mut ids = Vec::new();
+            index
+                .for_each_record(|record| {
+                    ids.push((record.text, record.doc_id));
+                    Ok(())
+                })
+                .unwrap();
+            ids.sort();
+            ids
+        };
+
+        let first = [
+            claude_line("u1", "user", "fix the parser bug"),
+            claude_line("a1", "assistant", "the parser reads files"),
+            claude_line("u2", "user", "add a test"),
+        ];
+        std::fs::write(&transcript, first.concat()).unwrap();
+        let report = ingest_all(&paths, &index, &options).unwrap();
+        assert_eq!(report.records_embedded, 3);
+        let before = doc_ids();
+        assert_eq!(before.len(), 3);
+        let session_id = index
+            .get_by_doc_id(before[0].1)
+            .unwrap()
+            .unwrap()
+            .session_id;
+        let session_mean = || {
+            crate::vector::SessionVectors::open(&crate::vector::sessions_dir(&store))
+                .unwrap()
+                .get(&session_id)
+                .unwrap()
+                .unwrap()
+        };
+        let mean_before = session_mean();
+
+        // A shorter file is reread from the start; only the changed message
+        // is embedded again.
+        let second = [
+            first[0].clone(),
+            first[1].clone(),
+            claude_line("u2", "user", "add test"),
+        ];
+        std::fs::write(&transcript, second.concat()).unwrap();
+        let report = ingest_all(&paths, &index, &options).unwrap();
+        assert_eq!(report.records_added, 3);
+        assert_eq!(report.records_embedded, 1);
+        let after = doc_ids();
+        assert_eq!(after.len(), 3);
+        let id_of = |ids: &[(String, u64)], text: &str| {
+            ids.iter().find(|(t, _)| t == text).map(|(_, id)| *id)
+        };
+        for text in ["fix the parser bug", "the parser reads files"] {
+            assert_eq!(id_of(&after, text), id_of(&before, text));
+        }
+        assert_eq!(id_of(&after, "add test"), id_of(&before, "add a test"));
+
+        let vectors = VectorIndex::open(&store).unwrap();
+        let mut vector_ids: Vec<u64> = vectors.doc_ids().collect();
+        vector_ids.sort();
+        let mut ids: Vec<u64> = after.iter().map(|(_, id)| *id).collect();
+        ids.sort();
+        assert_eq!(vector_ids, ids);
+        assert_eq!(crate::vector::stored_vectors(&store), 3);
+
+        // The session mean drops the old text's vector for the new one.
+        let mut expected = vec![0.0f32; mean_before.len()];
+        for doc_id in ids {
+            let vector = vectors.get(doc_id).unwrap().unwrap();
+            let norm = vector.iter().map(|x| x * x).sum::<f32>().sqrt();
+            for (total, x) in expected.iter_mut().zip(&vector) {
+                *total += x / norm / 3.0;
+            }
+        }
+        let mean_after = session_mean();
+        assert!(
+            mean_after
+                .iter()
+                .zip(&mean_before)
+                .any(|(a, b)| (a - b).abs() > 1e-4)
+        );
+        for (got, want) in mean_after.iter().zip(&expected) {
+            assert!((got - want).abs() < 1e-5, "{mean_after:?} != {expected:?}");
+        }
     }
-    text.truncate(end);
-    text
-}
 
-fn is_embedding_role(role: &str) -> bool {
-    role == "user" || role == "assistant"
+    #[test]
+    fn test_entry_ids_are_stable() {
+        let first: Vec<u64> = {
+            let mut ids = EntryIds::new("/a.jsonl", Some("uuid-1"), 10);
+            vec![ids.next(), ids.next()]
+        };
+        let mut ids = EntryIds::new("/a.jsonl", Some("uuid-1"), 99);
+        assert_eq!(vec![ids.next(), ids.next()], first);
+        assert_ne!(first[0], first[1]);
+
+        // Without a uuid the byte offset names the entry.
+        let at = |path, offset| EntryIds::new(path, None, offset).next();
+        assert_eq!(at("/a.jsonl", 10), at("/a.jsonl", 10));
+        assert_ne!(at("/a.jsonl", 10), at("/a.jsonl", 11));
+        assert_ne!(at("/a.jsonl", 10), at("/b.jsonl", 10));
+
+        let id = first[0];
+        assert_eq!(next_free_doc_id(id), next_free_doc_id(id));
+        assert_ne!(next_free_doc_id(id), id);
+    }
 }
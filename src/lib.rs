@@ -1,10 +1,21 @@
+pub mod activity;
+pub mod cache;
 pub mod cli;
+pub mod commands;
 pub mod config;
 pub mod embed;
+pub mod files;
+pub mod fusion;
 pub mod index;
 pub mod ingest;
+pub mod models;
+pub mod patch;
 pub mod progress;
+pub mod replay;
+pub mod snippet;
 pub mod state;
+pub mod tokenizer;
+pub mod topics;
 pub mod tui;
 pub mod types;
 pub mod vector;
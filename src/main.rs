@@ -1,10 +1,21 @@
+mod activity;
+mod cache;
 mod cli;
+mod commands;
 mod config;
 mod embed;
+mod files;
+mod fusion;
 mod index;
 mod ingest;
+mod models;
+mod patch;
 mod progress;
+mod replay;
+mod snippet;
 mod state;
+mod tokenizer;
+mod topics;
 mod tui;
 mod types;
 mod vector;
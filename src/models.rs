@@ -0,0 +1,251 @@
+use anyhow::{Result, anyhow};
+use hf_hub::api::sync::ApiBuilder;
+use hf_hub::{Cache, Repo};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Tokenizer files fastembed reads next to every ONNX model.
+pub const TOKENIZER_FILES: [&str; 4] = [
+    "tokenizer.json",
+    "config.json",
+    "special_tokens_map.json",
+    "tokenizer_config.json",
+];
+
+/// A built-in model and the files memex needs from its Hugging Face repo.
+#[derive(Debug, Clone)]
+pub struct ModelFiles {
+    pub name: String,
+    /// "embedding" or "reranker".
+    pub kind: &'static str,
+    pub repo: String,
+    pub files: Vec<String>,
+}
+
+/// Where model weights are downloaded to, and whether memex may download.
+#[derive(Debug, Clone)]
+pub struct ModelCache {
+    pub dir: PathBuf,
+    /// Fail instead of downloading a model that isn't cached.
+    pub offline: bool,
+}
+
+/// One built-in model as `memex models list` shows it.
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelStatus {
+    pub name: String,
+    pub kind: &'static str,
+    pub repo: String,
+    pub downloaded: bool,
+    /// Bytes on disk, including partial downloads.
+    pub size: u64,
+    pub path: Option<PathBuf>,
+    /// The configured embedding model or reranker.
+    pub active: bool,
+}
+
+/// Result of checking one file against its content hash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileCheck {
+    Ok,
+    Missing,
+    Mismatch,
+    /// Small files are named by their git hash, which isn't checked.
+    Unchecked,
+}
+
+impl ModelCache {
+    fn cache(&self) -> Cache {
+        Cache::new(self.dir.clone())
+    }
+
+    /// The model's directory in the cache.
+    pub fn repo_dir(&self, model: &ModelFiles) -> PathBuf {
+        self.dir.join(Repo::model(model.repo.clone()).folder_name())
+    }
+
+    /// Cached path of each file, or None when the file isn't downloaded.
+    fn cached(&self, model: &ModelFiles) -> Vec<Option<PathBuf>> {
+        let repo = self.cache().model(model.repo.clone());
+        model.files.iter().map(|file| repo.get(file)).collect()
+    }
+
+    /// Snapshot directory holding all of the model's files, if they are.
+    pub fn snapshot(&self, model: &ModelFiles) -> Option<PathBuf> {
+        let cached = self.cached(model);
+        let first = cached.first()?.as_ref()?;
+        if cached.iter().any(Option::is_none) {
+            return None;
+        }
+        first
+            .ancestors()
+            .nth(model.files[0].split('/').count())
+            .map(Path::to_path_buf)
+    }
+
+    /// Download the model's missing files, or fail when offline. Returns the
+    /// snapshot directory.
+    pub fn ensure(&self, model: &ModelFiles, progress: bool) -> Result<PathBuf> {
+        if let Some(dir) = self.snapshot(model) {
+            return Ok(dir);
+        }
+        if self.offline {
+            return Err(anyhow!(
+                "model '{}' is not downloaded and offline = true; run `memex models pull {}` \
+                 where the Hugging Face Hub is reachable, then copy {} to this machine",
+                model.name,
+                model.name,
+                self.dir.display()
+            ));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let api = ApiBuilder::new()
+            .with_cache_dir(self.dir.clone())
+            .with_progress(progress)
+            .build()?;
+        let repo = api.model(model.repo.clone());
+        for file in &model.files {
+            repo.get(file)
+                .map_err(|err| anyhow!("downloading {} from {}: {err}", file, model.repo))?;
+        }
+        self.snapshot(model)
+            .ok_or_else(|| anyhow!("model '{}' is incomplete after download", model.name))
+    }
+
+    pub fn status(&self, model: &ModelFiles, active: bool) -> ModelStatus {
+        let dir = self.repo_dir(model);
+        ModelStatus {
+            name: model.name.clone(),
+            kind: model.kind,
+            repo: model.repo.clone(),
+            downloaded: self.snapshot(model).is_some(),
+            size: dir_size(&dir),
+            path: dir.exists().then_some(dir),
+            active,
+        }
+    }
+
+    /// Hash each downloaded file and compare it with the blob name the hub
+    /// gave it (the file's sha256 for weights).
+    pub fn verify(&self, model: &ModelFiles) -> Result<Vec<(String, FileCheck)>> {
+        let mut checks = Vec::new();
+        for (file, cached) in model.files.iter().zip(self.cached(model)) {
+            let check = match cached {
+                None => FileCheck::Missing,
+                Some(pointer) => {
+                    let blob = std::fs::canonicalize(&pointer)?;
+                    let expected = blob
+                        .file_name()
+                        .and_then(|name| name.to_str())
+                        .unwrap_or_default()
+                        .to_string();
+                    if expected.len() == 64 && expected.chars().all(|c| c.is_ascii_hexdigit()) {
+                        if sha256_file(&blob)? == expected.to_ascii_lowercase() {
+                            FileCheck::Ok
+                        } else {
+                            FileCheck::Mismatch
+                        }
+                    } else {
+                        FileCheck::Unchecked
+                    }
+                }
+            };
+            checks.push((file.clone(), check));
+        }
+        Ok(checks)
+    }
+
+    /// Delete the model's cache directory. False when it wasn't there.
+    pub fn remove(&self, model: &ModelFiles) -> Result<bool> {
+        let dir = self.repo_dir(model);
+        if !dir.exists() {
+            return Ok(false);
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(true)
+    }
+}
+
+fn sha256_file(path: &Path) -> Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 1 << 20];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Total size of the regular files under `dir`, not following symlinks.
+fn dir_size(dir: &Path) -> u64 {
+    walkdir::WalkDir::new(dir)
+        .into_iter()
+        .flatten()
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.metadata().map_or(0, |meta| meta.len()))
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    /// Lay out a model the way hf-hub caches it: blobs named by hash, and
+    /// snapshot entries linking to them.
+    fn fake_download(cache: &ModelCache, model: &ModelFiles, contents: &[&[u8]]) {
+        let dir = cache.repo_dir(model);
+        std::fs::create_dir_all(dir.join("refs")).unwrap();
+        std::fs::write(dir.join("refs/main"), "abc123").unwrap();
+        for (file, data) in model.files.iter().zip(contents) {
+            let hash = format!("{:x}", Sha256::digest(data));
+            let blob = dir.join("blobs").join(&hash);
+            std::fs::create_dir_all(blob.parent().unwrap()).unwrap();
+            std::fs::write(&blob, data).unwrap();
+            let pointer = dir.join("snapshots/abc123").join(file);
+            std::fs::create_dir_all(pointer.parent().unwrap()).unwrap();
+            std::os::unix::fs::symlink(&blob, &pointer).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_verify_and_remove_cached_model() {
+        let tmp = TempDir::new().unwrap();
+        let cache = ModelCache {
+            dir: tmp.path().to_path_buf(),
+            offline: true,
+        };
+        let model = ModelFiles {
+            name: "test".to_string(),
+            kind: "embedding",
+            repo: "org/test-model".to_string(),
+            files: vec!["onnx/model.onnx".to_string(), "tokenizer.json".to_string()],
+        };
+        assert!(!cache.status(&model, false).downloaded);
+        assert!(cache.ensure(&model, false).is_err());
+
+        fake_download(&cache, &model, &[b"weights", b"{}"]);
+        let snapshot = cache.ensure(&model, false).unwrap();
+        assert!(snapshot.join("tokenizer.json").exists());
+        let status = cache.status(&model, false);
+        assert!(status.downloaded);
+        // Both blobs plus the ref; snapshot links aren't counted.
+        assert_eq!(status.size, 15);
+        let checks = cache.verify(&model).unwrap();
+        assert!(checks.iter().all(|(_, check)| *check == FileCheck::Ok));
+
+        let blob = std::fs::canonicalize(snapshot.join("onnx/model.onnx")).unwrap();
+        std::fs::write(&blob, b"corrupt").unwrap();
+        assert_eq!(cache.verify(&model).unwrap()[0].1, FileCheck::Mismatch);
+
+        assert!(cache.remove(&model).unwrap());
+        assert!(!cache.remove(&model).unwrap());
+        assert_eq!(cache.verify(&model).unwrap()[0].1, FileCheck::Missing);
+    }
+}
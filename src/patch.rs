@@ -0,0 +1,456 @@
+use crate::files::command_strings;
+use anyhow::{Result, anyhow};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PatchKind {
+    Add,
+    Update,
+    Delete,
+}
+
+/// One hunk of a unified diff. Lines keep their ` `, `-` or `+` prefix.
+///
+/// Codex `apply_patch` hunks carry no line numbers, only an optional `@@`
+/// context line, so the ranges are optional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Hunk {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub old_start: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_start: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub context: Option<String>,
+    pub lines: Vec<String>,
+}
+
+/// A change an agent applied to one file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FilePatch {
+    pub path: String,
+    pub kind: PatchKind,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub move_to: Option<String>,
+    /// File contents before the change, when the agent's tool reported them.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub original: Option<String>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl Hunk {
+    /// Lines the hunk expects to find (context and removals), without prefixes.
+    pub fn old_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|l| !l.starts_with('+') && !l.starts_with('\\'))
+            .map(|l| l.get(1..).unwrap_or(""))
+            .collect()
+    }
+
+    /// Lines the hunk leaves behind (context and additions), without prefixes.
+    pub fn new_lines(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter(|l| !l.starts_with('-') && !l.starts_with('\\'))
+            .map(|l| l.get(1..).unwrap_or(""))
+            .collect()
+    }
+
+    /// The `@@ ... @@` header line, with ranges when they are known.
+    pub fn header(&self) -> String {
+        let old_len = self.old_lines().len();
+        let new_len = self.new_lines().len();
+        let mut header = match (self.old_start, self.new_start) {
+            (Some(old), Some(new)) => format!("@@ -{old},{old_len} +{new},{new_len} @@"),
+            _ => "@@".to_string(),
+        };
+        if let Some(context) = &self.context {
+            header.push(' ');
+            header.push_str(context);
+        }
+        header
+    }
+}
+
+impl FilePatch {
+    /// Render as a standalone unified diff.
+    pub fn to_unified(&self) -> String {
+        let (old, new) = match self.kind {
+            PatchKind::Add => ("/dev/null", self.path.as_str()),
+            PatchKind::Delete => (self.path.as_str(), "/dev/null"),
+            PatchKind::Update => (
+                self.path.as_str(),
+                self.move_to.as_deref().unwrap_or(&self.path),
+            ),
+        };
+        let mut out = format!("--- {old}\n+++ {new}\n");
+        for hunk in &self.hunks {
+            out.push_str(&hunk.header());
+            out.push('\n');
+            for line in &hunk.lines {
+                out.push_str(line);
+                out.push('\n');
+            }
+        }
+        out
+    }
+
+    /// Apply to `content` (the file before this change).
+    pub fn apply(&self, content: &str) -> Result<String> {
+        match self.kind {
+            PatchKind::Delete => Ok(String::new()),
+            PatchKind::Add => Ok(join_lines(
+                &self
+                    .hunks
+                    .iter()
+                    .flat_map(|h| h.new_lines())
+                    .collect::<Vec<_>>(),
+                true,
+            )),
+            PatchKind::Update => {
+                let trailing_newline = content.is_empty() || content.ends_with('\n');
+                let mut lines: Vec<&str> = content.lines().collect();
+                let mut cursor = 0usize;
+                for hunk in &self.hunks {
+                    let old = hunk.old_lines();
+                    let new = hunk.new_lines();
+                    let hint = hunk
+                        .old_start
+                        .map(|s| (s as usize).saturating_sub(1))
+                        .unwrap_or(cursor);
+                    let at = find_lines(&lines, &old, hint, cursor).ok_or_else(|| {
+                        anyhow!("hunk {} does not apply to {}", hunk.header(), self.path)
+                    })?;
+                    lines.splice(at..at + old.len(), new.iter().copied());
+                    cursor = at + new.len();
+                }
+                Ok(join_lines(&lines, trailing_newline))
+            }
+        }
+    }
+}
+
+/// How much of a file's pre-session contents a changeset is based on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Baseline {
+    /// The agent's tool reported the contents before the first change.
+    Original,
+    /// The session created the file.
+    Created,
+    /// The contents before the session are unknown; the diff is a patch series.
+    Unknown,
+}
+
+/// Everything a session changed in one file.
+#[derive(Debug, Clone, Serialize)]
+pub struct Changeset {
+    pub path: String,
+    pub baseline: Baseline,
+    pub patches: usize,
+    pub diff: String,
+}
+
+/// Combine a file's patches, in the order they were applied, into one diff.
+///
+/// With a known baseline the patches are replayed and diffed against it;
+/// otherwise (or if a hunk fails to apply) they are emitted in sequence.
+pub fn changeset(patches: &[&FilePatch]) -> Option<Changeset> {
+    let first = patches.first()?;
+    let last = patches.last()?;
+    let path = first.path.clone();
+    let (baseline, base) = match (first.kind, &first.original) {
+        (PatchKind::Add, _) => (Baseline::Created, Some(String::new())),
+        (_, Some(original)) => (Baseline::Original, Some(original.clone())),
+        _ => (Baseline::Unknown, None),
+    };
+    let replayed = base.and_then(|base| {
+        let mut content = base.clone();
+        for patch in patches {
+            content = patch.apply(&content).ok()?;
+        }
+        Some((base, content))
+    });
+    let (baseline, diff) = match replayed {
+        Some((base, content)) => {
+            let old = if baseline == Baseline::Created {
+                "/dev/null"
+            } else {
+                path.as_str()
+            };
+            let new = match last.kind {
+                PatchKind::Delete => "/dev/null",
+                _ => last.move_to.as_deref().unwrap_or(&last.path),
+            };
+            let diff = similar::TextDiff::from_lines(&base, &content)
+                .unified_diff()
+                .context_radius(3)
+                .header(old, new)
+                .to_string();
+            (baseline, diff)
+        }
+        None => (
+            Baseline::Unknown,
+            patches.iter().map(|p| p.to_unified()).collect(),
+        ),
+    };
+    Some(Changeset {
+        path,
+        baseline,
+        patches: patches.len(),
+        diff,
+    })
+}
+
+/// Find `needle` in `lines`, preferring `hint`, then the first match at or after `from`.
+fn find_lines(lines: &[&str], needle: &[&str], hint: usize, from: usize) -> Option<usize> {
+    let fits = |at: usize| {
+        at + needle.len() <= lines.len()
+            && lines[at..at + needle.len()]
+                .iter()
+                .zip(needle)
+                .all(|(a, b)| a.trim_end() == b.trim_end())
+    };
+    if needle.is_empty() {
+        return Some(hint.min(lines.len()));
+    }
+    if fits(hint) {
+        return Some(hint);
+    }
+    (from..lines.len())
+        .chain(0..from.min(lines.len()))
+        .find(|&at| fits(at))
+}
+
+fn join_lines(lines: &[&str], trailing_newline: bool) -> String {
+    let mut out = lines.join("\n");
+    if trailing_newline && !lines.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+/// Patches described by a Claude `toolUseResult` for Edit, MultiEdit or Write.
+pub fn claude_patches(result: &Value) -> Vec<FilePatch> {
+    let Some(path) = result.get("filePath").and_then(|v| v.as_str()) else {
+        return Vec::new();
+    };
+    let original = result
+        .get("originalFile")
+        .or_else(|| result.get("originalFileContents"))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_string());
+    if result.get("type").and_then(|v| v.as_str()) == Some("create") {
+        let content = result.get("content").and_then(|v| v.as_str()).unwrap_or("");
+        return vec![FilePatch {
+            path: path.to_string(),
+            kind: PatchKind::Add,
+            move_to: None,
+            original: None,
+            hunks: vec![Hunk {
+                old_start: Some(0),
+                new_start: Some(1),
+                context: None,
+                lines: content.lines().map(|l| format!("+{l}")).collect(),
+            }],
+        }];
+    }
+    let Some(structured) = result.get("structuredPatch").and_then(|v| v.as_array()) else {
+        return Vec::new();
+    };
+    let hunks: Vec<Hunk> = structured
+        .iter()
+        .filter_map(|hunk| {
+            let start = |key: &str| hunk.get(key).and_then(|v| v.as_u64()).map(|n| n as u32);
+            let lines = hunk
+                .get("lines")?
+                .as_array()?
+                .iter()
+                .filter_map(|l| l.as_str().map(|s| s.to_string()))
+                .collect();
+            Some(Hunk {
+                old_start: start("oldStart"),
+                new_start: start("newStart"),
+                context: None,
+                lines,
+            })
+        })
+        .collect();
+    if hunks.is_empty() {
+        return Vec::new();
+    }
+    vec![FilePatch {
+        path: path.to_string(),
+        kind: PatchKind::Update,
+        move_to: None,
+        original,
+        hunks,
+    }]
+}
+
+/// Patches in a Codex `apply_patch` call, given its raw input or JSON arguments.
+pub fn tool_patches(input: &str) -> Vec<FilePatch> {
+    if !input.contains("*** Begin Patch") {
+        return Vec::new();
+    }
+    match serde_json::from_str::<Value>(input) {
+        Ok(Value::Object(obj)) => {
+            let mut out = Vec::new();
+            if let Some(patch) = obj.get("input").and_then(|v| v.as_str()) {
+                out.extend(parse_apply_patch(patch));
+            }
+            if let Some(command) = obj.get("command") {
+                for part in command_strings(command) {
+                    out.extend(parse_apply_patch(part));
+                }
+            }
+            out
+        }
+        _ => parse_apply_patch(input),
+    }
+}
+
+/// Parse the body of a Codex `apply_patch` call.
+pub fn parse_apply_patch(text: &str) -> Vec<FilePatch> {
+    let mut out: Vec<FilePatch> = Vec::new();
+    let mut in_patch = false;
+    for line in text.lines() {
+        let trimmed = line.trim_end();
+        if trimmed == "*** Begin Patch" {
+            in_patch = true;
+            continue;
+        }
+        if !in_patch {
+            continue;
+        }
+        if trimmed == "*** End Patch" {
+            in_patch = false;
+            continue;
+        }
+        if trimmed == "*** End of File" {
+            continue;
+        }
+        let header = [
+            ("*** Add File: ", PatchKind::Add),
+            ("*** Update File: ", PatchKind::Update),
+            ("*** Delete File: ", PatchKind::Delete),
+        ]
+        .into_iter()
+        .find_map(|(prefix, kind)| trimmed.strip_prefix(prefix).map(|p| (p, kind)));
+        if let Some((path, kind)) = header {
+            out.push(FilePatch {
+                path: path.trim().to_string(),
+                kind,
+                move_to: None,
+                original: None,
+                hunks: Vec::new(),
+            });
+            continue;
+        }
+        let Some(current) = out.last_mut() else {
+            continue;
+        };
+        if let Some(dest) = trimmed.strip_prefix("*** Move to: ") {
+            current.move_to = Some(dest.trim().to_string());
+            continue;
+        }
+        if let Some(rest) = trimmed.strip_prefix("@@") {
+            let context = rest.trim();
+            current.hunks.push(Hunk {
+                old_start: None,
+                new_start: None,
+                context: (!context.is_empty()).then(|| context.to_string()),
+                lines: Vec::new(),
+            });
+            continue;
+        }
+        if current.hunks.is_empty() {
+            let start = (current.kind == PatchKind::Add).then_some(1);
+            current.hunks.push(Hunk {
+                old_start: start.map(|_| 0),
+                new_start: start,
+                context: None,
+                lines: Vec::new(),
+            });
+        }
+        let line = if line.is_empty() { " " } else { line };
+        if let Some(hunk) = current.hunks.last_mut() {
+            hunk.lines.push(line.to_string());
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_apply_patch() {
+        let text = "*** Begin Patch\n*** Add File: README.md\n+# Demo\n+\n*** Update File: src/lib.rs\n*** Move to: src/core.rs\n@@ fn main\n-    old();\n+    new();\n*** Delete File: old.txt\n*** End Patch";
+        let patches = parse_apply_patch(text);
+        assert_eq!(patches.len(), 3);
+        assert_eq!(patches[0].kind, PatchKind::Add);
+        assert_eq!(patches[0].apply("").unwrap(), "# Demo\n\n");
+        assert_eq!(patches[1].move_to.as_deref(), Some("src/core.rs"));
+        assert_eq!(patches[1].hunks[0].context.as_deref(), Some("fn main"));
+        assert_eq!(
+            patches[1].apply("fn main() {\n    old();\n}\n").unwrap(),
+            "fn main() {\n    new();\n}\n"
+        );
+        assert_eq!(patches[2].kind, PatchKind::Delete);
+    }
+
+    #[test]
+    fn test_claude_structured_patch() {
+        let result = serde_json::json!({
+            "filePath": "/repo/config.toml",
+            "originalFile": "[server]\nport = 8080\nhost = \"localhost\"\n",
+            "structuredPatch": [{
+                "oldStart": 1, "oldLines": 3, "newStart": 1, "newLines": 3,
+                "lines": [" [server]", "-port = 8080", "+port = 9090", " host = \"localhost\""]
+            }]
+        });
+        let patches = claude_patches(&result);
+        assert_eq!(patches.len(), 1);
+        let patch = &patches[0];
+        assert_eq!(patch.hunks[0].header(), "@@ -1,3 +1,3 @@");
+        let updated = patch.apply(patch.original.as_deref().unwrap()).unwrap();
+        assert_eq!(updated, "[server]\nport = 9090\nhost = \"localhost\"\n");
+        assert!(patch.apply("unrelated\n").is_err());
+    }
+
+    #[test]
+    fn test_changeset_unified_diff() {
+        let edit = |old: &str, new: &str, original: Option<&str>| FilePatch {
+            path: "/repo/notes.txt".to_string(),
+            kind: PatchKind::Update,
+            move_to: None,
+            original: original.map(str::to_string),
+            hunks: vec![Hunk {
+                old_start: None,
+                new_start: None,
+                context: None,
+                lines: vec![format!("-{old}"), format!("+{new}")],
+            }],
+        };
+        let first = edit("b", "B", Some("a\nb\nc\nd\ne\n"));
+        let second = edit("d", "D", None);
+        let set = changeset(&[&first, &second]).unwrap();
+        assert_eq!(set.baseline, Baseline::Original);
+        assert_eq!(set.patches, 2);
+        assert_eq!(
+            set.diff,
+            "--- /repo/notes.txt\n+++ /repo/notes.txt\n@@ -1,5 +1,5 @@\n a\n-b\n+B\n c\n-d\n+D\n e\n"
+        );
+
+        let set = changeset(&[&second]).unwrap();
+        assert_eq!(set.baseline, Baseline::Unknown);
+        assert_eq!(
+            set.diff,
+            "--- /repo/notes.txt\n+++ /repo/notes.txt\n@@\n-d\n+D\n"
+        );
+    }
+}
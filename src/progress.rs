@@ -516,7 +516,7 @@ fn format_count(value: u64) -> String {
     out.chars().rev().collect()
 }
 
-fn format_bytes(bytes: u64) -> String {
+pub fn format_bytes(bytes: u64) -> String {
     const KB: f64 = 1024.0;
     const MB: f64 = KB * 1024.0;
     const GB: f64 = MB * 1024.0;
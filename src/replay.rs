@@ -0,0 +1,458 @@
+use crate::patch::{FilePatch, PatchKind};
+use crate::types::Record;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// Claude's Read tool numbers each line: `    12→text` (or a tab in older versions).
+static READ_LINE_RE: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^\s*(\d+)(?:→|\t)(.*)$").expect("read line regex"));
+
+/// Claude's Read tool returns at most this many lines unless asked for more.
+const READ_DEFAULT_LIMIT: usize = 2000;
+
+/// What an agent could have known about a file's contents at some point.
+///
+/// Lines are addressed by position; `None` marks a line whose existence is
+/// known (from Read line numbers) but whose text is not. Anything past the
+/// last line is unknown unless `complete` is set.
+#[derive(Debug, Clone, Default)]
+pub struct FileState {
+    lines: Vec<Option<String>>,
+    complete: bool,
+    trailing_newline: bool,
+    deleted: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum Segment {
+    Known {
+        start: usize,
+        lines: Vec<String>,
+    },
+    /// A gap starting at line `start`; `lines` is `None` when its length is unknown.
+    Unknown {
+        start: usize,
+        lines: Option<usize>,
+    },
+}
+
+impl FileState {
+    pub fn is_complete(&self) -> bool {
+        self.deleted || (self.complete && self.lines.iter().all(|l| l.is_some()))
+    }
+
+    pub fn is_deleted(&self) -> bool {
+        self.deleted
+    }
+
+    pub fn has_content(&self) -> bool {
+        self.deleted || self.complete || self.lines.iter().any(|l| l.is_some())
+    }
+
+    /// The whole file, if every line is known.
+    pub fn text(&self) -> Option<String> {
+        if !self.is_complete() {
+            return None;
+        }
+        let mut out = self
+            .lines
+            .iter()
+            .map(|l| l.as_deref().unwrap_or(""))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if self.trailing_newline && !self.lines.is_empty() {
+            out.push('\n');
+        }
+        Some(out)
+    }
+
+    pub fn segments(&self) -> Vec<Segment> {
+        let mut out = Vec::new();
+        let mut idx = 0;
+        while idx < self.lines.len() {
+            let start = idx;
+            if self.lines[idx].is_some() {
+                let mut lines = Vec::new();
+                while let Some(Some(line)) = self.lines.get(idx) {
+                    lines.push(line.clone());
+                    idx += 1;
+                }
+                out.push(Segment::Known {
+                    start: start + 1,
+                    lines,
+                });
+            } else {
+                while matches!(self.lines.get(idx), Some(None)) {
+                    idx += 1;
+                }
+                out.push(Segment::Unknown {
+                    start: start + 1,
+                    lines: Some(idx - start),
+                });
+            }
+        }
+        if !self.complete && !self.deleted {
+            out.push(Segment::Unknown {
+                start: self.lines.len() + 1,
+                lines: None,
+            });
+        }
+        out
+    }
+
+    /// The file was written in full.
+    pub fn set_content(&mut self, content: &str) {
+        self.lines = content.lines().map(|l| Some(l.to_string())).collect();
+        self.trailing_newline = content.ends_with('\n');
+        self.complete = true;
+        self.deleted = false;
+    }
+
+    pub fn delete(&mut self) {
+        *self = FileState {
+            deleted: true,
+            complete: true,
+            ..FileState::default()
+        };
+    }
+
+    /// Lines seen starting at 1-based line `start`; `complete` when they run to the end of the file.
+    pub fn observe(&mut self, start: usize, lines: Vec<String>, complete: bool) {
+        let offset = start.saturating_sub(1);
+        let end = offset + lines.len();
+        if self.lines.len() < end {
+            self.lines.resize(end, None);
+        }
+        for (slot, line) in self.lines[offset..end].iter_mut().zip(lines) {
+            *slot = Some(line);
+        }
+        if complete {
+            self.lines.truncate(end);
+            self.complete = true;
+            self.trailing_newline = true;
+        }
+        self.deleted = false;
+    }
+
+    /// Apply an Edit-style string replacement inside known text. Returns false
+    /// when `old` is not found in any known run of lines.
+    pub fn replace(&mut self, old: &str, new: &str, all: bool) -> bool {
+        if old.is_empty() {
+            return false;
+        }
+        let mut replaced = false;
+        let mut idx = 0;
+        while idx < self.lines.len() {
+            if self.lines[idx].is_none() {
+                idx += 1;
+                continue;
+            }
+            let start = idx;
+            while matches!(self.lines.get(idx), Some(Some(_))) {
+                idx += 1;
+            }
+            let run = self.lines[start..idx]
+                .iter()
+                .map(|l| l.as_deref().unwrap_or(""))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let at_end = idx == self.lines.len() && self.complete && self.trailing_newline;
+            let run = if at_end { run + "\n" } else { run };
+            if !run.contains(old) {
+                continue;
+            }
+            let updated = if all {
+                run.replace(old, new)
+            } else {
+                run.replacen(old, new, 1)
+            };
+            let updated = if at_end {
+                self.trailing_newline = updated.ends_with('\n');
+                updated.strip_suffix('\n').unwrap_or(&updated).to_string()
+            } else {
+                updated
+            };
+            let new_lines: Vec<Option<String>> =
+                updated.split('\n').map(|l| Some(l.to_string())).collect();
+            let added = new_lines.len();
+            self.lines.splice(start..idx, new_lines);
+            idx = start + added;
+            replaced = true;
+            if !all {
+                break;
+            }
+        }
+        replaced
+    }
+
+    /// Apply a captured patch. Returns the hunks that could not be placed.
+    pub fn apply_patch(&mut self, patch: &FilePatch) -> usize {
+        match patch.kind {
+            PatchKind::Delete => {
+                self.delete();
+                return 0;
+            }
+            PatchKind::Add => {
+                if let Ok(content) = patch.apply("") {
+                    self.set_content(&content);
+                }
+                return 0;
+            }
+            PatchKind::Update => {}
+        }
+        if let Some(original) = &patch.original {
+            self.set_content(original);
+        }
+        if let Some(text) = self.text()
+            && let Ok(updated) = patch.apply(&text)
+        {
+            self.set_content(&updated);
+            return 0;
+        }
+        let mut failed = 0;
+        for hunk in &patch.hunks {
+            let old = hunk.old_lines();
+            let new: Vec<Option<String>> = hunk
+                .new_lines()
+                .into_iter()
+                .map(|l| Some(l.to_string()))
+                .collect();
+            let hint = hunk.old_start.map(|s| (s as usize).saturating_sub(1));
+            match self.find_known(&old, hint) {
+                Some(at) => {
+                    self.lines.splice(at..at + old.len(), new);
+                }
+                None => failed += 1,
+            }
+        }
+        failed
+    }
+
+    fn find_known(&self, needle: &[&str], hint: Option<usize>) -> Option<usize> {
+        if needle.is_empty() {
+            return None;
+        }
+        let fits = |at: usize| {
+            at + needle.len() <= self.lines.len()
+                && self.lines[at..at + needle.len()]
+                    .iter()
+                    .zip(needle)
+                    .all(|(line, want)| {
+                        line.as_deref()
+                            .is_some_and(|l| l.trim_end() == want.trim_end())
+                    })
+        };
+        if let Some(hint) = hint
+            && fits(hint)
+        {
+            return Some(hint);
+        }
+        (0..self.lines.len()).find(|&at| fits(at))
+    }
+}
+
+/// A file as replayed from a session's tool calls.
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub state: FileState,
+    /// The last turn that contributed to `state`.
+    pub last_turn: Option<u32>,
+    /// Changes that could not be placed in the known contents.
+    pub notes: Vec<String>,
+}
+
+/// Replay what a session wrote, edited and read in `path`, up to and including `turn`.
+///
+/// `records` must be in session order. Claude edits are taken from the patches
+/// on their results when present, falling back to the call's own input.
+pub fn replay_file(records: &[Record], path: &str, turn: Option<u32>) -> Replay {
+    let mut replay = Replay {
+        state: FileState::default(),
+        last_turn: None,
+        notes: Vec::new(),
+    };
+    let mut pending: HashMap<&str, &Record> = HashMap::new();
+    for record in records {
+        if turn.is_some_and(|t| record.turn_id > t) {
+            break;
+        }
+        let patches: Vec<&FilePatch> = record.patches.iter().filter(|p| p.path == path).collect();
+        if !patches.is_empty() {
+            for patch in patches {
+                let failed = replay.state.apply_patch(patch);
+                if failed > 0 {
+                    replay.notes.push(format!(
+                        "turn {}: {failed} hunk(s) fall outside the known contents",
+                        record.turn_id
+                    ));
+                }
+            }
+            replay.last_turn = Some(record.turn_id);
+            if let Some(id) = record.tool_call_id.as_deref() {
+                pending.remove(id);
+            }
+            continue;
+        }
+        match record.role.as_str() {
+            "tool_use" if record.files.iter().any(|f| f == path) => {
+                match record.tool_call_id.as_deref() {
+                    Some(id) => {
+                        pending.insert(id, record);
+                    }
+                    None => {
+                        apply_call(&mut replay, record, None);
+                        replay.last_turn = Some(record.turn_id);
+                    }
+                }
+            }
+            "tool_result" => {
+                let Some(call) = record
+                    .tool_call_id
+                    .as_deref()
+                    .and_then(|id| pending.remove(id))
+                else {
+                    continue;
+                };
+                if record.text.contains("<tool_use_error>") {
+                    continue;
+                }
+                let output = record.tool_output.as_deref().unwrap_or(&record.text);
+                apply_call(&mut replay, call, Some(output));
+                replay.last_turn = Some(record.turn_id);
+            }
+            _ => {}
+        }
+    }
+    replay
+}
+
+fn apply_call(replay: &mut Replay, call: &Record, output: Option<&str>) {
+    let Some(input) = call
+        .tool_input
+        .as_deref()
+        .and_then(|i| serde_json::from_str::<Value>(i).ok())
+    else {
+        return;
+    };
+    let str_field = |value: &Value, key: &str| {
+        value
+            .get(key)
+            .and_then(|v| v.as_str())
+            .unwrap_or("")
+            .to_string()
+    };
+    let edits: Vec<Value> = match call.tool_name.as_deref() {
+        Some("Read") => {
+            if let Some((start, lines)) = output.and_then(read_output_lines) {
+                let complete = read_is_complete(&input, start, lines.len());
+                replay.state.observe(start, lines, complete);
+            }
+            return;
+        }
+        Some("Write") => {
+            replay.state.set_content(&str_field(&input, "content"));
+            return;
+        }
+        Some("Edit") => vec![input],
+        Some("MultiEdit") => input
+            .get("edits")
+            .and_then(|v| v.as_array())
+            .cloned()
+            .unwrap_or_default(),
+        _ => return,
+    };
+    for edit in edits {
+        let all = edit
+            .get("replace_all")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false);
+        let old = str_field(&edit, "old_string");
+        let new = str_field(&edit, "new_string");
+        if !replay.state.replace(&old, &new, all) {
+            replay.notes.push(format!(
+                "turn {}: edit of {:?} falls outside the known contents",
+                call.turn_id,
+                old.lines().next().unwrap_or("")
+            ));
+        }
+    }
+}
+
+/// The numbered lines in a Claude Read result: `(first line number, lines)`.
+pub fn read_output_lines(output: &str) -> Option<(usize, Vec<String>)> {
+    let mut start = None;
+    let mut lines = Vec::new();
+    for line in output.lines() {
+        let Some(caps) = READ_LINE_RE.captures(line) else {
+            if start.is_some() {
+                break;
+            }
+            continue;
+        };
+        let number: usize = caps[1].parse().ok()?;
+        match start {
+            None => start = Some(number),
+            Some(first) if number != first + lines.len() => break,
+            Some(_) => {}
+        }
+        lines.push(caps[2].to_string());
+    }
+    Some((start?, lines))
+}
+
+/// Whether a Read call with this input returned the whole file, given how many lines came back.
+pub fn read_is_complete(input: &Value, start: usize, count: usize) -> bool {
+    let offset = input.get("offset").and_then(|v| v.as_u64()).unwrap_or(0);
+    let limit = input.get("limit").and_then(|v| v.as_u64());
+    start == 1 && offset <= 1 && limit.is_none() && count < READ_DEFAULT_LIMIT
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_read_then_edit() {
+        let mut state = FileState::default();
+        let output = "    10→fn main() {\n    11→    run(8080);\n    12→}\n\n<system-reminder>x</system-reminder>";
+        let (start, lines) = read_output_lines(output).unwrap();
+        assert_eq!((start, lines.len()), (10, 3));
+        state.observe(start, lines, false);
+        assert!(state.replace("run(8080)", "run(9090)", false));
+        assert!(!state.replace("missing", "x", false));
+        assert!(state.text().is_none());
+        assert_eq!(
+            state.segments(),
+            vec![
+                Segment::Unknown {
+                    start: 1,
+                    lines: Some(9)
+                },
+                Segment::Known {
+                    start: 10,
+                    lines: vec!["fn main() {".into(), "    run(9090);".into(), "}".into()]
+                },
+                Segment::Unknown {
+                    start: 13,
+                    lines: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_full_write_then_edit() {
+        let mut state = FileState::default();
+        state.set_content("a\nb\nc\n");
+        assert!(state.replace("b\nc\n", "B\n", false));
+        assert_eq!(state.text().as_deref(), Some("a\nB\n"));
+        let input = serde_json::json!({ "file_path": "/x" });
+        assert!(read_is_complete(&input, 1, 3));
+        let input = serde_json::json!({ "file_path": "/x", "limit": 3 });
+        assert!(!read_is_complete(&input, 1, 3));
+    }
+}
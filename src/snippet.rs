@@ -0,0 +1,221 @@
+use crate::index::{QueryOptions, SearchIndex};
+use anyhow::Result;
+use regex::Regex;
+use std::ops::Range;
+use tantivy::snippet::SnippetGenerator;
+
+/// Where a hit's highlights come from.
+pub enum Highlighter {
+    /// Terms of the tantivy query. `window` picks the best fragment; `all`
+    /// has an unbounded window, so its single fragment starts at offset 0 and
+    /// carries every highlight.
+    Query {
+        window: SnippetGenerator,
+        all: SnippetGenerator,
+    },
+    Regex(Regex),
+}
+
+/// Byte ranges into a record's text.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Highlights {
+    pub window: Option<Range<usize>>,
+    pub matches: Vec<Range<usize>>,
+}
+
+impl Highlighter {
+    /// Highlighter for the text terms of `options.query`, if it has any.
+    pub fn for_query(
+        index: &SearchIndex,
+        options: &QueryOptions,
+        window_chars: usize,
+    ) -> Result<Option<Self>> {
+        let Some(window) = index.snippet_generator(options, window_chars)? else {
+            return Ok(None);
+        };
+        let Some(all) = index.snippet_generator(options, usize::MAX)? else {
+            return Ok(None);
+        };
+        Ok(Some(Highlighter::Query { window, all }))
+    }
+
+    pub fn highlight(&self, text: &str, window_chars: usize) -> Highlights {
+        match self {
+            Highlighter::Query { window, all } => {
+                let matches = all.snippet(text).highlighted().to_vec();
+                let snippet = window.snippet(text);
+                let window = snippet.highlighted().first().and_then(|first| {
+                    let fragment = snippet.fragment();
+                    // Anchor the fragment on the absolute match it starts with.
+                    matches
+                        .iter()
+                        .filter_map(|m| m.start.checked_sub(first.start))
+                        .find(|start| text.get(*start..).is_some_and(|s| s.starts_with(fragment)))
+                        .map(|start| start..start + fragment.len())
+                });
+                Highlights { window, matches }
+            }
+            Highlighter::Regex(regex) => {
+                let matches: Vec<Range<usize>> = regex.find_iter(text).map(|m| m.range()).collect();
+                let window = matches
+                    .first()
+                    .map(|first| window_around(text, first.start, window_chars));
+                Highlights { window, matches }
+            }
+        }
+    }
+}
+
+/// A window of about `max` bytes starting a little before `at`.
+fn window_around(text: &str, at: usize, max: usize) -> Range<usize> {
+    let mut start = at.saturating_sub(max / 4);
+    while !text.is_char_boundary(start) {
+        start -= 1;
+    }
+    start..floor_boundary(text, start.saturating_add(max))
+}
+
+/// The last char boundary at or before `at`.
+fn floor_boundary(text: &str, at: usize) -> usize {
+    let mut at = at.min(text.len());
+    while !text.is_char_boundary(at) {
+        at -= 1;
+    }
+    at
+}
+
+/// Consecutive sentences of `text` grouped into windows of at most `max`
+/// bytes. A sentence longer than `max` is cut.
+pub fn sentence_windows(text: &str, max: usize) -> Vec<Range<usize>> {
+    let mut sentences = Vec::new();
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, ch)) = chars.next() {
+        let end = i + ch.len_utf8();
+        let boundary = ch == '\n'
+            || (matches!(ch, '.' | '!' | '?')
+                && chars.peek().is_none_or(|(_, next)| next.is_whitespace()));
+        if boundary {
+            sentences.push(start..end);
+            start = end;
+        }
+    }
+    sentences.push(start..text.len());
+
+    let mut windows: Vec<Range<usize>> = Vec::new();
+    for sentence in sentences {
+        let trimmed = text[sentence.clone()].trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let from = sentence.start + text[sentence.clone()].find(trimmed).unwrap_or(0);
+        let to = from + trimmed.len();
+        match windows.last_mut() {
+            Some(window) if to - window.start <= max => window.end = to,
+            _ => windows.push(from..to.min(floor_boundary(text, from.saturating_add(max)))),
+        }
+    }
+    windows
+}
+
+/// Index of the embedding most similar to `query` by cosine similarity.
+pub fn best_match(query: &[f32], candidates: &[Vec<f32>]) -> Option<usize> {
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let query_norm = norm(query);
+    candidates
+        .iter()
+        .enumerate()
+        .map(|(i, candidate)| {
+            let dot: f32 = query.iter().zip(candidate).map(|(a, b)| a * b).sum();
+            let denom = query_norm * norm(candidate);
+            (i, if denom > 0.0 { dot / denom } else { 0.0 })
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::{Record, SourceKind};
+
+    #[test]
+    fn test_query_highlights() {
+        let dir = tempfile::tempdir().unwrap();
+        let index = SearchIndex::open_or_create(dir.path()).unwrap();
+        let mut writer = index.writer().unwrap();
+        let text = format!(
+            "{} The parseClaudeFile helper panics on empty lines. {}",
+            "Unrelated preamble. ".repeat(20),
+            "More unrelated text. ".repeat(20)
+        );
+        let record = Record {
+            source: SourceKind::Claude,
+            doc_id: 1,
+            ts: 0,
+            project: "demo".to_string(),
+            session_id: "s1".to_string(),
+            turn_id: 0,
+            role: "assistant".to_string(),
+            text: text.clone(),
+            tool_name: None,
+            tool_input: None,
+            tool_output: None,
+            files: Vec::new(),
+            command: None,
+            exit_code: None,
+            tool_call_id: None,
+            patches: Vec::new(),
+            source_path: "/tmp/s1.jsonl".to_string(),
+        };
+        index.add_record(&mut writer, &record).unwrap();
+        writer.commit().unwrap();
+
+        let options = QueryOptions {
+            query: "parse_claude_file panics".to_string(),
+            project: None,
+            role: None,
+            tool: None,
+            session_id: None,
+            source: None,
+            since: None,
+            until: None,
+            fuzzy: None,
+            limit: 10,
+        };
+        let highlighter = Highlighter::for_query(&index, &options, 80)
+            .unwrap()
+            .unwrap();
+        let highlights = highlighter.highlight(&text, 80);
+        let matched: Vec<&str> = highlights
+            .matches
+            .iter()
+            .map(|m| &text[m.clone()])
+            .collect();
+        assert_eq!(matched, ["parse", "Claude", "File", "panics"]);
+        let window = highlights.window.unwrap();
+        assert!(text[window.clone()].starts_with("The parseClaudeFile helper panics"));
+        assert!(window.len() <= 80);
+    }
+
+    #[test]
+    fn test_sentence_windows() {
+        let text = "First one. Second one!\nThird is a longer sentence here. Fourth.";
+        let windows: Vec<&str> = sentence_windows(text, 25)
+            .into_iter()
+            .map(|w| &text[w])
+            .collect();
+        assert_eq!(
+            windows,
+            [
+                "First one. Second one!",
+                "Third is a longer sentenc",
+                "Fourth."
+            ]
+        );
+        assert_eq!(
+            best_match(&[1.0, 0.0], &[vec![0.0, 1.0], vec![0.9, 0.1]]),
+            Some(1)
+        );
+    }
+}
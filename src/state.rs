@@ -63,21 +63,11 @@ impl ScanCache {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct IngestState {
-    pub next_doc_id: u64,
     pub files: HashMap<String, FileState>,
 }
 
-impl Default for IngestState {
-    fn default() -> Self {
-        Self {
-            next_doc_id: 1,
-            files: HashMap::new(),
-        }
-    }
-}
-
 impl IngestState {
     pub fn load(path: &Path) -> anyhow::Result<Self> {
         if !path.exists() {
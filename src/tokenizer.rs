@@ -0,0 +1,240 @@
+use tantivy::Index;
+use tantivy::tokenizer::{
+    LowerCaser, RemoveLongFilter, TextAnalyzer, Token, TokenStream, Tokenizer, TokenizerManager,
+};
+
+/// Name the code-aware analyzer is registered under.
+pub const CODE_TOKENIZER: &str = "code";
+
+/// Characters that join identifier pieces into one compound token
+/// (`snake_case`, `a::b`, `src/main.rs`, `kebab-case`).
+const JOINERS: &[char] = &['_', ':', '/', '.', '-'];
+
+/// Splits text into identifier-aware tokens.
+///
+/// At index time every compound is emitted whole, followed by its
+/// `::`/path/snake parts and their camelCase leaves. Leaves take consecutive
+/// positions, so a phrase of leaves matches whichever spelling the source
+/// used. At query time only the leaves are emitted, which makes `parseFile`,
+/// `parse_file` and `parse file` equivalent queries.
+#[derive(Clone, Default)]
+pub struct CodeTokenizer {
+    query: bool,
+}
+
+pub struct CodeTokenStream {
+    tokens: Vec<Token>,
+    index: usize,
+}
+
+impl Tokenizer for CodeTokenizer {
+    type TokenStream<'a> = CodeTokenStream;
+
+    fn token_stream<'a>(&'a mut self, text: &'a str) -> CodeTokenStream {
+        CodeTokenStream {
+            tokens: code_tokens(text, self.query),
+            index: 0,
+        }
+    }
+}
+
+impl TokenStream for CodeTokenStream {
+    fn advance(&mut self) -> bool {
+        self.index += 1;
+        self.index <= self.tokens.len()
+    }
+
+    fn token(&self) -> &Token {
+        &self.tokens[self.index - 1]
+    }
+
+    fn token_mut(&mut self) -> &mut Token {
+        &mut self.tokens[self.index - 1]
+    }
+}
+
+fn analyzer(query: bool) -> TextAnalyzer {
+    TextAnalyzer::builder(CodeTokenizer { query })
+        .filter(RemoveLongFilter::limit(100))
+        .filter(LowerCaser)
+        .build()
+}
+
+/// Register the indexing analyzer on `index`.
+pub fn register(index: &Index) {
+    index.tokenizers().register(CODE_TOKENIZER, analyzer(false));
+}
+
+/// Tokenizers for parsing queries: tantivy's defaults plus the query-side
+/// code analyzer.
+pub fn query_tokenizers() -> TokenizerManager {
+    let manager = TokenizerManager::default();
+    manager.register(CODE_TOKENIZER, analyzer(true));
+    manager
+}
+
+fn code_tokens(text: &str, query: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    for (start, compound) in compounds(text) {
+        let parts = split_parts(compound);
+        let leaves: Vec<Vec<(usize, &str)>> =
+            parts.iter().map(|(_, part)| split_camel(part)).collect();
+        let leaf_count: usize = leaves.iter().map(Vec::len).sum();
+        if leaf_count == 0 {
+            continue;
+        }
+        let single_part = parts.len() == 1;
+        if !query && leaf_count > 1 {
+            push(&mut tokens, start, compound, position, leaf_count);
+        }
+        let mut leaf_position = position;
+        for ((part_start, part), part_leaves) in parts.iter().zip(&leaves) {
+            if !query && !single_part && part_leaves.len() > 1 {
+                push(
+                    &mut tokens,
+                    start + part_start,
+                    part,
+                    leaf_position,
+                    part_leaves.len(),
+                );
+            }
+            for (leaf_start, leaf) in part_leaves {
+                push(
+                    &mut tokens,
+                    start + part_start + leaf_start,
+                    leaf,
+                    leaf_position,
+                    1,
+                );
+                leaf_position += 1;
+            }
+        }
+        position += leaf_count;
+    }
+    tokens
+}
+
+fn push(tokens: &mut Vec<Token>, offset: usize, text: &str, position: usize, length: usize) {
+    tokens.push(Token {
+        offset_from: offset,
+        offset_to: offset + text.len(),
+        position,
+        text: text.to_string(),
+        position_length: length,
+    });
+}
+
+/// Runs of alphanumerics and joiners, with joiners trimmed from both ends.
+fn compounds(text: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut start = None;
+    for (i, ch) in text.char_indices() {
+        if ch.is_alphanumeric() || JOINERS.contains(&ch) {
+            start.get_or_insert(i);
+        } else if let Some(from) = start.take() {
+            out.extend(trim_joiners(text, from, i));
+        }
+    }
+    if let Some(from) = start {
+        out.extend(trim_joiners(text, from, text.len()));
+    }
+    out
+}
+
+fn trim_joiners(text: &str, from: usize, to: usize) -> Option<(usize, &str)> {
+    let run = &text[from..to];
+    let trimmed = run.trim_start_matches(JOINERS);
+    let offset = from + run.len() - trimmed.len();
+    let trimmed = trimmed.trim_end_matches(JOINERS);
+    (!trimmed.is_empty()).then_some((offset, trimmed))
+}
+
+fn split_parts(compound: &str) -> Vec<(usize, &str)> {
+    let mut out = Vec::new();
+    let mut offset = 0;
+    for piece in compound.split(JOINERS) {
+        if !piece.is_empty() {
+            out.push((offset, piece));
+        }
+        offset += piece.len() + 1;
+    }
+    out
+}
+
+/// Split `HTTPServerError2` into `HTTP`, `Server`, `Error2`.
+fn split_camel(part: &str) -> Vec<(usize, &str)> {
+    let chars: Vec<(usize, char)> = part.char_indices().collect();
+    let mut out = Vec::new();
+    let mut start = 0;
+    for i in 1..chars.len() {
+        let (at, ch) = chars[i];
+        let prev = chars[i - 1].1;
+        let next_lower = chars.get(i + 1).is_some_and(|(_, c)| c.is_lowercase());
+        let boundary = ch.is_uppercase()
+            && (prev.is_lowercase() || prev.is_numeric() || (prev.is_uppercase() && next_lower));
+        if boundary {
+            out.push((start, &part[start..at]));
+            start = at;
+        }
+    }
+    out.push((start, &part[start..]));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(text: &str, query: bool) -> Vec<(String, usize)> {
+        let mut analyzer = analyzer(query);
+        let mut stream = analyzer.token_stream(text);
+        let mut out = Vec::new();
+        while stream.advance() {
+            let token = stream.token();
+            out.push((token.text.clone(), token.position));
+        }
+        out
+    }
+
+    #[test]
+    fn test_index_tokens_keep_compound_and_parts() {
+        let tokens = texts("call crate::index::SearchIndex now", false);
+        let expected = [
+            ("call", 0),
+            ("crate::index::searchindex", 1),
+            ("crate", 1),
+            ("index", 2),
+            ("searchindex", 3),
+            ("search", 3),
+            ("index", 4),
+            ("now", 5),
+        ];
+        let expected: Vec<(String, usize)> =
+            expected.iter().map(|(t, p)| (t.to_string(), *p)).collect();
+        assert_eq!(tokens, expected);
+
+        let tokens = texts("HTTPServer v2Config.", false);
+        let names: Vec<&str> = tokens.iter().map(|(t, _)| t.as_str()).collect();
+        assert_eq!(
+            names,
+            ["httpserver", "http", "server", "v2config", "v2", "config"]
+        );
+    }
+
+    #[test]
+    fn test_query_tokens_are_leaves() {
+        let snake = texts("parse_claude_file", true);
+        let camel = texts("parseClaudeFile", true);
+        assert_eq!(snake, camel);
+        assert_eq!(
+            snake,
+            vec![
+                ("parse".to_string(), 0),
+                ("claude".to_string(), 1),
+                ("file".to_string(), 2)
+            ]
+        );
+        assert_eq!(texts("src/index.rs", true).len(), 3);
+    }
+}
@@ -0,0 +1,214 @@
+use serde::Serialize;
+use std::fmt::Write;
+
+/// A cluster of sessions with a shared theme.
+#[derive(Debug, Clone, Serialize)]
+pub struct Topic {
+    /// 1-based, largest topic first.
+    pub topic: usize,
+    /// Terms that set the topic's text apart from the rest of the index.
+    pub terms: Vec<String>,
+    pub size: usize,
+    pub start: String,
+    pub end: String,
+    /// Members closest to the topic's center first, up to `--members`.
+    pub sessions: Vec<TopicSession>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TopicSession {
+    pub session_id: String,
+    pub project: String,
+    pub source: String,
+    pub start: String,
+    pub end: String,
+    /// Cosine similarity to the topic's center.
+    pub similarity: f32,
+    /// The session's first user prompt.
+    pub title: String,
+}
+
+/// Cluster count when none is given: about sqrt(n / 2), at least 2 and at
+/// most 20.
+pub fn default_clusters(n: usize) -> usize {
+    (((n / 2) as f64).sqrt().round() as usize).clamp(2, 20)
+}
+
+/// Spherical k-means over unit vectors, seeded with k-means++ from a fixed
+/// seed so the same vectors always give the same clusters. Returns each
+/// vector's cluster and the cluster centers.
+pub fn kmeans(vectors: &[Vec<f32>], k: usize, iterations: usize) -> (Vec<usize>, Vec<Vec<f32>>) {
+    let k = k.min(vectors.len());
+    if k == 0 {
+        return (Vec::new(), Vec::new());
+    }
+    let mut rng = SplitMix(0x6d65_6d65_7874_6f70);
+    let mut centers = vec![vectors[0].clone()];
+    let mut nearest: Vec<f32> = vectors.iter().map(|v| distance(v, &centers[0])).collect();
+    while centers.len() < k {
+        let total: f32 = nearest.iter().map(|d| d * d).sum();
+        let next = if total > 0.0 {
+            let mut target = rng.next_f32() * total;
+            nearest
+                .iter()
+                .position(|d| {
+                    target -= d * d;
+                    target <= 0.0
+                })
+                .unwrap_or(vectors.len() - 1)
+        } else {
+            centers.len()
+        };
+        centers.push(vectors[next].clone());
+        for (d, v) in nearest.iter_mut().zip(vectors) {
+            *d = d.min(distance(v, &centers[centers.len() - 1]));
+        }
+    }
+
+    let mut assignments = vec![0; vectors.len()];
+    for round in 0..iterations.max(1) {
+        let mut changed = false;
+        for (assignment, v) in assignments.iter_mut().zip(vectors) {
+            let best = closest(v, &centers);
+            changed |= *assignment != best;
+            *assignment = best;
+        }
+        if round > 0 && !changed {
+            break;
+        }
+        for (cluster, center) in centers.iter_mut().enumerate() {
+            let mut sum = vec![0.0; center.len()];
+            for (_, v) in assignments
+                .iter()
+                .zip(vectors)
+                .filter(|(a, _)| **a == cluster)
+            {
+                for (total, x) in sum.iter_mut().zip(v) {
+                    *total += x;
+                }
+            }
+            // An emptied cluster keeps its old center.
+            if normalize(&mut sum) {
+                *center = sum;
+            }
+        }
+    }
+    (assignments, centers)
+}
+
+fn closest(v: &[f32], centers: &[Vec<f32>]) -> usize {
+    let mut best = (0, f32::NEG_INFINITY);
+    for (i, center) in centers.iter().enumerate() {
+        let similarity = dot(v, center);
+        if similarity > best.1 {
+            best = (i, similarity);
+        }
+    }
+    best.0
+}
+
+pub fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+fn distance(a: &[f32], b: &[f32]) -> f32 {
+    (1.0 - dot(a, b)).max(0.0)
+}
+
+/// Scale `v` to unit length. False when it is all zeros.
+pub fn normalize(v: &mut [f32]) -> bool {
+    let norm = dot(v, v).sqrt();
+    if norm == 0.0 {
+        return false;
+    }
+    for x in v.iter_mut() {
+        *x /= norm;
+    }
+    true
+}
+
+/// Small deterministic generator for k-means++ seeding.
+struct SplitMix(u64);
+
+impl SplitMix {
+    fn next_f32(&mut self) -> f32 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^= z >> 31;
+        (z >> 40) as f32 / (1u64 << 24) as f32
+    }
+}
+
+/// Human-readable topics.
+pub fn render(topics: &[Topic]) -> String {
+    let mut out = String::new();
+    for topic in topics {
+        let _ = writeln!(
+            out,
+            "topic {} ({} sessions, {} .. {}): {}",
+            topic.topic,
+            topic.size,
+            topic.start,
+            topic.end,
+            topic.terms.join(", ")
+        );
+        for session in &topic.sessions {
+            let _ = writeln!(
+                out,
+                "  [{:.3}] {} {} {} {}",
+                session.similarity, session.end, session.session_id, session.project, session.title
+            );
+        }
+        if topic.size > topic.sessions.len() {
+            let _ = writeln!(out, "  ... {} more", topic.size - topic.sessions.len());
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit(v: &[f32]) -> Vec<f32> {
+        let mut v = v.to_vec();
+        normalize(&mut v);
+        v
+    }
+
+    #[test]
+    fn test_kmeans_separates_groups() {
+        let vectors: Vec<Vec<f32>> = [
+            [1.0, 0.1, 0.0],
+            [0.9, 0.0, 0.1],
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.1],
+            [0.1, 0.9, 0.0],
+            [0.0, 0.0, 1.0],
+            [0.1, 0.0, 0.9],
+        ]
+        .iter()
+        .map(|v| unit(v))
+        .collect();
+        let (assignments, centers) = kmeans(&vectors, 3, 20);
+        assert_eq!(centers.len(), 3);
+        assert_eq!(assignments[0], assignments[1]);
+        assert_eq!(assignments[1], assignments[2]);
+        assert_eq!(assignments[3], assignments[4]);
+        assert_eq!(assignments[5], assignments[6]);
+        assert_ne!(assignments[0], assignments[3]);
+        assert_ne!(assignments[0], assignments[5]);
+        assert_ne!(assignments[3], assignments[5]);
+        assert_eq!(kmeans(&vectors, 3, 20).0, assignments);
+    }
+
+    #[test]
+    fn test_default_clusters() {
+        assert_eq!(default_clusters(3), 2);
+        assert_eq!(default_clusters(200), 10);
+        assert_eq!(default_clusters(100_000), 20);
+    }
+}
@@ -45,7 +45,10 @@ enum IndexUpdate {
 
 enum SearchUpdate {
     Started,
-    Results(Vec<SessionSummary>),
+    Results {
+        sessions: Vec<SessionSummary>,
+        fuzzy: bool,
+    },
     Projects {
         projects: Vec<String>,
         source: SourceChoice,
@@ -54,6 +57,8 @@ enum SearchUpdate {
 }
 
 const RESULT_LIMIT: usize = 200;
+/// Edit distance for the fuzzy retry when an exact search finds nothing.
+const FUZZY_FALLBACK_DISTANCE: u8 = 1;
 const DETAIL_TAIL_LINES: usize = 10;
 const MAX_MESSAGE_CHARS: usize = 4000;
 const PREVIEW_LINE_MAX_CHARS: usize = 320;
@@ -429,9 +434,11 @@ impl App {
                 let index = SearchIndex::open_or_create(&paths.index)?;
                 let embeddings_default = config.embeddings_default();
                 let model_choice = config.resolve_model(None)?;
-                let vector_exists = paths.vectors.join("meta.json").exists()
-                    && paths.vectors.join("vectors.f32").exists()
-                    && paths.vectors.join("doc_ids.u64").exists();
+                crate::vector::migrate_legacy_store(&paths.vectors, &model_choice)?;
+                let vector_exists = crate::vector::VectorIndex::is_current(
+                    &crate::vector::model_store(&paths.vectors, &model_choice),
+                    model_choice.template_version(),
+                )?;
                 let backfill_embeddings =
                     embeddings_default && !vector_exists && index.doc_count()? > 0;
                 let opts = IngestOptions {
@@ -441,7 +448,9 @@ impl App {
                     include_opencode: true,
                     embeddings: embeddings_default,
                     backfill_embeddings,
+                    prune_embed_cache: false,
                     model: model_choice,
+                    models: config.model_cache()?,
                 };
                 ingest_if_stale(&paths, &index, &opts, config.scan_cache_ttl())
             })();
@@ -539,24 +548,33 @@ impl App {
         self.set_status("searching...");
         std::thread::spawn(move || {
             let _ = tx.send(SearchUpdate::Started);
-            let result = (|| -> Result<(Vec<SessionSummary>, Option<Vec<String>>)> {
+            let result = (|| -> Result<(Vec<SessionSummary>, bool, Option<Vec<String>>)> {
                 let index = SearchIndex::open_or_create(&paths.index)?;
-                let sessions = if query.is_empty() {
-                    sessions_from_recent(&index, source.as_filter(), project_opt.as_deref())?
-                } else {
+                if query.is_empty() {
+                    let sessions =
+                        sessions_from_recent(&index, source.as_filter(), project_opt.as_deref())?;
+                    return Ok((sessions, false, None));
+                }
+                let search = |fuzzy| {
                     sessions_from_query(
                         &index,
                         &query,
                         source.as_filter(),
                         project_opt.as_deref(),
+                        fuzzy,
                         RESULT_LIMIT,
-                    )?
+                    )
                 };
-                Ok((sessions, None))
+                let sessions = search(None)?;
+                if !sessions.is_empty() {
+                    return Ok((sessions, false, None));
+                }
+                // Nothing matched exactly; a typo may be to blame.
+                Ok((search(Some(FUZZY_FALLBACK_DISTANCE))?, true, None))
             })();
             match result {
-                Ok((sessions, projects)) => {
-                    let _ = tx.send(SearchUpdate::Results(sessions));
+                Ok((sessions, fuzzy, projects)) => {
+                    let _ = tx.send(SearchUpdate::Results { sessions, fuzzy });
                     if let Some(projects) = projects {
                         let _ = tx.send(SearchUpdate::Projects { projects, source });
                     }
@@ -802,8 +820,8 @@ fn run_loop(terminal: &mut TuiTerminal, app: &mut App) -> Result<()> {
         while let Ok(update) = app.search_rx.try_recv() {
             match update {
                 SearchUpdate::Started => app.set_status("searching..."),
-                SearchUpdate::Results(results) => {
-                    app.results = results;
+                SearchUpdate::Results { sessions, fuzzy } => {
+                    app.results = sessions;
                     if app.results.is_empty() {
                         app.selected.select(None);
                     } else {
@@ -811,7 +829,14 @@ fn run_loop(terminal: &mut TuiTerminal, app: &mut App) -> Result<()> {
                     }
                     app.last_detail_session = None;
                     app.detail_scroll = 0;
-                    app.set_status(format!("{} sessions", app.results.len()));
+                    if fuzzy {
+                        app.set_status(format!(
+                            "{} sessions (no exact matches; showing fuzzy)",
+                            app.results.len()
+                        ));
+                    } else {
+                        app.set_status(format!("{} sessions", app.results.len()));
+                    }
                     app.update_detail();
                 }
                 SearchUpdate::Projects { projects, source } => {
@@ -1410,6 +1435,7 @@ fn sessions_from_query(
     query: &str,
     source: Option<SourceFilter>,
     project: Option<&str>,
+    fuzzy: Option<u8>,
     limit: usize,
 ) -> Result<Vec<SessionSummary>> {
     let options = QueryOptions {
@@ -1421,6 +1447,7 @@ fn sessions_from_query(
         source,
         since: None,
         until: None,
+        fuzzy,
         limit: limit.max(20),
     };
     let results = index.search(&options)?;
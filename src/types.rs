@@ -1,3 +1,4 @@
+use crate::patch::FilePatch;
 use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
@@ -88,5 +89,15 @@ pub struct Record {
     pub tool_input: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub tool_output: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub files: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<FilePatch>,
     pub source_path: String,
 }
@@ -1,75 +1,233 @@
+use crate::embed::ModelChoice;
 use anyhow::{Result, anyhow};
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fs;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
 use usearch::{Index, IndexOptions, MetricKind, ScalarKind};
 
+/// Message vectors for one model. A long message has one vector per chunk of
+/// its text, each under its own key.
 pub struct VectorIndex {
     dims: usize,
     path: PathBuf,
     index: Index,
-    doc_id_set: HashSet<u64>,
+    /// What each key's vector embeds.
+    chunks: HashMap<u64, Chunk>,
+    /// Each document's keys, in text order.
+    docs: HashMap<u64, Vec<u64>>,
+    next_key: u64,
+    /// Documents whose vectors were dropped since their sessions' means were
+    /// last updated; those means no longer match.
+    removed: HashSet<u64>,
+}
+
+/// The part of a document one vector embeds.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Chunk {
+    pub doc_id: u64,
+    /// Bytes of the document's text. Empty when the vector embeds the whole
+    /// text, as every vector from before chunking does.
+    pub span: Range<usize>,
+}
+
+/// What a vector store holds, in `manifest.json` next to its vectors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub model: String,
+    pub dims: usize,
+    /// [`ModelChoice::template_version`] of the document prompt the vectors
+    /// were embedded with. Missing in stores from before prompts: 0.
+    #[serde(default)]
+    pub template: u32,
+}
+
+impl Manifest {
+    pub fn load(dir: &Path) -> Result<Option<Self>> {
+        let path = dir.join("manifest.json");
+        if !path.exists() {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&fs::read(path)?)?))
+    }
+
+    fn save(&self, dir: &Path) -> Result<()> {
+        fs::write(dir.join("manifest.json"), serde_json::to_vec(self)?)?;
+        Ok(())
+    }
+}
+
+/// The store for `model`'s vectors under `root`: `root/<model id>`.
+pub fn model_store(root: &Path, model: &ModelChoice) -> PathBuf {
+    root.join(model.id())
+}
+
+/// Move vectors from before per-model stores, which sit directly in `root`,
+/// into the store of `configured`, the model config.toml names. They were
+/// embedded with that model unless the config changed since, so they are
+/// dropped instead when its store already exists or its dimensions differ.
+/// Runs before writing vectors, never from a search.
+pub fn migrate_legacy_store(root: &Path, configured: &ModelChoice) -> Result<()> {
+    let legacy = root.join("usearch.index");
+    if !legacy.exists() {
+        return Ok(());
+    }
+    let dir = model_store(root, configured);
+    let index = Index::new(&IndexOptions::default())?;
+    index.load(legacy.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
+    if dir.exists() || index.dimensions() != configured.dims() {
+        return clear_store(root);
+    }
+    fs::create_dir_all(&dir)?;
+    for name in ["usearch.index", "doc_ids.bin", "chunks.bin", "sessions"] {
+        if root.join(name).exists() {
+            fs::rename(root.join(name), dir.join(name))?;
+        }
+    }
+    Manifest {
+        model: configured.id().to_string(),
+        dims: configured.dims(),
+        template: 0,
+    }
+    .save(&dir)
+}
+
+/// Per-session means kept alongside a store's message vectors.
+pub fn sessions_dir(store: &Path) -> PathBuf {
+    store.join("sessions")
+}
+
+/// Delete a store's vectors and session means, keeping the directory.
+fn clear_store(dir: &Path) -> Result<()> {
+    for name in ["usearch.index", "doc_ids.bin", "chunks.bin", "removed.bin"] {
+        if dir.join(name).exists() {
+            fs::remove_file(dir.join(name))?;
+        }
+    }
+    if sessions_dir(dir).exists() {
+        fs::remove_dir_all(sessions_dir(dir))?;
+    }
+    Ok(())
 }
 
 impl VectorIndex {
-    pub fn open_or_create(dir: &Path, dimensions: usize) -> Result<Self> {
+    /// Open the store at `dir` for `model`, creating it if needed. A store
+    /// belongs to one model: opening it for another, or with other
+    /// dimensions, is an error rather than a reset. Vectors embedded with
+    /// another document `template` are dropped so they get embedded again.
+    pub fn open_or_create(
+        dir: &Path,
+        model: &str,
+        dimensions: usize,
+        template: u32,
+    ) -> Result<Self> {
         fs::create_dir_all(dir)?;
         let index_path = dir.join("usearch.index");
-        let ids_path = dir.join("doc_ids.bin");
 
-        // Check if existing index has different dimensions
+        let manifest = Manifest {
+            model: model.to_string(),
+            dims: dimensions,
+            template,
+        };
+        match Manifest::load(dir)? {
+            Some(existing) if existing.model != manifest.model || existing.dims != dimensions => {
+                return Err(anyhow!(
+                    "vector store {} holds {}-dim vectors from {}, not {}-dim vectors from {}",
+                    dir.display(),
+                    existing.dims,
+                    existing.model,
+                    dimensions,
+                    model
+                ));
+            }
+            Some(existing) if existing.template != template => {
+                clear_store(dir)?;
+                manifest.save(dir)?;
+            }
+            Some(_) => {}
+            None => manifest.save(dir)?,
+        }
+
         if index_path.exists() {
-            let existing = Index::new(&IndexOptions::default())?;
-            existing.load(index_path.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
-            if existing.dimensions() != dimensions {
-                // Dimension mismatch, remove old files
-                let _ = fs::remove_file(&index_path);
-                let _ = fs::remove_file(&ids_path);
+            let existing = Self::open(dir)?;
+            if existing.dims != dimensions {
+                return Err(anyhow!(
+                    "vector store {} holds {}-dim vectors, expected {dimensions}",
+                    dir.display(),
+                    existing.dims
+                ));
             }
+            return Ok(existing);
         }
 
-        let options = IndexOptions {
+        let index = Index::new(&IndexOptions {
             dimensions,
             metric: MetricKind::Cos,
             quantization: ScalarKind::F32,
             ..IndexOptions::default()
-        };
-
-        let index = Index::new(&options)?;
-
-        let doc_id_set = if index_path.exists() {
-            index.load(index_path.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
-            if ids_path.exists() {
-                load_doc_ids(&ids_path)?
-            } else {
-                HashSet::new()
-            }
-        } else {
-            index.reserve(10000)?;
-            HashSet::new()
-        };
-
+        })?;
+        index.reserve(10000)?;
         Ok(Self {
             dims: dimensions,
             path: dir.to_path_buf(),
             index,
-            doc_id_set,
+            chunks: HashMap::new(),
+            docs: HashMap::new(),
+            next_key: 0,
+            removed: HashSet::new(),
         })
     }
 
+    /// Whether `dir` holds a saved vector index.
+    pub fn exists(dir: &Path) -> bool {
+        dir.join("usearch.index").exists()
+    }
+
+    /// Whether `dir` holds vectors embedded with document `template`.
+    pub fn is_current(dir: &Path, template: u32) -> Result<bool> {
+        Ok(Self::exists(dir)
+            && Manifest::load(dir)?.is_none_or(|manifest| manifest.template == template))
+    }
+
     pub fn open(dir: &Path) -> Result<Self> {
         let index_path = dir.join("usearch.index");
+        let chunks_path = dir.join("chunks.bin");
         let ids_path = dir.join("doc_ids.bin");
 
         if !index_path.exists() {
-            return Err(anyhow!("vector index not found"));
+            return Err(anyhow!(
+                "vector index not found at {}; run `memex embed` to build it",
+                dir.display()
+            ));
         }
 
         let index = Index::new(&IndexOptions::default())?;
         index.load(index_path.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
 
-        let doc_id_set = if ids_path.exists() {
+        let chunks = if chunks_path.exists() {
+            load_chunks(&chunks_path)?
+        } else if ids_path.exists() {
+            // Stores from before chunking key each document's one vector by
+            // its doc id.
             load_doc_ids(&ids_path)?
+                .into_iter()
+                .map(|doc_id| (doc_id, Chunk { doc_id, span: 0..0 }))
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        let mut docs: HashMap<u64, Vec<u64>> = HashMap::new();
+        for (&key, chunk) in &chunks {
+            docs.entry(chunk.doc_id).or_default().push(key);
+        }
+        for keys in docs.values_mut() {
+            keys.sort_by_key(|key| chunks[key].span.start);
+        }
+        let next_key = chunks.keys().max().map_or(0, |key| key + 1);
+        let removed_path = dir.join("removed.bin");
+        let removed = if removed_path.exists() {
+            load_doc_ids(&removed_path)?
         } else {
             HashSet::new()
         };
@@ -78,11 +236,16 @@ impl VectorIndex {
             dims: index.dimensions(),
             path: dir.to_path_buf(),
             index,
-            doc_id_set,
+            chunks,
+            docs,
+            next_key,
+            removed,
         })
     }
 
-    pub fn add(&mut self, doc_id: u64, embedding: &[f32]) -> Result<()> {
+    /// Add the vector of `span` of `doc_id`'s text; an empty span stands for
+    /// the whole text. A span the document already has is ignored.
+    pub fn add(&mut self, doc_id: u64, span: Range<usize>, embedding: &[f32]) -> Result<()> {
         if embedding.len() != self.dims {
             return Err(anyhow!(
                 "embedding dimensions mismatch: expected {}, got {}",
@@ -90,7 +253,11 @@ impl VectorIndex {
                 embedding.len()
             ));
         }
-        if !self.doc_id_set.insert(doc_id) {
+        if self
+            .docs
+            .get(&doc_id)
+            .is_some_and(|keys| keys.iter().any(|key| self.chunks[key].span == span))
+        {
             return Ok(());
         }
 
@@ -100,11 +267,47 @@ impl VectorIndex {
             self.index.reserve(new_capacity)?;
         }
 
-        self.index.add(doc_id, embedding)?;
+        let key = self.next_key;
+        self.index.add(key, embedding)?;
+        self.next_key += 1;
+        let keys = self.docs.entry(doc_id).or_default();
+        let at = keys.partition_point(|key| self.chunks[key].span.start <= span.start);
+        keys.insert(at, key);
+        self.chunks.insert(key, Chunk { doc_id, span });
         Ok(())
     }
 
-    pub fn search(&self, embedding: &[f32], limit: usize) -> Result<Vec<(u64, f32)>> {
+    /// Drop all of `doc_id`'s vectors.
+    pub fn remove(&mut self, doc_id: u64) -> Result<()> {
+        let Some(keys) = self.docs.remove(&doc_id) else {
+            return Ok(());
+        };
+        for key in keys {
+            self.index.remove(key)?;
+            self.chunks.remove(&key);
+        }
+        self.removed.insert(doc_id);
+        Ok(())
+    }
+
+    /// Documents whose vectors were dropped since `clear_removed`.
+    pub fn removed(&self) -> &HashSet<u64> {
+        &self.removed
+    }
+
+    /// Forget the removed documents, once the session means no longer
+    /// count them.
+    pub fn clear_removed(&mut self) -> Result<()> {
+        self.removed.clear();
+        let path = self.path.join("removed.bin");
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    /// The closest chunk of each of the `limit` nearest documents.
+    pub fn search(&self, embedding: &[f32], limit: usize) -> Result<Vec<(Chunk, f32)>> {
         if embedding.len() != self.dims {
             return Err(anyhow!(
                 "embedding dimensions mismatch: expected {}, got {}",
@@ -116,34 +319,452 @@ impl VectorIndex {
             return Ok(Vec::new());
         }
 
-        let results = self.index.search(embedding, limit)?;
-        Ok(results.keys.into_iter().zip(results.distances).collect())
+        // Chunks of one document can crowd out others: widen the search
+        // until `limit` documents turn up.
+        let mut k = limit;
+        loop {
+            let results = self.index.search(embedding, k)?;
+            let hits = self.best_per_doc(results.keys.into_iter().zip(results.distances), limit);
+            if hits.len() >= limit || k >= self.index.size() {
+                return Ok(hits);
+            }
+            k = (k * 2).min(self.index.size());
+        }
+    }
+
+    /// Nearest neighbors among `allowed` doc ids only. Small sets are scanned
+    /// exactly; larger ones use the graph search with `allowed` as a
+    /// predicate, so filtered queries still return up to `limit` hits.
+    pub fn search_within(
+        &self,
+        embedding: &[f32],
+        limit: usize,
+        allowed: &HashSet<u64>,
+    ) -> Result<Vec<(Chunk, f32)>> {
+        if embedding.len() != self.dims {
+            return Err(anyhow!(
+                "embedding dimensions mismatch: expected {}, got {}",
+                self.dims,
+                embedding.len()
+            ));
+        }
+        if self.index.size() == 0 || allowed.is_empty() {
+            return Ok(Vec::new());
+        }
+        if allowed.len() > EXACT_SEARCH_MAX {
+            let mut k = limit;
+            loop {
+                let results = self.index.filtered_search(embedding, k, |key| {
+                    self.chunks
+                        .get(&key)
+                        .is_some_and(|chunk| allowed.contains(&chunk.doc_id))
+                })?;
+                let found = results.keys.len();
+                let hits =
+                    self.best_per_doc(results.keys.into_iter().zip(results.distances), limit);
+                if hits.len() >= limit || found < k {
+                    return Ok(hits);
+                }
+                k *= 2;
+            }
+        }
+
+        let query_norm = norm(embedding);
+        let mut results = Vec::new();
+        for doc_id in allowed {
+            let Some(keys) = self.docs.get(doc_id) else {
+                continue;
+            };
+            let mut best: Option<(u64, f32)> = None;
+            for &key in keys {
+                let Some(vector) = self.vector(key)? else {
+                    continue;
+                };
+                let denominator = query_norm * norm(&vector);
+                let similarity = if denominator > 0.0 {
+                    embedding
+                        .iter()
+                        .zip(&vector)
+                        .map(|(a, b)| a * b)
+                        .sum::<f32>()
+                        / denominator
+                } else {
+                    0.0
+                };
+                if best.is_none_or(|(_, distance)| 1.0 - similarity < distance) {
+                    best = Some((key, 1.0 - similarity));
+                }
+            }
+            if let Some((key, distance)) = best {
+                results.push((self.chunks[&key].clone(), distance));
+            }
+        }
+        results.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.doc_id.cmp(&b.0.doc_id)));
+        results.truncate(limit);
+        Ok(results)
+    }
+
+    /// The first `limit` documents of `hits`, nearest first, each with its
+    /// nearest chunk.
+    fn best_per_doc(
+        &self,
+        hits: impl Iterator<Item = (u64, f32)>,
+        limit: usize,
+    ) -> Vec<(Chunk, f32)> {
+        let mut seen = HashSet::new();
+        let mut results = Vec::new();
+        for (key, distance) in hits {
+            if results.len() >= limit {
+                break;
+            }
+            if let Some(chunk) = self.chunks.get(&key)
+                && seen.insert(chunk.doc_id)
+            {
+                results.push((chunk.clone(), distance));
+            }
+        }
+        results
+    }
+
+    /// The embedding of `doc_id`: its vector, or the mean of its chunks'
+    /// unit-length vectors when it has several.
+    pub fn get(&self, doc_id: u64) -> Result<Option<Vec<f32>>> {
+        let Some(keys) = self.docs.get(&doc_id) else {
+            return Ok(None);
+        };
+        if let [key] = keys.as_slice() {
+            return self.vector(*key);
+        }
+        let mut sum: Option<Vec<f32>> = None;
+        for &key in keys {
+            let Some(vector) = self.vector(key)? else {
+                continue;
+            };
+            let length = norm(&vector);
+            if length == 0.0 {
+                continue;
+            }
+            let sum = sum.get_or_insert_with(|| vec![0.0; self.dims]);
+            for (total, x) in sum.iter_mut().zip(&vector) {
+                *total += x / length;
+            }
+        }
+        Ok(sum)
+    }
+
+    fn vector(&self, key: u64) -> Result<Option<Vec<f32>>> {
+        let mut vector = Vec::new();
+        let found = self.index.export(key, &mut vector)?;
+        if found == 0 {
+            return Ok(None);
+        }
+        vector.truncate(self.dims);
+        Ok(Some(vector))
     }
 
     pub fn save(&self) -> Result<()> {
         let index_path = self.path.join("usearch.index");
-        let ids_path = self.path.join("doc_ids.bin");
 
         // Save index
         self.index
             .save(index_path.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
 
-        // Save doc_ids
-        save_doc_ids(&ids_path, &self.doc_id_set)?;
+        save_chunks(&self.path.join("chunks.bin"), &self.chunks)?;
+        if !self.removed.is_empty() {
+            save_doc_ids(&self.path.join("removed.bin"), &self.removed)?;
+        }
+        // Superseded by the chunk list.
+        let ids_path = self.path.join("doc_ids.bin");
+        if ids_path.exists() {
+            fs::remove_file(ids_path)?;
+        }
 
         Ok(())
     }
 
     pub fn contains(&self, doc_id: u64) -> bool {
-        self.doc_id_set.contains(&doc_id)
+        self.docs.contains_key(&doc_id)
+    }
+
+    /// Whether `doc_id`'s vectors cover all of `text`. A whole-text vector
+    /// from before chunking saw only the start of a text longer than a chunk.
+    pub fn covers(&self, doc_id: u64, text: &str) -> bool {
+        match self.docs.get(&doc_id).map(Vec::as_slice) {
+            None => false,
+            Some([key]) => {
+                text.len() <= crate::embed::CHUNK_BYTES || !self.chunks[key].span.is_empty()
+            }
+            Some(_) => true,
+        }
+    }
+
+    pub fn doc_ids(&self) -> impl Iterator<Item = u64> + '_ {
+        self.docs.keys().copied()
     }
 
-    #[allow(dead_code)]
     pub fn dimensions(&self) -> usize {
         self.dims
     }
 }
 
+/// Vectors saved in the store at `dir`, counting each chunk.
+pub fn stored_vectors(dir: &Path) -> u64 {
+    let size = |name: &str| fs::metadata(dir.join(name)).map(|meta| meta.len());
+    size("chunks.bin")
+        .map(|len| len / CHUNK_RECORD_BYTES as u64)
+        .or_else(|_| size("doc_ids.bin").map(|len| len / 8))
+        .unwrap_or(0)
+}
+
+/// One embedding per session: the mean of its messages' unit-length vectors.
+/// Kept next to the message vectors and updated incrementally as they grow.
+pub struct SessionVectors {
+    dims: usize,
+    path: PathBuf,
+    index: Index,
+    meta: SessionMeta,
+    keys: HashMap<u64, String>,
+    /// Message doc ids already counted in a session mean, and that
+    /// session's key.
+    folded: HashMap<u64, u64>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SessionMeta {
+    next_key: u64,
+    sessions: HashMap<String, SessionEntry>,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct SessionEntry {
+    key: u64,
+    /// Messages in the mean.
+    count: u64,
+}
+
+impl SessionVectors {
+    pub fn open_or_create(dir: &Path, dimensions: usize) -> Result<Self> {
+        fs::create_dir_all(dir)?;
+        // `folded.bin` lists counted messages without their sessions, so a
+        // changed message couldn't be taken out of its mean.
+        if let Ok(existing) = Self::open(dir)
+            && existing.dims == dimensions
+            && !dir.join("folded.bin").exists()
+        {
+            return Ok(existing);
+        }
+        // New, older, or built from a model with other dimensions: start over.
+        for name in [
+            "usearch.index",
+            "sessions.json",
+            "folded.bin",
+            "folded_sessions.bin",
+        ] {
+            let _ = fs::remove_file(dir.join(name));
+        }
+        let index = Index::new(&IndexOptions {
+            dimensions,
+            metric: MetricKind::Cos,
+            quantization: ScalarKind::F32,
+            ..IndexOptions::default()
+        })?;
+        index.reserve(1000)?;
+        Ok(Self {
+            dims: dimensions,
+            path: dir.to_path_buf(),
+            index,
+            meta: SessionMeta::default(),
+            keys: HashMap::new(),
+            folded: HashMap::new(),
+        })
+    }
+
+    pub fn open(dir: &Path) -> Result<Self> {
+        let index_path = dir.join("usearch.index");
+        let meta_path = dir.join("sessions.json");
+        if !index_path.exists() || !meta_path.exists() {
+            return Err(anyhow!(
+                "session vectors not found; run `memex embed` to build them"
+            ));
+        }
+        let index = Index::new(&IndexOptions::default())?;
+        index.load(index_path.to_str().ok_or_else(|| anyhow!("invalid path"))?)?;
+        let meta: SessionMeta = serde_json::from_slice(&fs::read(&meta_path)?)?;
+        let keys = meta
+            .sessions
+            .iter()
+            .map(|(session_id, entry)| (entry.key, session_id.clone()))
+            .collect();
+        let folded_path = dir.join("folded_sessions.bin");
+        let folded = if folded_path.exists() {
+            fs::read(&folded_path)?
+                .chunks_exact(16)
+                .map(|pair| {
+                    let field = |i: usize| u64::from_le_bytes(pair[i..i + 8].try_into().unwrap());
+                    (field(0), field(8))
+                })
+                .collect()
+        } else {
+            HashMap::new()
+        };
+        Ok(Self {
+            dims: index.dimensions(),
+            path: dir.to_path_buf(),
+            index,
+            meta,
+            keys,
+            folded,
+        })
+    }
+
+    pub fn is_folded(&self, doc_id: u64) -> bool {
+        self.folded.contains_key(&doc_id)
+    }
+
+    /// Record that `doc_id`'s vector is counted in `session_id`'s mean.
+    pub fn mark_folded(&mut self, doc_id: u64, session_id: &str) {
+        if let Some(entry) = self.meta.sessions.get(session_id) {
+            self.folded.insert(doc_id, entry.key);
+        }
+    }
+
+    /// Drop the means of the sessions any of `doc_ids` is counted in, so they
+    /// can be folded again from their messages' current vectors. Returns
+    /// every message no longer counted, with its session.
+    pub fn unfold(&mut self, doc_ids: &HashSet<u64>) -> Result<Vec<(u64, String)>> {
+        let stale: HashSet<u64> = doc_ids
+            .iter()
+            .filter_map(|doc_id| self.folded.get(doc_id).copied())
+            .collect();
+        if stale.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut unfolded = Vec::new();
+        self.folded.retain(|doc_id, key| {
+            if !stale.contains(key) {
+                return true;
+            }
+            if let Some(session_id) = self.keys.get(key) {
+                unfolded.push((*doc_id, session_id.clone()));
+            }
+            false
+        });
+        for key in stale {
+            if let Some(session_id) = self.keys.remove(&key) {
+                self.meta.sessions.remove(&session_id);
+                self.index.remove(key)?;
+            }
+        }
+        Ok(unfolded)
+    }
+
+    /// Add `count` messages whose unit vectors sum to `sum` to a session's mean.
+    pub fn fold(&mut self, session_id: &str, sum: &[f32], count: u64) -> Result<()> {
+        if sum.len() != self.dims {
+            return Err(anyhow!(
+                "embedding dimensions mismatch: expected {}, got {}",
+                self.dims,
+                sum.len()
+            ));
+        }
+        if count == 0 {
+            return Ok(());
+        }
+        let mut mean: Vec<f32> = sum.iter().map(|x| x / count as f32).collect();
+        let entry = match self.meta.sessions.get(session_id).copied() {
+            Some(entry) => {
+                if let Some(old) = self.get(session_id)? {
+                    let total = (entry.count + count) as f32;
+                    for (value, old) in mean.iter_mut().zip(old) {
+                        *value = (old * entry.count as f32 + *value * count as f32) / total;
+                    }
+                }
+                self.index.remove(entry.key)?;
+                SessionEntry {
+                    key: entry.key,
+                    count: entry.count + count,
+                }
+            }
+            None => {
+                let key = self.meta.next_key;
+                self.meta.next_key += 1;
+                self.keys.insert(key, session_id.to_string());
+                SessionEntry { key, count }
+            }
+        };
+        if self.index.size() >= self.index.capacity() {
+            let new_capacity = (self.index.capacity() * 2).max(1000);
+            self.index.reserve(new_capacity)?;
+        }
+        self.index.add(entry.key, &mean)?;
+        self.meta.sessions.insert(session_id.to_string(), entry);
+        Ok(())
+    }
+
+    /// A session's mean embedding, if it has one.
+    pub fn get(&self, session_id: &str) -> Result<Option<Vec<f32>>> {
+        let Some(entry) = self.meta.sessions.get(session_id) else {
+            return Ok(None);
+        };
+        let mut vector = Vec::new();
+        if self.index.export(entry.key, &mut vector)? == 0 {
+            return Ok(None);
+        }
+        vector.truncate(self.dims);
+        Ok(Some(vector))
+    }
+
+    /// Nearest sessions to `embedding` as (session id, cosine distance).
+    pub fn search(&self, embedding: &[f32], limit: usize) -> Result<Vec<(String, f32)>> {
+        if embedding.len() != self.dims {
+            return Err(anyhow!(
+                "embedding dimensions mismatch: expected {}, got {}",
+                self.dims,
+                embedding.len()
+            ));
+        }
+        if self.index.size() == 0 {
+            return Ok(Vec::new());
+        }
+        let results = self.index.search(embedding, limit)?;
+        Ok(results
+            .keys
+            .into_iter()
+            .zip(results.distances)
+            .filter_map(|(key, distance)| Some((self.keys.get(&key)?.clone(), distance)))
+            .collect())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        self.index.save(
+            self.path
+                .join("usearch.index")
+                .to_str()
+                .ok_or_else(|| anyhow!("invalid path"))?,
+        )?;
+        let meta_path = self.path.join("sessions.json");
+        let tmp = meta_path.with_extension("json.tmp");
+        fs::write(&tmp, serde_json::to_vec(&self.meta)?)?;
+        fs::rename(&tmp, &meta_path)?;
+        let mut folded = Vec::with_capacity(self.folded.len() * 16);
+        for (doc_id, key) in &self.folded {
+            folded.extend_from_slice(&doc_id.to_le_bytes());
+            folded.extend_from_slice(&key.to_le_bytes());
+        }
+        let folded_path = self.path.join("folded_sessions.bin");
+        fs::write(folded_path.with_extension("bin.tmp"), &folded)?;
+        fs::rename(folded_path.with_extension("bin.tmp"), &folded_path)?;
+        Ok(())
+    }
+}
+
+/// Largest filtered set `search_within` compares one by one.
+const EXACT_SEARCH_MAX: usize = 10_000;
+
+fn norm(v: &[f32]) -> f32 {
+    v.iter().map(|x| x * x).sum::<f32>().sqrt()
+}
+
 fn load_doc_ids(path: &Path) -> Result<HashSet<u64>> {
     let bytes = fs::read(path)?;
     let ids: Vec<u64> = bytes
@@ -153,6 +774,48 @@ fn load_doc_ids(path: &Path) -> Result<HashSet<u64>> {
     Ok(ids.into_iter().collect())
 }
 
+/// Bytes per key in `chunks.bin`: key, doc id, span start and end.
+const CHUNK_RECORD_BYTES: usize = 32;
+
+fn load_chunks(path: &Path) -> Result<HashMap<u64, Chunk>> {
+    let bytes = fs::read(path)?;
+    Ok(bytes
+        .chunks_exact(CHUNK_RECORD_BYTES)
+        .map(|record| {
+            let field = |i: usize| u64::from_le_bytes(record[i * 8..i * 8 + 8].try_into().unwrap());
+            let span = field(2) as usize..field(3) as usize;
+            (
+                field(0),
+                Chunk {
+                    doc_id: field(1),
+                    span,
+                },
+            )
+        })
+        .collect())
+}
+
+fn save_chunks(path: &Path, chunks: &HashMap<u64, Chunk>) -> Result<()> {
+    let mut keys: Vec<&u64> = chunks.keys().collect();
+    keys.sort();
+    let mut bytes = Vec::with_capacity(chunks.len() * CHUNK_RECORD_BYTES);
+    for key in keys {
+        let chunk = &chunks[key];
+        for field in [
+            *key,
+            chunk.doc_id,
+            chunk.span.start as u64,
+            chunk.span.end as u64,
+        ] {
+            bytes.extend_from_slice(&field.to_le_bytes());
+        }
+    }
+    let tmp = path.with_extension("bin.tmp");
+    fs::write(&tmp, &bytes)?;
+    fs::rename(&tmp, path)?;
+    Ok(())
+}
+
 fn save_doc_ids(path: &Path, ids: &HashSet<u64>) -> Result<()> {
     let mut bytes = Vec::with_capacity(ids.len() * 8);
     for id in ids {
@@ -176,10 +839,10 @@ mod tests {
     #[test]
     fn test_create_and_add() {
         let tmp = TempDir::new().unwrap();
-        let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         let v1 = make_vector(64, 1.0);
-        idx.add(1, &v1).unwrap();
+        idx.add(1, 0..0, &v1).unwrap();
 
         assert!(idx.contains(1));
         assert!(!idx.contains(2));
@@ -189,11 +852,11 @@ mod tests {
     #[test]
     fn test_duplicate_add_ignored() {
         let tmp = TempDir::new().unwrap();
-        let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         let v1 = make_vector(64, 1.0);
-        idx.add(1, &v1).unwrap();
-        idx.add(1, &v1).unwrap(); // duplicate
+        idx.add(1, 0..0, &v1).unwrap();
+        idx.add(1, 0..0, &v1).unwrap(); // duplicate
 
         assert!(idx.contains(1));
     }
@@ -201,17 +864,17 @@ mod tests {
     #[test]
     fn test_dimension_mismatch_error() {
         let tmp = TempDir::new().unwrap();
-        let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         let wrong_dims = make_vector(32, 1.0);
-        let result = idx.add(1, &wrong_dims);
+        let result = idx.add(1, 0..0, &wrong_dims);
         assert!(result.is_err());
     }
 
     #[test]
     fn test_search_empty_index() {
         let tmp = TempDir::new().unwrap();
-        let idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         let query = make_vector(64, 1.0);
         let results = idx.search(&query, 10).unwrap();
@@ -221,34 +884,47 @@ mod tests {
     #[test]
     fn test_search_returns_nearest() {
         let tmp = TempDir::new().unwrap();
-        let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         let v1 = make_vector(64, 1.0);
         let v2 = make_vector(64, 2.0);
         let v3 = make_vector(64, 3.0);
 
-        idx.add(1, &v1).unwrap();
-        idx.add(2, &v2).unwrap();
-        idx.add(3, &v3).unwrap();
+        idx.add(1, 0..0, &v1).unwrap();
+        idx.add(2, 0..0, &v2).unwrap();
+        idx.add(3, 0..0, &v3).unwrap();
 
         // Search with v1 as query, should return v1 first (distance ~0)
         let results = idx.search(&v1, 3).unwrap();
         assert_eq!(results.len(), 3);
-        assert_eq!(results[0].0, 1); // v1 should be first match
+        assert_eq!(results[0].0.doc_id, 1); // v1 should be first match
         assert!(results[0].1 < 0.01); // distance should be near zero
     }
 
+    #[test]
+    fn test_get_stored_vector() {
+        let tmp = TempDir::new().unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
+        let v1 = make_vector(64, 1.0);
+        idx.add(7, 0..0, &v1).unwrap();
+
+        let stored = idx.get(7).unwrap().unwrap();
+        assert_eq!(stored.len(), 64);
+        assert!(stored.iter().zip(&v1).all(|(a, b)| (a - b).abs() < 1e-6));
+        assert!(idx.get(8).unwrap().is_none());
+    }
+
     #[test]
     fn test_save_and_reload() {
         let tmp = TempDir::new().unwrap();
 
         // Create and populate index
         {
-            let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+            let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
             let v1 = make_vector(64, 1.0);
             let v2 = make_vector(64, 2.0);
-            idx.add(100, &v1).unwrap();
-            idx.add(200, &v2).unwrap();
+            idx.add(100, 0..0, &v1).unwrap();
+            idx.add(200, 0..0, &v2).unwrap();
             idx.save().unwrap();
         }
 
@@ -264,7 +940,7 @@ mod tests {
             let query = make_vector(64, 1.0);
             let results = idx.search(&query, 2).unwrap();
             assert_eq!(results.len(), 2);
-            assert_eq!(results[0].0, 100);
+            assert_eq!(results[0].0.doc_id, 100);
         }
     }
 
@@ -276,37 +952,181 @@ mod tests {
     }
 
     #[test]
-    fn test_dimension_change_resets_index() {
+    fn test_other_model_is_refused() {
         let tmp = TempDir::new().unwrap();
 
         // Create index with 64 dims
         {
-            let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+            let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
             let v = make_vector(64, 1.0);
-            idx.add(1, &v).unwrap();
+            idx.add(1, 0..0, &v).unwrap();
             idx.save().unwrap();
         }
 
-        // Reopen with different dims, should reset
+        // Reopen with different dims or model, should keep the data
+        assert!(VectorIndex::open_or_create(tmp.path(), "test", 128, 0).is_err());
+        assert!(VectorIndex::open_or_create(tmp.path(), "other", 64, 0).is_err());
+        let idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
+        assert!(idx.contains(1));
+    }
+
+    #[test]
+    fn test_template_change_clears_vectors() {
+        let tmp = TempDir::new().unwrap();
         {
-            let idx = VectorIndex::open_or_create(tmp.path(), 128).unwrap();
-            assert!(!idx.contains(1)); // old data should be gone
-            assert_eq!(idx.dimensions(), 128);
+            let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
+            idx.add(1, 0..0, &make_vector(64, 1.0)).unwrap();
+            idx.save().unwrap();
         }
+        assert!(VectorIndex::is_current(tmp.path(), 0).unwrap());
+        assert!(!VectorIndex::is_current(tmp.path(), 1).unwrap());
+
+        let idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 1).unwrap();
+        assert!(!idx.contains(1));
+        let manifest = Manifest::load(tmp.path()).unwrap().unwrap();
+        assert_eq!(manifest.template, 1);
+    }
+
+    #[test]
+    fn test_legacy_vectors_move_to_configured_model() {
+        let dims = ModelChoice::MiniLM.dims();
+        let legacy = |dir: &Path| {
+            let mut idx = VectorIndex::open_or_create(dir, "minilm", dims, 0).unwrap();
+            idx.add(7, 0..0, &make_vector(dims, 1.0)).unwrap();
+            idx.save().unwrap();
+            // Stores from before per-model directories had no manifest.
+            fs::remove_file(dir.join("manifest.json")).unwrap();
+        };
+
+        // Another model with the same dimensions never takes them.
+        let tmp = TempDir::new().unwrap();
+        legacy(tmp.path());
+        migrate_legacy_store(tmp.path(), &ModelChoice::MiniLM).unwrap();
+        let minilm = model_store(tmp.path(), &ModelChoice::MiniLM);
+        assert_eq!(minilm, tmp.path().join("minilm"));
+        assert!(!tmp.path().join("usearch.index").exists());
+        assert!(!model_store(tmp.path(), &ModelChoice::BGESmall).exists());
+        let idx = VectorIndex::open_or_create(&minilm, "minilm", dims, 0).unwrap();
+        assert!(idx.contains(7));
+
+        // Vectors that can't be the configured model's are dropped.
+        let tmp = TempDir::new().unwrap();
+        legacy(tmp.path());
+        migrate_legacy_store(tmp.path(), &ModelChoice::Nomic).unwrap();
+        assert!(!tmp.path().join("usearch.index").exists());
+        assert!(!model_store(tmp.path(), &ModelChoice::Nomic).exists());
     }
 
     #[test]
     fn test_search_with_limit() {
         let tmp = TempDir::new().unwrap();
-        let mut idx = VectorIndex::open_or_create(tmp.path(), 64).unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
 
         for i in 0..10 {
             let v = make_vector(64, i as f32);
-            idx.add(i, &v).unwrap();
+            idx.add(i, 0..0, &v).unwrap();
         }
 
         let query = make_vector(64, 0.0);
         let results = idx.search(&query, 3).unwrap();
         assert_eq!(results.len(), 3);
     }
+
+    #[test]
+    fn test_session_means_fold_incrementally() {
+        let tmp = TempDir::new().unwrap();
+        let a = make_vector(64, 1.0);
+        let b = make_vector(64, 2.0);
+        {
+            let mut sessions = SessionVectors::open_or_create(tmp.path(), 64).unwrap();
+            sessions.fold("s1", &a, 1).unwrap();
+            sessions.fold("s2", &b, 1).unwrap();
+            sessions.mark_folded(1, "s1");
+            sessions.save().unwrap();
+        }
+
+        let mut sessions = SessionVectors::open_or_create(tmp.path(), 64).unwrap();
+        assert!(sessions.is_folded(1));
+        let sum: Vec<f32> = b.iter().map(|x| x * 3.0).collect();
+        sessions.fold("s1", &sum, 3).unwrap();
+        let mean = sessions.get("s1").unwrap().unwrap();
+        for ((mean, a), b) in mean.iter().zip(&a).zip(&b) {
+            assert!((mean - (a + 3.0 * b) / 4.0).abs() < 1e-5);
+        }
+        let nearest = sessions.search(&b, 2).unwrap();
+        assert_eq!(nearest[0].0, "s2");
+        assert_eq!(nearest.len(), 2);
+
+        // Unfolding a changed message drops its session's whole mean.
+        let changed = HashSet::from([1, 7]);
+        assert_eq!(
+            sessions.unfold(&changed).unwrap(),
+            vec![(1, "s1".to_string())]
+        );
+        assert!(!sessions.is_folded(1));
+        assert!(sessions.get("s1").unwrap().is_none());
+        assert!(sessions.get("s2").unwrap().is_some());
+
+        let resized = SessionVectors::open_or_create(tmp.path(), 32).unwrap();
+        assert!(resized.get("s1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_search_within_allowed_ids() {
+        let tmp = TempDir::new().unwrap();
+        let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
+        for i in 0..200u64 {
+            idx.add(i, 0..0, &make_vector(64, i as f32 * 0.1)).unwrap();
+        }
+        let query = make_vector(64, 0.0);
+        let allowed: HashSet<u64> = [150, 151, 190, 999].into_iter().collect();
+        let results = idx.search_within(&query, 10, &allowed).unwrap();
+        let ids: Vec<u64> = results.iter().map(|(chunk, _)| chunk.doc_id).collect();
+        assert_eq!(ids.len(), 3);
+        assert!(ids.iter().all(|id| allowed.contains(id)));
+        let unfiltered = idx.search(&query, 1).unwrap()[0].1;
+        assert!(results[0].1 >= unfiltered - 1e-5);
+        assert!(results.windows(2).all(|w| w[0].1 <= w[1].1));
+    }
+
+    #[test]
+    fn test_chunks_rank_their_document() {
+        let tmp = TempDir::new().unwrap();
+        let near = make_vector(64, 1.0);
+        let far = make_vector(64, 4.0);
+        {
+            let mut idx = VectorIndex::open_or_create(tmp.path(), "test", 64, 0).unwrap();
+            idx.add(1, 0..10, &far).unwrap();
+            idx.add(1, 8..20, &near).unwrap();
+            idx.add(2, 0..0, &make_vector(64, 1.5)).unwrap();
+            idx.save().unwrap();
+        }
+
+        let mut idx = VectorIndex::open(tmp.path()).unwrap();
+        assert_eq!(stored_vectors(tmp.path()), 3);
+        let results = idx.search(&near, 2).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0].0,
+            Chunk {
+                doc_id: 1,
+                span: 8..20
+            }
+        );
+        assert_eq!(results[1].0.doc_id, 2);
+        let within = idx
+            .search_within(&near, 2, &[1].into_iter().collect())
+            .unwrap();
+        assert_eq!(within[0].0.span, 8..20);
+        assert_eq!(idx.get(1).unwrap().unwrap().len(), 64);
+
+        // A whole-text vector only covers text that fits one chunk.
+        let long = "x".repeat(crate::embed::CHUNK_BYTES + 1);
+        assert!(idx.covers(1, &long));
+        assert!(idx.covers(2, "short"));
+        assert!(!idx.covers(2, &long));
+        idx.remove(1).unwrap();
+        assert!(!idx.contains(1));
+        assert_eq!(idx.search(&near, 2).unwrap().len(), 1);
+    }
 }